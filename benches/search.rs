@@ -0,0 +1,168 @@
+//! Benchmarks of building and searching a berlin-core database, so that
+//! regressions in scoring or in the FST show up.
+//!
+//! By default they run against a synthetic database. To run them against a
+//! data directory instead, as read by `berlin_core::locations_db::
+//! parse_data_files`, set BERLIN_BENCH_DATA_DIR:
+//!
+//!     BERLIN_BENCH_DATA_DIR=../data cargo bench
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use berlin_core::locations_db::{parse_data_blocks, parse_data_files, LocationsDb};
+use berlin_core::rayon::prelude::*;
+use berlin_core::search::SearchTerm;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use serde_json::{json, Map, Value};
+
+const DATA_DIR_VAR: &str = "BERLIN_BENCH_DATA_DIR";
+// Synthetic locations are named from pairs of these, with a number, so
+// that common words match many locations as in real data
+const WORDS: [&str; 12] = [
+    "port", "saint", "new", "upper", "lower", "north", "south", "bridge", "mill", "green",
+    "castle", "haven",
+];
+const STATES: [(&str, &str, &str); 4] = [
+    ("GB", "GBR", "United Kingdom"),
+    ("FR", "FRA", "France"),
+    ("DE", "DEU", "Germany"),
+    ("BG", "BGR", "Bulgaria"),
+];
+const SUBDIVISIONS_PER_STATE: usize = 20;
+const LOCODES_PER_SUBDIVISION: usize = 250;
+
+// The JSON blocks of a synthetic database, as in state.json,
+// subdivision.json and locode.json
+fn synthetic_blocks() -> Vec<(String, Value)> {
+    let mut states = Map::new();
+    let mut subdivisions = Map::new();
+    let mut locodes = Map::new();
+    let mut n = 0;
+    for (alpha2, alpha3, name) in STATES {
+        states.insert(
+            alpha2.to_string(),
+            json!({
+                "<c>": "ISO-3166-1",
+                "i": alpha2,
+                "d": {
+                    "name": name,
+                    "short": name,
+                    "alpha2": alpha2,
+                    "alpha3": alpha3,
+                    "continent": "EU",
+                },
+            }),
+        );
+        for s in 0..SUBDIVISIONS_PER_STATE {
+            let subcode = format!["S{s:02}"];
+            let subdiv_name = format!["{} {}shire", WORDS[s % WORDS.len()], name];
+            subdivisions.insert(
+                format!["{alpha2}:{subcode}"],
+                json!({
+                    "<c>": "ISO-3166-2",
+                    "i": format!["{alpha2}:{subcode}"],
+                    "d": {
+                        "name": subdiv_name,
+                        "supercode": alpha2,
+                        "subcode": subcode,
+                        "level": "county",
+                    },
+                }),
+            );
+            for _ in 0..LOCODES_PER_SUBDIVISION {
+                let code = format!["{n:03X}"];
+                let name = format![
+                    "{} {} {}",
+                    WORDS[n % WORDS.len()],
+                    WORDS[n / WORDS.len() % WORDS.len()],
+                    n
+                ];
+                locodes.insert(
+                    format!["{alpha2}:{code}"],
+                    json!({
+                        "<c>": "UN-LOCODE",
+                        "i": format!["{alpha2}:{code}"],
+                        "d": {
+                            "name": name,
+                            "supercode": alpha2,
+                            "subcode": code,
+                            "subdivision_code": subcode,
+                            "function_code": "1-------",
+                        },
+                    }),
+                );
+                n += 1;
+            }
+        }
+    }
+    vec![
+        ("state.json".to_string(), Value::Object(states)),
+        ("subdivision.json".to_string(), Value::Object(subdivisions)),
+        ("locode.json".to_string(), Value::Object(locodes)),
+    ]
+}
+
+fn build(blocks: Vec<(String, Value)>) -> LocationsDb {
+    parse_data_blocks(blocks.into_par_iter(), None)
+        .expect("synthetic data should parse")
+        .mk_fst()
+}
+
+fn data_dir() -> Option<PathBuf> {
+    env::var_os(DATA_DIR_VAR).map(PathBuf::from)
+}
+
+fn load() -> LocationsDb {
+    match data_dir() {
+        Some(data_dir) => parse_data_files(data_dir).expect("data directory should parse"),
+        None => build(synthetic_blocks()),
+    }
+}
+
+fn bench_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(20));
+    match data_dir() {
+        Some(data_dir) => {
+            group.bench_function("data_dir", |b| {
+                b.iter(|| parse_data_files(data_dir.clone()).expect("data directory should parse"))
+            });
+        }
+        None => {
+            group.bench_function("synthetic", |b| {
+                b.iter_batched(synthetic_blocks, build, BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let db = load();
+    let queries = [
+        ("single_word", "castle", 10),
+        ("single_word_fuzzy", "brigde", 10),
+        (
+            "multi_word_fuzzy",
+            "hotels in saint brigde near norht haven",
+            10,
+        ),
+        ("high_limit", "port", 1000),
+    ];
+    let mut group = c.benchmark_group("search");
+    for (name, query, limit) in queries {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let st = SearchTerm::from_raw_query(query.to_string(), None, limit, 2);
+                black_box(db.search(&st))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_build, bench_search);
+criterion_main!(benches);
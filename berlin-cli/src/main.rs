@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use berlin_core::location::Location;
+use berlin_core::search::Score;
+use berlin_core::ustr::Ustr;
+use berlin_db::builder::{
+    check_data_files, dataset_versions, BuildError, DataFile, LocationsDbBuilder,
+};
+use berlin_db::db::BerlinDb;
+use berlin_db::query::raw_score;
+use berlin_db::search::SearchOptions;
+use berlin_explain::{word_coverage, LocationRepr, ScoreBreakdown};
+use serde_json::{json, Value};
+
+const USAGE: &str = "Usage:
+  berlin [--data-dir DIR] search QUERY [--state CODE] [--limit N] [--lev-distance N] [--format FORMAT]
+  berlin [--data-dir DIR] get KEY
+  berlin [--data-dir DIR] explain QUERY KEY [--state CODE] [--lev-distance N]
+  berlin [--data-dir DIR] bulk FILE --column NAME [--state CODE] [--limit N] [--lev-distance N] [--format FORMAT]
+  berlin [--data-dir DIR] negatives FILE [--threshold N] [--state CODE] [--lev-distance N]
+
+The data directory defaults to ./data, as for berlin-web, and may also be set
+with BERLIN_DATA_DIR. Results are written to stdout as JSON lines. The data
+directory is loaded as by the Python bindings: the files listed in its
+data-files.json, or else the default files and any regional files such as
+ISO-3166-2:GB.json, with any stop-words.txt, aliases.json,
+transliterations.json, which maps names in other scripts to the names in the
+data, such as {\"Москва\": \"Moscow\"}, tags.json, importance.json,
+external-ids.tsv, int-ids.json and rewrites.json.
+
+With --format csv, search and bulk write a CSV row per result instead, with
+columns query, key, name, state, subdiv, score, lat and lon, for spreadsheets
+and shell pipelines.
+
+negatives reads one known non-location phrase per line and reports each that
+matches a location scoring above the threshold, exiting non-zero if any do.
+
+explain writes the score breakdown of location KEY for QUERY, in the same
+format as the Python bindings' explain.";
+
+const CSV_COLUMNS: [&str; 8] = [
+    "query", "key", "name", "state", "subdiv", "score", "lat", "lon",
+];
+
+const DEFAULT_LIMIT: usize = 1;
+const DEFAULT_LEV_DISTANCE: u32 = 2;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Csv,
+}
+
+enum Command {
+    Search {
+        query: String,
+        search: SearchArgs,
+        format: Format,
+    },
+    Get {
+        key: String,
+    },
+    Explain {
+        query: String,
+        key: String,
+        search: SearchArgs,
+    },
+    Bulk {
+        file: PathBuf,
+        column: String,
+        search: SearchArgs,
+        format: Format,
+    },
+    Negatives {
+        file: PathBuf,
+        threshold: i64,
+        search: SearchArgs,
+    },
+}
+
+struct SearchArgs {
+    state: Option<String>,
+    limit: usize,
+    lev_distance: u32,
+}
+
+struct CliArgs {
+    data_dir: PathBuf,
+    command: Command,
+}
+
+fn parse_args(mut args: VecDeque<String>) -> Result<CliArgs, String> {
+    let mut data_dir = match std::env::var("BERLIN_DATA_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from("./data"),
+    };
+    let mut positional: Vec<String> = vec![];
+    let mut state = None;
+    let mut limit = DEFAULT_LIMIT;
+    let mut lev_distance = DEFAULT_LEV_DISTANCE;
+    let mut column = None;
+    let mut threshold = 0;
+    let mut format = Format::Json;
+    while let Some(arg) = args.pop_front() {
+        let mut value = |name: &str| {
+            args.pop_front()
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+        match arg.as_str() {
+            "--data-dir" => data_dir = PathBuf::from(value("--data-dir")?),
+            "--state" => state = Some(value("--state")?),
+            "--column" => column = Some(value("--column")?),
+            "--format" => {
+                format = match value("--format")?.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    other => return Err(format!("--format: {other} is not json or csv")),
+                }
+            }
+            "--limit" => {
+                limit = value("--limit")?
+                    .parse()
+                    .map_err(|err| format!("--limit: {err}"))?
+            }
+            "--lev-distance" => {
+                lev_distance = value("--lev-distance")?
+                    .parse()
+                    .map_err(|err| format!("--lev-distance: {err}"))?
+            }
+            "--threshold" => {
+                threshold = value("--threshold")?
+                    .parse()
+                    .map_err(|err| format!("--threshold: {err}"))?
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {flag}")),
+            _ => positional.push(arg),
+        }
+    }
+    let search = SearchArgs {
+        state,
+        limit,
+        lev_distance,
+    };
+    let command = match positional.as_slice() {
+        [cmd, query] if cmd == "search" => Command::Search {
+            query: query.clone(),
+            search,
+            format,
+        },
+        [cmd, key] if cmd == "get" => Command::Get { key: key.clone() },
+        [cmd, query, key] if cmd == "explain" => Command::Explain {
+            query: query.clone(),
+            key: key.clone(),
+            search,
+        },
+        [cmd, file] if cmd == "bulk" => Command::Bulk {
+            file: PathBuf::from(file),
+            column: column.ok_or("bulk requires --column")?,
+            search,
+            format,
+        },
+        [cmd, file] if cmd == "negatives" => Command::Negatives {
+            file: PathBuf::from(file),
+            threshold,
+            search,
+        },
+        _ => return Err(USAGE.to_string()),
+    };
+    Ok(CliArgs { data_dir, command })
+}
+
+fn loc_json(loc: &Location, score: Option<Score>) -> Value {
+    let mut value = serde_json::to_value(LocationRepr::brief(loc)).expect("locations serialize");
+    if let Some(score) = score {
+        value["score"] = json!(score.score);
+        value["offset"] = json!(score.offset);
+    }
+    value
+}
+
+// Offsets are reported as characters of the raw query, and coverage of the
+// normalized query, as by the Python bindings
+fn search(db: &BerlinDb, query: &str, args: &SearchArgs) -> Vec<Value> {
+    let rewrite = db.rewrite(query);
+    let (_, char_indices) = rewrite.normalizer(db.normalizer()).with_offsets(query);
+    let st = db.rewritten_search_term(
+        query.to_string(),
+        args.state.clone(),
+        args.limit,
+        args.lev_distance,
+        None,
+        &rewrite,
+    );
+    let is_stop_word = |word: &str| db.stop_words().iter().any(|stop_word| stop_word == word);
+    let mut results = db.search(&st, &SearchOptions::default());
+    results.truncate(args.limit);
+    results
+        .into_iter()
+        .filter_map(|(key, score)| {
+            let mut value = loc_json(db.all.get(&key)?, Some(raw_score(&char_indices, score)));
+            value["coverage"] = json!(word_coverage(
+                &st.normalized,
+                score.offset.start,
+                score.offset.end,
+                is_stop_word
+            ));
+            Some(value)
+        })
+        .collect()
+}
+
+// A CSV row of a search result, as from `search`
+fn csv_row(db: &BerlinDb, query: &str, result: &Value) -> Vec<String> {
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    };
+    let key = Ustr::from(result["key"].as_str().unwrap_or_default());
+    let coordinates = json!(db.coordinates(&key));
+    vec![
+        query.to_string(),
+        key.to_string(),
+        db.display_name(&key).unwrap_or_default(),
+        text(&result["state"]),
+        text(&result["subdiv"]),
+        text(&result["score"]),
+        text(&coordinates["lat"]),
+        text(&coordinates["lon"]),
+    ]
+}
+
+// As the Python bindings' explain, so both report the same breakdown
+fn explain(db: &BerlinDb, query: &str, key: &str, args: &SearchArgs) -> Option<ScoreBreakdown> {
+    let loc = db.retrieve(key)?;
+    let st = db.search_term(
+        query.to_string(),
+        args.state.clone(),
+        1,
+        args.lev_distance,
+        None,
+    );
+    db.explain(&st, &SearchOptions::default(), &loc.key)
+}
+
+// Every missing or undecodable file is reported at once, rather than the
+// first, as by the Python bindings' load
+fn load_data_dir(data_dir: &Path) -> Result<BerlinDb, BuildError> {
+    let files = DataFile::in_dir(data_dir)?;
+    check_data_files(&files)?;
+    let mut builder = LocationsDbBuilder::new();
+    if let Some((_, version)) = dataset_versions(data_dir, &files)?.into_iter().next() {
+        builder = builder.version(version);
+    }
+    for file in &files {
+        builder = builder.add_data_file(file)?;
+    }
+    builder.add_optional_files(data_dir)?.build()
+}
+
+fn run(args: CliArgs) -> Result<(), Box<dyn Error>> {
+    let db = &load_data_dir(&args.data_dir)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    match args.command {
+        Command::Search {
+            query,
+            search: sa,
+            format,
+        } => {
+            let results = search(db, &query, &sa);
+            match format {
+                Format::Json => {
+                    for result in results {
+                        writeln!(out, "{result}")?;
+                    }
+                }
+                Format::Csv => {
+                    let mut writer = csv::Writer::from_writer(&mut out);
+                    writer.write_record(CSV_COLUMNS)?;
+                    for result in results {
+                        writer.write_record(csv_row(db, &query, &result))?;
+                    }
+                    writer.flush()?;
+                }
+            }
+        }
+        Command::Get { key } => match db.retrieve(&key) {
+            Some(loc) => writeln!(out, "{}", loc_json(loc, None))?,
+            None => return Err(format!("{key} not found").into()),
+        },
+        Command::Explain {
+            query,
+            key,
+            search: sa,
+        } => {
+            if db.retrieve(&key).is_none() {
+                return Err(format!("{key} not found").into());
+            }
+            let explanation = explain(db, &query, &key, &sa);
+            writeln!(out, "{}", json!(explanation))?;
+        }
+        Command::Bulk {
+            file,
+            column,
+            search: sa,
+            format,
+        } => {
+            let mut reader = csv::Reader::from_reader(File::open(&file)?);
+            let idx = reader
+                .headers()?
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| format!("No column {column} in {}", file.display()))?;
+            let queries = reader.records().map(|record| {
+                Ok::<_, csv::Error>(record?.get(idx).unwrap_or_default().to_string())
+            });
+            match format {
+                Format::Json => {
+                    for query in queries {
+                        let query = query?;
+                        let results = search(db, &query, &sa);
+                        writeln!(out, "{}", json!({ "query": query, "results": results }))?;
+                    }
+                }
+                Format::Csv => {
+                    let mut writer = csv::Writer::from_writer(&mut out);
+                    writer.write_record(CSV_COLUMNS)?;
+                    for query in queries {
+                        let query = query?;
+                        for result in search(db, &query, &sa) {
+                            writer.write_record(csv_row(db, &query, &result))?;
+                        }
+                    }
+                    writer.flush()?;
+                }
+            }
+        }
+        Command::Negatives {
+            file,
+            threshold,
+            search: sa,
+        } => {
+            let sa = SearchArgs { limit: 1, ..sa };
+            let mut found = 0;
+            for phrase in fs::read_to_string(&file)?.lines() {
+                let phrase = phrase.trim();
+                if phrase.is_empty() {
+                    continue;
+                }
+                let results = search(db, phrase, &sa);
+                if let Some(result) = results
+                    .into_iter()
+                    .find(|r| r["score"].as_i64() > Some(threshold))
+                {
+                    writeln!(out, "{}", json!({ "query": phrase, "result": result }))?;
+                    found += 1;
+                }
+            }
+            out.flush()?;
+            if found > 0 {
+                return Err(format!("{found} false positives above {threshold}").into());
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("berlin: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
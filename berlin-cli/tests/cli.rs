@@ -0,0 +1,66 @@
+//! Runs the `berlin` binary against the test data of the Python bindings.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+// A data directory of the test codes and code list, unique to `name`
+fn data_dir(name: &str) -> PathBuf {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let dir = std::env::temp_dir().join(format!["berlin-cli-{name}-{}", std::process::id()]);
+    fs::create_dir_all(&dir).unwrap();
+    for file in ["test-codes.json", "test-code-list.csv"] {
+        fs::copy(source.join(file), dir.join(file)).unwrap();
+    }
+    fs::write(
+        dir.join("data-files.json"),
+        r#"["test-codes.json", ["code-list", "test-code-list.csv"]]"#,
+    )
+    .unwrap();
+    dir
+}
+
+// The standard output of a successful run of `berlin` on `dir`
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_berlin"))
+        .arg("--data-dir")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn search(dir: &Path, query: &str) -> Vec<Value> {
+    run(dir, &["search", query])
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn offsets_are_characters_of_the_raw_query() {
+    let dir = data_dir("offsets");
+    // "Ærø" is normalized to "aero", one byte longer, and "ö" to "o"
+    for (query, start, end) in [("Ærø, Stonehaven", 5, 15), ("«Stönehaven»!", 1, 11)] {
+        let results = search(&dir, query);
+        assert_eq!(results[0]["key"], "UN-LOCODE-gb:svn");
+        assert_eq!(results[0]["offset"]["start"], start, "{query}");
+        assert_eq!(results[0]["offset"]["end"], end, "{query}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn csv_names_are_display_names() {
+    let dir = data_dir("csv");
+    let output = run(&dir, &["search", "bognor regis", "--format", "csv"]);
+    let row = output.lines().nth(1).unwrap();
+    assert!(
+        row.starts_with("bognor regis,UN-LOCODE-gb:bsi,Bognor Regis,gb,"),
+        "{row}"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
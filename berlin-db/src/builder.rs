@@ -0,0 +1,1054 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use berlin_core::coordinates::Coordinates;
+use berlin_core::location::{AnyLocation, CsvLocode, Location};
+use berlin_core::locations_db::{parse_data_list, LocationsDb};
+use berlin_core::normalize;
+use berlin_core::ustr::Ustr;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::db::{
+    BerlinDb, DatasetVersion, ExternalIds, LocodeStatus, FIPS_ENCODING, NUTS_ENCODING,
+    POSTAL_ENCODING,
+};
+use crate::query::{Normalizer, RewriteRules};
+
+/// The JSON data files every data directory has.
+pub const DATA_FILES: [&str; 4] = ["state.json", "subdivision.json", "locode.json", "iata.json"];
+
+/// The LOCODE code list of a data directory.
+pub const CODE_LIST_FILE: &str = "code-list_csv.csv";
+
+/// Lists the data files to load in place of the defaults, as a JSON array
+/// of paths or [kind, path] pairs.
+pub const MANIFEST_FILE: &str = "data-files.json";
+
+/// Records the release of the data in its directory, as a JSON object with
+/// "version" and, optionally, "date".
+pub const VERSION_FILE: &str = "version.json";
+
+/// An optional list of stop words, one per line, replacing the defaults.
+pub const STOP_WORDS_FILE: &str = "stop-words.txt";
+
+/// Optional colloquial names, as a JSON object from key to names.
+pub const ALIASES_FILE: &str = "aliases.json";
+
+/// Optional names in other scripts, as a JSON object from each to the name
+/// in the data, such as {"Москва": "Moscow"}.
+pub const TRANSLITERATIONS_FILE: &str = "transliterations.json";
+
+/// An optional overlay of tags for locations, as a JSON object from key to
+/// tags.
+pub const TAGS_FILE: &str = "tags.json";
+
+/// Optional importance of locations, as a JSON object from key to number.
+pub const IMPORTANCE_FILE: &str = "importance.json";
+
+/// Optional rules rewriting every query, as a JSON object of "drop",
+/// "synonyms" and "states".
+pub const REWRITES_FILE: &str = "rewrites.json";
+
+/// Optional GeoNames IDs and Wikidata QIDs of locations, as a TSV file with
+/// columns key, geonames and wikidata.
+pub const EXTERNAL_IDS_FILE: &str = "external-ids.tsv";
+
+/// Integer ids from an earlier load, as a JSON object from key to id.
+pub const INT_IDS_FILE: &str = "int-ids.json";
+
+// Regional subdivision files, such as ISO-3166-2:GB.json, are loaded if
+// present
+const REGIONAL_FILE_PREFIX: &str = "ISO-3166-2:";
+
+/// A data file and how it is read.
+#[derive(Clone)]
+pub enum DataFile {
+    /// A JSON object from key to location.
+    Json(PathBuf),
+    /// The LOCODE code list, completing the LOCODEs of the JSON files.
+    CodeList(PathBuf),
+    /// A CSV table of locations.
+    Csv(CsvTable, PathBuf),
+}
+
+impl DataFile {
+    /// A file of the given kind: "json", "code-list" or a `CsvTable`. With
+    /// no kind, CSV files are taken as code lists and the rest as JSON.
+    pub fn parse(kind: Option<&str>, path: PathBuf) -> Result<Self, String> {
+        Ok(match kind {
+            Some("json") => DataFile::Json(path),
+            Some("code-list") => DataFile::CodeList(path),
+            Some(kind) => match CsvTable::parse(kind) {
+                Some(table) => DataFile::Csv(table, path),
+                None => return Err(format!("Unknown kind of data file: {kind}")),
+            },
+            None if path.extension().is_some_and(|ext| ext == "csv") => DataFile::CodeList(path),
+            None => DataFile::Json(path),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            DataFile::Json(path) | DataFile::CodeList(path) | DataFile::Csv(_, path) => path,
+        }
+    }
+
+    /// The data files of `data_dir`: those its manifest lists, relative to
+    /// it, or else the default files and any regional files present.
+    pub fn in_dir(data_dir: &Path) -> Result<Vec<Self>, BuildError> {
+        let manifest_path = data_dir.join(MANIFEST_FILE);
+        if manifest_path.exists() {
+            return read_manifest(data_dir, &manifest_path);
+        }
+        let mut files = DATA_FILES
+            .iter()
+            .map(|file| DataFile::Json(data_dir.join(file)))
+            .collect::<Vec<_>>();
+        let mut regional = match fs::read_dir(data_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.starts_with(REGIONAL_FILE_PREFIX) && name.ends_with(".json"))
+                .collect::<Vec<_>>(),
+            Err(err) => return Err(BuildError::Io(data_dir.to_path_buf(), err)),
+        };
+        regional.sort();
+        files.extend(
+            regional
+                .iter()
+                .map(|file| DataFile::Json(data_dir.join(file))),
+        );
+        files.push(DataFile::CodeList(data_dir.join(CODE_LIST_FILE)));
+        Ok(files)
+    }
+}
+
+fn read_manifest(data_dir: &Path, path: &Path) -> Result<Vec<DataFile>, BuildError> {
+    let invalid =
+        |err: String| BuildError::Json(vec![RecordError::new(path.display().to_string(), err)]);
+    let contents =
+        fs::read_to_string(path).map_err(|err| BuildError::Io(path.to_path_buf(), err))?;
+    let entries: Vec<Value> =
+        serde_json::from_str(&contents).map_err(|err| invalid(err.to_string()))?;
+    entries
+        .iter()
+        .map(|entry| {
+            let (kind, file) = match entry {
+                Value::String(file) => (None, file),
+                Value::Array(pair) => match pair.as_slice() {
+                    [Value::String(kind), Value::String(file)] => (Some(kind.as_str()), file),
+                    _ => return Err(invalid(format!("expected [kind, path], not {entry}"))),
+                },
+                _ => {
+                    return Err(invalid(format!(
+                        "expected a path or [kind, path], not {entry}"
+                    )))
+                }
+            };
+            DataFile::parse(kind, data_dir.join(file)).map_err(invalid)
+        })
+        .collect()
+}
+
+/// The versions recorded beside `data_dir` and the data files, one for each
+/// directory recording one, `data_dir`'s first. More than one version means
+/// the data files come from different releases.
+pub fn dataset_versions(
+    data_dir: &Path,
+    files: &[DataFile],
+) -> Result<Vec<(PathBuf, DatasetVersion)>, BuildError> {
+    let mut dirs = vec![data_dir.to_path_buf()];
+    for dir in files.iter().filter_map(|file| file.path().parent()) {
+        if !dirs.iter().any(|seen| seen == dir) {
+            dirs.push(dir.to_path_buf());
+        }
+    }
+    let mut versions = vec![];
+    for dir in dirs {
+        let path = dir.join(VERSION_FILE);
+        if !path.exists() {
+            continue;
+        }
+        let contents =
+            fs::read_to_string(&path).map_err(|err| BuildError::Io(path.clone(), err))?;
+        let version = serde_json::from_str(&contents).map_err(|err| {
+            BuildError::Json(vec![RecordError::new(
+                path.display().to_string(),
+                err.to_string(),
+            )])
+        })?;
+        versions.push((dir, version));
+    }
+    Ok(versions)
+}
+
+/// Check that every data file exists, before reading any of them.
+pub fn check_data_files(files: &[DataFile]) -> Result<(), BuildError> {
+    let missing = files
+        .iter()
+        .map(DataFile::path)
+        .filter(|path| !path.is_file())
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(BuildError::Missing(missing)),
+    }
+}
+
+/// Why a database could not be built.
+#[derive(Debug)]
+pub enum BuildError {
+    /// A data file could not be read.
+    Io(PathBuf, std::io::Error),
+    /// Data files that do not exist.
+    Missing(Vec<PathBuf>),
+    /// Records of location data that could not be parsed.
+    Json(Vec<RecordError>),
+    /// An alias, tag or other overlay was given for a location that is not
+    /// in the data.
+    UnknownKey(String),
+    /// Recorded integer ids that are missing or repeated, so cannot be kept.
+    IntId(PathBuf, u32),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Io(path, err) => write!(f, "Cannot read {}: {err}", path.display()),
+            BuildError::Missing(paths) => {
+                let paths = paths.iter().map(|path| path.display().to_string());
+                write!(
+                    f,
+                    "Missing data files: {}",
+                    paths.collect::<Vec<_>>().join(", ")
+                )
+            }
+            BuildError::Json(errors) => {
+                let errors = errors.iter().map(RecordError::to_string);
+                write!(
+                    f,
+                    "Parsing errors:\n{}",
+                    errors.collect::<Vec<_>>().join("\n")
+                )
+            }
+            BuildError::UnknownKey(key) => write!(f, "{key} not found"),
+            BuildError::IntId(path, int_id) => write!(
+                f,
+                "Integer id {int_id} is missing or repeated in {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Why a record of location data could not be parsed: the block or file it
+/// is in, its position or key there, if known, and the field at fault.
+#[derive(Debug)]
+pub struct RecordError {
+    pub block: String,
+    pub index: Option<usize>,
+    pub record: Option<String>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl RecordError {
+    /// An error in a block as a whole.
+    pub fn new(block: impl Into<String>, message: impl Into<String>) -> Self {
+        RecordError {
+            block: block.into(),
+            index: None,
+            record: None,
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    /// An error in one record, with the field taken from serde's message.
+    pub fn in_record(block: &str, record: &str, message: String) -> Self {
+        RecordError {
+            field: message_field(&message),
+            record: Some(record.to_string()),
+            ..RecordError::new(block, message)
+        }
+    }
+
+    /// An error in the record at `index` of a block of rows.
+    pub fn in_row(block: &str, index: usize, field: Option<&str>, message: String) -> Self {
+        RecordError {
+            index: Some(index),
+            field: field.map(String::from),
+            ..RecordError::new(block, message)
+        }
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.block)?;
+        if let Some(index) = self.index {
+            write!(f, ", record {index}")?;
+        }
+        if let Some(record) = &self.record {
+            write!(f, ", {record}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// The field of "missing field `name`" from serde, "Missing field name" from
+// berlin-core and similar messages
+fn message_field(message: &str) -> Option<String> {
+    let (_, rest) = message.split_once("field ")?;
+    let field = rest
+        .trim_start_matches('`')
+        .split(|c: char| c == '`' || c.is_whitespace())
+        .next()?;
+    (!field.is_empty()).then(|| field.to_string())
+}
+
+/// Gathers what a database is built from: JSON blocks of locations, rows
+/// of the LOCODE code list, aliases, transliterations and stop words, and
+/// the tags, importance and other overlays of a data directory.
+/// The Python bindings and the command line build their databases through
+/// it.
+#[derive(Default)]
+pub struct LocationsDbBuilder {
+    db: LocationsDb,
+    source_names: Vec<SourceNames>,
+    locodes: Vec<CsvLocode>,
+    aliases: Vec<(String, String)>,
+    transliterations: Vec<(String, String)>,
+    normalizer: Normalizer,
+    stop_words: Option<Vec<String>>,
+    version: Option<DatasetVersion>,
+    tags: HashMap<String, Vec<String>>,
+    importance: HashMap<String, f64>,
+    external_ids: Vec<(String, ExternalIds)>,
+    int_ids: Option<(PathBuf, HashMap<String, u32>)>,
+    rewrites: Option<RewriteRules>,
+    errors: Vec<RecordError>,
+}
+
+impl LocationsDbBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Normalize names and queries with `normalizer`. Set before adding
+    /// locations, whose names are normalized as they are added.
+    pub fn normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Parse a block of locations, given as a JSON object from key to
+    /// location. Errors are reported by `build`.
+    pub fn add_json_block(mut self, name: String, string: &str) -> Self {
+        match serde_json::from_str::<Value>(string) {
+            Ok(value) => self.add_json_value(name, value),
+            Err(err) => {
+                self.errors.push(RecordError::new(name, err.to_string()));
+                self
+            }
+        }
+    }
+
+    /// Add a block of locations that has already been parsed.
+    pub fn add_json_value(mut self, name: String, mut value: Value) -> Self {
+        for id in invalid_elevations(&mut value) {
+            self.errors.push(RecordError {
+                field: Some("elevation".to_string()),
+                ..RecordError::in_record(&name, &id, "not a whole number of feet".to_string())
+            });
+        }
+        let block = (name, value);
+        self.source_names
+            .extend(source_names(std::slice::from_ref(&block)));
+        let (name, value) = block;
+        let Value::Object(entries) = value else {
+            let message = format!("Expected a JSON object, not {value}");
+            self.errors.push(RecordError::new(name, message));
+            return self;
+        };
+        // each location is decoded as berlin-core's parse_data_blocks
+        // would, but its errors are kept apart
+        let results = entries
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|(id, mut entry)| {
+                self.normalizer.apply_to_entry(&mut entry);
+                serde_json::from_value::<AnyLocation>(entry)
+                    .and_then(Location::from_raw)
+                    .map_err(|err| RecordError::in_record(&name, &id, err.to_string()))
+            })
+            .collect::<Vec<_>>();
+        for result in results {
+            match result {
+                Ok(loc) => self.db.insert(loc),
+                Err(err) => self.errors.push(err),
+            }
+        }
+        self
+    }
+
+    pub fn add_json_file(self, path: &Path) -> Result<Self, BuildError> {
+        match fs::read_to_string(path) {
+            Ok(string) => Ok(self.add_json_block(path.display().to_string(), &string)),
+            Err(err) => Err(BuildError::Io(path.to_path_buf(), err)),
+        }
+    }
+
+    /// Add rows of the LOCODE code list, whose coordinates and IATA codes
+    /// complete the LOCODEs of the JSON blocks.
+    pub fn add_csv_locodes(mut self, rows: impl IntoIterator<Item = CsvLocode>) -> Self {
+        self.locodes.extend(rows);
+        self
+    }
+
+    /// Add the rows of a code list file. Rows that cannot be decoded are
+    /// reported by `build`.
+    pub fn add_csv_file(mut self, path: &Path) -> Result<Self, BuildError> {
+        match read_csv::<CsvLocode>(path) {
+            Ok(rows) => Ok(self.add_csv_locodes(rows)),
+            Err(err) => {
+                self.errors.push(err);
+                Ok(self)
+            }
+        }
+    }
+
+    /// Report errors found in records before they were added, such as
+    /// rows missing columns.
+    pub fn add_errors(mut self, errors: impl IntoIterator<Item = RecordError>) -> Self {
+        self.errors.extend(errors);
+        self
+    }
+
+    /// Add the locations of a CSV table. LOCODEs are read from the code
+    /// list itself, which also completes them as `add_csv_file` does. Rows
+    /// that cannot be decoded are reported by `build`.
+    pub fn add_csv_table(mut self, table: CsvTable, path: &Path) -> Result<Self, BuildError> {
+        let name = path.display().to_string();
+        if let CsvTable::Locode = table {
+            let rows = match read_csv::<CsvLocode>(path) {
+                Ok(rows) => rows,
+                Err(err) => {
+                    self.errors.push(err);
+                    return Ok(self);
+                }
+            };
+            let block = rows
+                .iter()
+                .filter(|row| !row.subcode.trim().is_empty())
+                .map(locode_entry)
+                .collect();
+            let builder = self.add_json_value(name, Value::Object(block));
+            return Ok(builder.add_csv_locodes(rows));
+        }
+        let rows = match read_csv::<HashMap<String, String>>(path) {
+            Ok(rows) => rows,
+            Err(err) => {
+                self.errors.push(err);
+                return Ok(self);
+            }
+        };
+        let mut block = Map::new();
+        for (n, row) in rows.into_iter().enumerate() {
+            if table.skips(&row) {
+                continue;
+            }
+            match table.entry(row) {
+                Ok((id, entry)) => {
+                    block.insert(id, entry);
+                }
+                Err((field, err)) => self.errors.push(RecordError::in_row(&name, n, field, err)),
+            }
+        }
+        Ok(self.add_json_value(name, Value::Object(block)))
+    }
+
+    /// Read a data file. Files that cannot be decoded are reported
+    /// together by `build`, rather than one at a time.
+    pub fn add_data_file(self, file: &DataFile) -> Result<Self, BuildError> {
+        match file {
+            DataFile::Json(path) => self.add_json_file(path),
+            DataFile::CodeList(path) => self.add_csv_file(path),
+            DataFile::Csv(table, path) => self.add_csv_table(*table, path),
+        }
+    }
+
+    /// Add colloquial names, as (key, name) pairs.
+    pub fn add_aliases(mut self, aliases: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.aliases.extend(aliases);
+        self
+    }
+
+    /// Add names in other scripts, as (name, name in the data) pairs.
+    pub fn add_transliterations(
+        mut self,
+        transliterations: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.transliterations.extend(transliterations);
+        self
+    }
+
+    /// Replace the default stop words.
+    pub fn stop_words(mut self, stop_words: impl IntoIterator<Item = String>) -> Self {
+        self.stop_words = Some(stop_words.into_iter().collect());
+        self
+    }
+
+    /// Add the stop words, aliases, transliterations, tags, importance,
+    /// external ids, integer ids and rewrite rules of `data_dir`, from
+    /// whichever of their files it has.
+    pub fn add_optional_files(mut self, data_dir: &Path) -> Result<Self, BuildError> {
+        let stop_words_path = data_dir.join(STOP_WORDS_FILE);
+        if stop_words_path.exists() {
+            let contents = fs::read_to_string(&stop_words_path)
+                .map_err(|err| BuildError::Io(stop_words_path, err))?;
+            self = self.stop_words(
+                contents
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(String::from),
+            );
+        }
+        let aliases_path = data_dir.join(ALIASES_FILE);
+        if aliases_path.exists() {
+            let aliases: HashMap<String, Vec<String>> = read_json_file(&aliases_path)?;
+            self =
+                self.add_aliases(aliases.into_iter().flat_map(|(key, names)| {
+                    names.into_iter().map(move |name| (key.clone(), name))
+                }));
+        }
+        let transliterations_path = data_dir.join(TRANSLITERATIONS_FILE);
+        if transliterations_path.exists() {
+            let transliterations: HashMap<String, String> = read_json_file(&transliterations_path)?;
+            self = self.add_transliterations(transliterations);
+        }
+        let tags_path = data_dir.join(TAGS_FILE);
+        if tags_path.exists() {
+            self.tags = read_json_file(&tags_path)?;
+        }
+        let importance_path = data_dir.join(IMPORTANCE_FILE);
+        if importance_path.exists() {
+            self.importance = read_json_file(&importance_path)?;
+        }
+        let external_ids_path = data_dir.join(EXTERNAL_IDS_FILE);
+        if external_ids_path.exists() {
+            self.external_ids = read_external_ids(&external_ids_path)?;
+        }
+        let int_ids_path = data_dir.join(INT_IDS_FILE);
+        if int_ids_path.exists() {
+            let recorded = read_json_file(&int_ids_path)?;
+            self.int_ids = Some((int_ids_path, recorded));
+        }
+        let rewrites_path = data_dir.join(REWRITES_FILE);
+        if rewrites_path.exists() {
+            self.rewrites = Some(read_json_file(&rewrites_path)?);
+        }
+        Ok(self)
+    }
+
+    /// Record the release of the data the database is built from.
+    pub fn version(mut self, version: DatasetVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn build(self) -> Result<BerlinDb, BuildError> {
+        if !self.errors.is_empty() {
+            return Err(BuildError::Json(self.errors));
+        }
+        let code_list = CodeListExtras::from_rows(&self.locodes);
+        let db = match parse_data_list(self.db, self.locodes.into_iter()) {
+            Ok(db) => db,
+            Err(err) => {
+                return Err(BuildError::Json(vec![RecordError::new(
+                    "rows",
+                    err.to_string(),
+                )]))
+            }
+        };
+        let mut db = BerlinDb::new(db);
+        db.set_normalizer(self.normalizer);
+        db.set_version(self.version);
+        code_list.apply(&mut db);
+        self.source_names
+            .into_iter()
+            .for_each(|names| names.apply(&mut db));
+        if let Some(stop_words) = self.stop_words {
+            db.set_stop_words(stop_words.into_iter());
+        }
+        for (key, name) in self.aliases {
+            match Ustr::from_existing(&key).filter(|key| db.all.contains_key(key)) {
+                Some(key) => db.add_alias(key, &name),
+                None => return Err(BuildError::UnknownKey(key)),
+            }
+        }
+        for (native, name) in self.transliterations {
+            db.add_transliteration(&native, &name);
+        }
+        apply_tags(&mut db, self.tags)?;
+        apply_importance(&mut db, self.importance)?;
+        apply_external_ids(&mut db, self.external_ids)?;
+        if let Some((path, recorded)) = self.int_ids {
+            apply_int_ids(&mut db, &path, recorded)?;
+        }
+        if let Some(rules) = self.rewrites {
+            db.set_rewrite_rules(rules);
+        }
+        Ok(db)
+    }
+}
+
+/// A table of locations distributed as CSV. The columns are the fields of
+/// the JSON data, except for the LOCODE code list, which is read as
+/// published.
+#[derive(Clone, Copy)]
+pub enum CsvTable {
+    /// alpha2, alpha3, name, short (the name if missing) and continent.
+    State,
+    /// supercode, subcode, name and level.
+    Subdivision,
+    Locode,
+    /// iata, name, type, city, country, region, x, y and elevation.
+    Airport,
+    /// Eurostat NUTS regions of levels 1 to 3: code, name and, optionally,
+    /// the ISO 3166-2 subdivision each lies in, such as "GB-SCT". Rows for
+    /// whole countries (level 0) are skipped.
+    Nuts,
+    /// US FIPS state (two digit) and county (five digit) codes: code, name
+    /// and, optionally, the ISO 3166-2 subdivision, such as "US-CA".
+    Fips,
+    /// Postal codes or their prefixes, such as UK outcodes: country, code
+    /// and, optionally, name (the code if missing), the ISO 3166-2
+    /// subdivision, and lat and lon in degrees.
+    Postal,
+}
+
+// NUTS codes start with the ISO 3166-1 alpha-2 code of the country, but
+// for these
+const NUTS_COUNTRIES: [(&str, &str); 2] = [("EL", "GR"), ("UK", "GB")];
+
+impl CsvTable {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "state" => CsvTable::State,
+            "subdivision" => CsvTable::Subdivision,
+            "locode" => CsvTable::Locode,
+            "iata" => CsvTable::Airport,
+            "nuts" => CsvTable::Nuts,
+            "fips" => CsvTable::Fips,
+            "postal" => CsvTable::Postal,
+            _ => return None,
+        })
+    }
+
+    // Whether a row holds no location of its own, as the NUTS rows for
+    // whole countries, which are the states
+    fn skips(self, row: &HashMap<String, String>) -> bool {
+        match self {
+            CsvTable::Nuts => row.get("code").is_some_and(|code| code.trim().len() == 2),
+            _ => false,
+        }
+    }
+
+    // The id and JSON entry of the location in a row. Empty cells are left
+    // out, and any other columns, such as "name:fr", are kept as fields.
+    // Errors come with the column at fault.
+    fn entry(
+        self,
+        row: HashMap<String, String>,
+    ) -> Result<(String, Value), (Option<&'static str>, String)> {
+        let mut data = row
+            .into_iter()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(column, value)| (column, Value::String(value.trim().to_string())))
+            .collect::<Map<_, _>>();
+        let column = |data: &Map<String, Value>, column: &'static str| match data.get(column) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            _ => Err((Some(column), format!("no {column}"))),
+        };
+        let (encoding, id) = match self {
+            CsvTable::State => {
+                if !data.contains_key("short") {
+                    data.insert("short".to_string(), Value::String(column(&data, "name")?));
+                }
+                ("ISO-3166-1", column(&data, "alpha2")?)
+            }
+            CsvTable::Subdivision => {
+                let id = format!(
+                    "{}:{}",
+                    column(&data, "supercode")?,
+                    column(&data, "subcode")?
+                );
+                ("ISO-3166-2", id)
+            }
+            CsvTable::Airport => {
+                for axis in ["x", "y"] {
+                    let value = column(&data, axis)?;
+                    let value = value
+                        .parse::<f64>()
+                        .map_err(|_| (Some(axis), format!("{axis} is not a number: {value}")))?;
+                    data.insert(axis.to_string(), Value::from(value));
+                }
+                ("IATA", column(&data, "iata")?)
+            }
+            CsvTable::Nuts => {
+                let code = column(&data, "code")?.to_uppercase();
+                if !code.is_ascii() || !(3..=5).contains(&code.len()) {
+                    return Err((Some("code"), format!("not a NUTS code: {code}")));
+                }
+                let (country, _) = code.split_at(2);
+                let country = NUTS_COUNTRIES
+                    .iter()
+                    .find(|(nuts, _)| *nuts == country)
+                    .map_or(country, |(_, iso)| iso);
+                data.insert("supercode".to_string(), Value::from(country));
+                data.insert("subcode".to_string(), Value::from(code.as_str()));
+                link_subdivision(&mut data);
+                (NUTS_ENCODING, code)
+            }
+            CsvTable::Fips => {
+                let code = column(&data, "code")?;
+                let code = match code.len() {
+                    _ if !code.chars().all(|c| c.is_ascii_digit()) => None,
+                    1..=2 => Some(format!("{code:0>2}")),
+                    4..=5 => Some(format!("{code:0>5}")),
+                    _ => None,
+                };
+                let Some(code) = code else {
+                    return Err((Some("code"), "not a FIPS state or county code".to_string()));
+                };
+                data.insert("supercode".to_string(), Value::from("US"));
+                data.insert("subcode".to_string(), Value::from(code.as_str()));
+                link_subdivision(&mut data);
+                (FIPS_ENCODING, code)
+            }
+            CsvTable::Postal => {
+                let country = column(&data, "country")?.to_uppercase();
+                let code = column(&data, "code")?.to_uppercase();
+                if !data.contains_key("name") {
+                    data.insert("name".to_string(), Value::from(code.as_str()));
+                }
+                data.insert("supercode".to_string(), Value::from(country.as_str()));
+                data.insert("subcode".to_string(), Value::from(code.as_str()));
+                link_subdivision(&mut data);
+                if data.contains_key("lat") || data.contains_key("lon") {
+                    let mut degrees = |axis: &'static str| {
+                        let value = column(&data, axis)?;
+                        data.remove(axis);
+                        value
+                            .parse::<f64>()
+                            .map_err(|_| (Some(axis), format!("{axis} is not a number: {value}")))
+                    };
+                    let (lat, lon) = (degrees("lat")?, degrees("lon")?);
+                    data.insert("c".to_string(), Value::from(locode_coordinates(lat, lon)));
+                }
+                (POSTAL_ENCODING, format!("{country}:{code}"))
+            }
+            CsvTable::Locode => unreachable!("the code list is read as CsvLocode rows"),
+        };
+        let entry = json!({ "<c>": encoding, "i": id, "d": data });
+        Ok((id, entry))
+    }
+}
+
+// Link a location to the subdivision of its state given in the
+// "subdivision" column, as "GB-SCT" or just "SCT"
+fn link_subdivision(data: &mut Map<String, Value>) {
+    if let Some(subdivision) = data.remove("subdivision") {
+        let subdivision = subdivision.as_str().unwrap_or_default();
+        let subcode = subdivision.rsplit(['-', ':']).next().unwrap_or_default();
+        data.insert("subdivision_code".to_string(), Value::from(subcode));
+    }
+}
+
+// Coordinates in degrees as the code list writes them, such as
+// "5130N 00008W", which is what berlin-core parses for generic locations.
+// They are kept to the nearest minute.
+fn locode_coordinates(lat: f64, lon: f64) -> String {
+    let minutes = |degrees: f64| (degrees.abs() * 60.0).round() as u32;
+    let (lat_min, lon_min) = (minutes(lat), minutes(lon));
+    format!(
+        "{:02}{:02}{} {:03}{:02}{}",
+        lat_min / 60,
+        lat_min % 60,
+        if lat < 0.0 { 'S' } else { 'N' },
+        lon_min / 60,
+        lon_min % 60,
+        if lon < 0.0 { 'W' } else { 'E' },
+    )
+}
+
+fn locode_entry(row: &CsvLocode) -> (String, Value) {
+    let id = format!("{}:{}", row.country.trim(), row.subcode.trim());
+    let mut data = json!({
+        "name": row.name.trim(),
+        "supercode": row.country.trim(),
+        "subcode": row.subcode.trim(),
+        "function_code": row.function.trim(),
+    });
+    if !row.subdivision_code.trim().is_empty() {
+        data["subdivision_code"] = Value::from(row.subdivision_code.trim());
+    }
+    let entry = json!({ "<c>": "UN-LOCODE", "i": id, "d": data });
+    (id, entry)
+}
+
+// Remove the airports whose elevation berlin-core would fail to parse,
+// returning their ids
+fn invalid_elevations(block: &mut Value) -> Vec<String> {
+    let Some(entries) = block.as_object_mut() else {
+        return vec![];
+    };
+    let invalid = entries
+        .iter()
+        .filter(|(_, entry)| entry.get("<c>").and_then(Value::as_str) == Some("IATA"))
+        .filter(|(_, entry)| match entry.pointer("/d/elevation") {
+            Some(Value::String(elevation)) => elevation.parse::<i16>().is_err(),
+            _ => false,
+        })
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+    invalid.iter().for_each(|id| {
+        entries.remove(id);
+    });
+    invalid
+}
+
+// The key of a location in `db`, or an error if there is none
+fn existing_key(db: &BerlinDb, key: String) -> Result<Ustr, BuildError> {
+    Ustr::from_existing(&key)
+        .filter(|k| db.all.contains_key(k))
+        .ok_or(BuildError::UnknownKey(key))
+}
+
+/// Tag locations, from key to tags. Keys are checked before any location is
+/// tagged.
+pub fn apply_tags(db: &mut BerlinDb, tags: HashMap<String, Vec<String>>) -> Result<(), BuildError> {
+    let tags = tags
+        .into_iter()
+        .map(|(key, key_tags)| Ok((existing_key(db, key)?, key_tags)))
+        .collect::<Result<Vec<_>, BuildError>>()?;
+    for (key, key_tags) in tags {
+        key_tags.iter().for_each(|tag| db.add_tag(key, tag));
+    }
+    Ok(())
+}
+
+/// Set the importance of locations, from key to importance. Keys are
+/// checked before any importance is set.
+pub fn apply_importance(
+    db: &mut BerlinDb,
+    importance: HashMap<String, f64>,
+) -> Result<(), BuildError> {
+    let importance = importance
+        .into_iter()
+        .map(|(key, value)| Ok((existing_key(db, key)?, value)))
+        .collect::<Result<Vec<_>, BuildError>>()?;
+    for (key, value) in importance {
+        db.set_importance(key, value);
+    }
+    Ok(())
+}
+
+/// Set the external ids of locations, as from `read_external_ids`. Keys
+/// are checked before any ids are set.
+pub fn apply_external_ids(
+    db: &mut BerlinDb,
+    rows: Vec<(String, ExternalIds)>,
+) -> Result<(), BuildError> {
+    let rows = rows
+        .into_iter()
+        .map(|(key, ids)| Ok((existing_key(db, key)?, ids)))
+        .collect::<Result<Vec<_>, BuildError>>()?;
+    for (key, ids) in rows {
+        db.set_external_ids(key, ids);
+    }
+    Ok(())
+}
+
+/// Assign the integer ids recorded in the file at `path`, from key to id,
+/// as `BerlinDb::restore_int_ids` does.
+pub fn apply_int_ids(
+    db: &mut BerlinDb,
+    path: &Path,
+    recorded: HashMap<String, u32>,
+) -> Result<(), BuildError> {
+    db.restore_int_ids(recorded)
+        .map_err(|int_id| BuildError::IntId(path.to_path_buf(), int_id))
+}
+
+/// Read a JSON file, reporting it by its path if it cannot be decoded.
+pub fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<T, BuildError> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| BuildError::Io(path.to_path_buf(), err))?;
+    serde_json::from_str(&contents).map_err(|err| {
+        BuildError::Json(vec![RecordError::new(
+            path.display().to_string(),
+            err.to_string(),
+        )])
+    })
+}
+
+fn read_csv<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, RecordError> {
+    let rows = csv::Reader::from_path(path)
+        .and_then(|mut reader| reader.deserialize::<T>().collect::<Result<Vec<_>, _>>());
+    rows.map_err(|err| RecordError::new(path.display().to_string(), err.to_string()))
+}
+
+#[derive(Deserialize)]
+struct ExternalIdsRow {
+    key: String,
+    geonames: Option<String>,
+    wikidata: Option<String>,
+}
+
+/// Read a crosswalk from location keys to their GeoNames IDs and Wikidata
+/// QIDs, as a TSV file with columns key, geonames and wikidata.
+pub fn read_external_ids(path: &Path) -> Result<Vec<(String, ExternalIds)>, BuildError> {
+    let rows = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .and_then(|mut reader| {
+            reader
+                .deserialize::<ExternalIdsRow>()
+                .collect::<Result<Vec<_>, _>>()
+        });
+    let rows = rows.map_err(|err| {
+        BuildError::Json(vec![RecordError::new(
+            path.display().to_string(),
+            err.to_string(),
+        )])
+    })?;
+    let ids = rows
+        .into_iter()
+        .map(|row| {
+            let ids = ExternalIds {
+                geonames: row.geonames,
+                wikidata: row.wikidata,
+            };
+            (row.key, ids)
+        })
+        .collect();
+    Ok(ids)
+}
+
+/// Names of a location as given in its source data, before folding.
+#[derive(Clone)]
+pub struct SourceNames {
+    key: Ustr,
+    name: Option<String>,
+    // (language, name) pairs from `name:<lang>` fields, which may hold
+    // several names separated by semicolons
+    alt_names: Vec<(String, String)>,
+    // the ISO 3166-1 numeric code of a state
+    numeric: Option<String>,
+}
+
+impl SourceNames {
+    pub fn from_data(key: Ustr, data: &Value) -> Self {
+        let name = data.get("name").and_then(|n| n.as_str()).map(String::from);
+        let alt_names = data
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(field, value)| Some((field.strip_prefix("name:")?, value.as_str()?)))
+            .flat_map(|(lang, names)| {
+                names
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(move |name| (lang.to_string(), name.to_string()))
+            })
+            .collect();
+        let numeric = data
+            .get("numeric")
+            .and_then(|n| n.as_str())
+            .map(String::from);
+        SourceNames {
+            key,
+            name,
+            alt_names,
+            numeric,
+        }
+    }
+
+    pub fn apply(self, db: &mut BerlinDb) {
+        if let Some(name) = self.name {
+            db.add_raw_name(self.key, &name);
+        }
+        for (lang, name) in self.alt_names {
+            db.add_alt_name(self.key, &lang, &name);
+        }
+        if let Some(numeric) = self.numeric {
+            db.add_numeric_code(self.key, &numeric);
+        }
+    }
+}
+
+/// Collect the source names of each location in the JSON blocks.
+fn source_names(blocks: &[(String, Value)]) -> Vec<SourceNames> {
+    blocks
+        .iter()
+        .filter_map(|(_, block)| block.as_object())
+        .flat_map(|obj| obj.values())
+        .filter_map(|entry| {
+            let encoding = entry.get("<c>")?.as_str()?;
+            let id = entry.get("i")?.as_str()?;
+            let key = Ustr::from(&format!("{}-{}", encoding, normalize(id)));
+            Some(SourceNames::from_data(key, entry.get("d")?))
+        })
+        .collect()
+}
+
+// What berlin-core reads from the code list, but does not keep
+struct CodeListExtras {
+    coordinates: Vec<(Ustr, Coordinates)>,
+    iata_codes: Vec<(Ustr, String)>,
+    statuses: Vec<(Ustr, LocodeStatus)>,
+}
+
+impl CodeListExtras {
+    fn from_rows(rows: &[CsvLocode]) -> Self {
+        CodeListExtras {
+            coordinates: rows
+                .iter()
+                .filter_map(|row| row.parse_coordinates().map(|c| (row.key(), c)))
+                .collect(),
+            iata_codes: rows
+                .iter()
+                .filter(|row| !row.iata_code.trim().is_empty())
+                .map(|row| (row.key(), normalize(row.iata_code.trim())))
+                .collect(),
+            statuses: rows
+                .iter()
+                .map(|row| {
+                    let status = LocodeStatus {
+                        status: row.status.trim().to_string(),
+                        date: row.date.trim().to_string(),
+                    };
+                    (row.key(), status)
+                })
+                .collect(),
+        }
+    }
+
+    fn apply(self, db: &mut BerlinDb) {
+        db.load_coordinates(self.coordinates);
+        db.link_airports(self.iata_codes);
+        db.load_locode_statuses(self.statuses);
+    }
+}
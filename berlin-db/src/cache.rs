@@ -0,0 +1,120 @@
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use berlin_core::search::{Score, SearchTerm};
+use berlin_core::ustr::Ustr;
+use lru::LruCache;
+
+use crate::search::SearchOptions;
+
+/// What decides the results of a search: the normalized query and
+/// everything that filters or ranks its matches.
+#[derive(PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    normalized: String,
+    stop_words: Vec<Ustr>,
+    state_filter: Option<Ustr>,
+    limit: usize,
+    lev_dist: u32,
+    candidate_limit: Option<usize>,
+    tags: Vec<String>,
+    phonetic: bool,
+    infix: bool,
+    strict: Option<i64>,
+    context: Vec<String>,
+    continent: Option<String>,
+    graph: (bool, i64, [u64; 4], bool),
+}
+
+impl CacheKey {
+    pub fn new(st: &SearchTerm, opts: &SearchOptions) -> Self {
+        CacheKey {
+            normalized: st.normalized.clone(),
+            stop_words: st.matches.stop_words.clone(),
+            state_filter: st.state_filter,
+            limit: st.limit,
+            lev_dist: st.lev_dist,
+            candidate_limit: opts.candidate_limit,
+            tags: opts.tags.clone(),
+            phonetic: opts.phonetic,
+            infix: opts.infix,
+            strict: opts.strict,
+            context: opts.context.clone(),
+            continent: opts.continent.clone(),
+            graph: opts.graph.key(),
+        }
+    }
+}
+
+/// The results of recent searches, least recently used first out, each
+/// kept for at most `ttl`. Results from before a change to the database
+/// are never returned.
+pub struct ResultCache {
+    entries: LruCache<CacheKey, (Instant, Vec<(Ustr, Score)>)>,
+    ttl: Option<Duration>,
+    // the revision of the database the entries were found in
+    revision: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ResultCache {
+    pub fn new(capacity: NonZeroUsize, ttl: Option<Duration>) -> Self {
+        ResultCache {
+            entries: LruCache::new(capacity),
+            ttl,
+            revision: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// An empty cache of the same capacity and time to live.
+    pub fn emptied(&self) -> Self {
+        ResultCache::new(self.entries.cap(), self.ttl)
+    }
+
+    pub fn get(&mut self, key: &CacheKey, revision: u64) -> Option<Vec<(Ustr, Score)>> {
+        if revision != self.revision {
+            self.entries.clear();
+            self.revision = revision;
+        }
+        let fresh = match self.entries.get(key) {
+            Some((added, _)) => self.ttl.is_none_or(|ttl| added.elapsed() < ttl),
+            None => false,
+        };
+        match fresh {
+            true => {
+                self.hits += 1;
+                self.entries.get(key).map(|(_, res)| res.clone())
+            }
+            false => {
+                self.misses += 1;
+                self.entries.pop(key);
+                None
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: CacheKey, revision: u64, res: Vec<(Ustr, Score)>) {
+        if revision == self.revision {
+            self.entries.put(key, (Instant::now(), res));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.cap().get()
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
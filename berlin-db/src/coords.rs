@@ -0,0 +1,151 @@
+use berlin_core::coordinates::Coordinates;
+use berlin_core::ustr::{Ustr, UstrMap};
+
+// A country's rough bounding box is only estimated from this many points
+const BOX_MIN_POINTS: usize = 5;
+// The box extends its points' interquartile range on each side by this many
+// times that range, or by at least BOX_MARGIN_DEGREES for compact countries
+const BOX_IQR_FACTOR: f64 = 3.0;
+const BOX_MARGIN_DEGREES: f64 = 10.0;
+// The mean radius of the Earth
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// A point that validation corrected, dropped or flagged as doubtful.
+#[derive(Clone)]
+pub struct CoordinateIssue {
+    pub key: Ustr,
+    pub original: Coordinates,
+    pub corrected: Option<Coordinates>,
+    pub dropped: bool,
+    pub reason: &'static str,
+}
+
+struct BoundingBox {
+    lat: (f64, f64),
+    lon: (f64, f64),
+}
+
+impl BoundingBox {
+    fn estimate(points: &[Coordinates]) -> Option<Self> {
+        if points.len() < BOX_MIN_POINTS {
+            return None;
+        }
+        let range = |mut values: Vec<f64>| {
+            values.sort_unstable_by(f64::total_cmp);
+            let n = values.len() - 1;
+            let (q1, q3) = (values[n / 4], values[n * 3 / 4]);
+            let margin = ((q3 - q1) * BOX_IQR_FACTOR).max(BOX_MARGIN_DEGREES);
+            (q1 - margin, q3 + margin)
+        };
+        Some(BoundingBox {
+            lat: range(points.iter().map(|c| c.lat).collect()),
+            lon: range(points.iter().map(|c| c.lon).collect()),
+        })
+    }
+
+    fn contains(&self, c: &Coordinates) -> bool {
+        (self.lat.0..=self.lat.1).contains(&c.lat) && (self.lon.0..=self.lon.1).contains(&c.lon)
+    }
+}
+
+/// The great-circle distance between two points, in kilometres, by the
+/// haversine formula.
+pub fn distance_km(a: &Coordinates, b: &Coordinates) -> f64 {
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.lon - a.lon).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+fn in_range(c: &Coordinates) -> bool {
+    (-90.0..=90.0).contains(&c.lat) && (-180.0..=180.0).contains(&c.lon)
+}
+
+// The usual mistakes in LOCODE coordinates: a wrong hemisphere letter, or
+// latitude and longitude given the wrong way round.
+fn corrections(c: &Coordinates) -> [(Coordinates, &'static str); 4] {
+    let (lat, lon) = (c.lat, c.lon);
+    [
+        (
+            Coordinates { lat: -lat, lon },
+            "latitude hemisphere flipped",
+        ),
+        (
+            Coordinates { lat, lon: -lon },
+            "longitude hemisphere flipped",
+        ),
+        (
+            Coordinates {
+                lat: -lat,
+                lon: -lon,
+            },
+            "both hemispheres flipped",
+        ),
+        (
+            Coordinates { lat: lon, lon: lat },
+            "latitude and longitude swapped",
+        ),
+    ]
+}
+
+/// Check points against the plausible ranges and the rough bounding box of
+/// their state, estimated from the state's points. Points out of range are
+/// dropped. Points outside the box are corrected if a usual mistake explains
+/// them, and otherwise kept but flagged, as remote territories fall outside
+/// too. Returns the points kept, along with every issue found.
+pub fn validate(
+    points: Vec<(Ustr, Ustr, Coordinates)>,
+) -> (UstrMap<Coordinates>, Vec<CoordinateIssue>) {
+    let mut issues = vec![];
+    let mut by_state: UstrMap<Vec<(Ustr, Coordinates)>> = UstrMap::default();
+    for (key, state, c) in points {
+        match in_range(&c) {
+            true => by_state.entry(state).or_default().push((key, c)),
+            false => issues.push(CoordinateIssue {
+                key,
+                original: c,
+                corrected: None,
+                dropped: true,
+                reason: "out of range",
+            }),
+        }
+    }
+    let mut valid = UstrMap::default();
+    for (_, points) in by_state {
+        let bbox = BoundingBox::estimate(&points.iter().map(|(_, c)| *c).collect::<Vec<_>>());
+        for (key, c) in points {
+            let Some(bbox) = bbox.as_ref().filter(|bbox| !bbox.contains(&c)) else {
+                valid.insert(key, c);
+                continue;
+            };
+            let correction = corrections(&c)
+                .into_iter()
+                .find(|(corrected, _)| in_range(corrected) && bbox.contains(corrected));
+            match correction {
+                Some((corrected, reason)) => {
+                    valid.insert(key, corrected);
+                    issues.push(CoordinateIssue {
+                        key,
+                        original: c,
+                        corrected: Some(corrected),
+                        dropped: false,
+                        reason,
+                    });
+                }
+                None => {
+                    valid.insert(key, c);
+                    issues.push(CoordinateIssue {
+                        key,
+                        original: c,
+                        corrected: None,
+                        dropped: false,
+                        reason: "outside the country's bounding box",
+                    });
+                }
+            }
+        }
+    }
+    issues.sort_unstable_by(|a, b| a.key.as_str().cmp(b.key.as_str()));
+    (valid, issues)
+}
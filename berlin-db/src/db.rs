@@ -0,0 +1,1960 @@
+use std::borrow::Cow;
+use std::cmp::{max, min, Reverse};
+use std::collections::HashMap;
+use std::io;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use berlin_core::coordinates::Coordinates;
+use berlin_core::location::{LocData, Location};
+use berlin_core::locations_db::LocationsDb;
+use berlin_core::normalize;
+use berlin_core::rayon::prelude::*;
+use berlin_core::search::{Offset, Score, SearchTerm};
+use berlin_core::ustr::{Ustr, UstrMap, UstrSet};
+use berlin_explain::{MatchTier, ScoreBreakdown};
+use fst::{Automaton, IntoStreamer, Streamer};
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::cache::{CacheKey, ResultCache};
+use crate::coords::{distance_km, validate, CoordinateIssue};
+use crate::disk_index::DiskIndex;
+use crate::infix::InfixIndex;
+use crate::language::{self, Profile};
+use crate::phonetic::phonetic_key;
+use crate::query::{parse_query, raw_score, Normalizer, Rewrite, RewriteRules, DEFAULT_STOP_WORDS};
+use crate::render::TextFields;
+use crate::search::SearchOptions;
+
+// The encodings berlin-core gives UN-LOCODEs, subdivisions and states
+const LOCODE_ENCODING: &str = "UN-LOCODE";
+const SUBDIVISION_ENCODING: &str = "ISO-3166-2";
+const STATE_ENCODING: &str = "ISO-3166-1";
+/// The encoding of Eurostat NUTS regions, which are kept as generic
+/// locations.
+pub const NUTS_ENCODING: &str = "NUTS";
+/// The encoding of US FIPS state and county codes, kept as generic
+/// locations.
+pub const FIPS_ENCODING: &str = "FIPS";
+/// The encoding of postal codes and their prefixes, kept as generic
+/// locations.
+pub const POSTAL_ENCODING: &str = "POSTAL";
+// Exact matches score as the core scores an exact name match
+const EXACT_SCORE_BASE: i64 = 1000;
+// Longest run of query words that will be tried as a single name
+const EXACT_MAX_SPAN_WORDS: usize = 6;
+// Alternate names are a weaker signal than the location's own names
+const ALT_NAME_PENALTY: i64 = 50;
+// A part of a hyphenated name, such as "trent" of "stoke-on-trent", or a
+// short word of a name, is weaker than the name itself
+const PART_PENALTY: i64 = 100;
+// Articles and prepositions within names, left out of the short words
+const NAME_PARTICLES: [&str; 24] = [
+    "de", "la", "le", "les", "du", "des", "da", "do", "dos", "das", "di", "del", "el", "los",
+    "las", "y", "e", "van", "von", "der", "den", "het", "ter", "al",
+];
+// Sounding alike is weaker still, and short codes match too much to be useful
+const PHONETIC_PENALTY: i64 = 100;
+const PHONETIC_MIN_CODE_LENGTH: usize = 3;
+// A name right after a word such as "near" is more likely a place
+const CONTEXT_BOOST: i64 = 50;
+/// What each tenfold increase in importance adds to a score, by default, so
+/// that a city of a million outranks a hamlet of a hundred of the same name.
+pub const DEFAULT_IMPORTANCE_WEIGHT: f64 = 10.0;
+// Documents are searched in overlapping windows of words. The overlap
+// covers the longest name span tried, so no name is cut off in every window.
+const TEXT_WINDOW_WORDS: usize = 16;
+const TEXT_WINDOW_OVERLAP: usize = EXACT_MAX_SPAN_WORDS;
+// Matching inside a name is weaker than matching the name itself
+const INFIX_PENALTY: i64 = 150;
+// Short prefixes match much of the index, so completions are ranked among
+// the first this many locations, in the index's alphabetical order
+const AUTOCOMPLETE_MAX_CANDIDATES: usize = 1000;
+
+/// The core database, along with the indexes that the Python bindings and
+/// command line maintain on top of it.
+/// It derefs to the core database only for reading; locations change
+/// through `insert` and `apply_changes`, which keep the indexes in step.
+pub struct BerlinDb {
+    db: LocationsDb,
+    // dense integer ids, assigned in key order so that the same data
+    // always produces the same ids
+    int_ids: UstrMap<u32>,
+    keys_by_int_id: Vec<Ustr>,
+    // lower-cased, but otherwise unfolded, names and codes
+    exact_index: HashMap<String, UstrSet>,
+    // normalized colloquial names registered by users
+    aliases: HashMap<String, UstrSet>,
+    // names in other languages, by key and by normalized name
+    alt_names: UstrMap<Vec<(Ustr, String)>>,
+    alt_index: HashMap<String, UstrSet>,
+    // hyphenated names and names with apostrophes, such as
+    // "stoke-on-trent", by their other spellings ("stoke on trent") and by
+    // their parts ("stoke", "trent")
+    compound_index: HashMap<String, UstrSet>,
+    part_index: HashMap<String, UstrSet>,
+    // words of two or three letters of longer names, such as "rye" of "rye
+    // harbour", which berlin-core leaves out of its index; only matched
+    // exactly, as they would match too much fuzzily
+    short_word_index: HashMap<String, UstrSet>,
+    // names by the Metaphone codes of their words
+    phonetic_index: HashMap<String, UstrSet>,
+    // trigrams of names, only built once a search asks for infix matches,
+    // along with the time that took
+    infix_index: OnceLock<(InfixIndex, Duration)>,
+    // names as they appeared in the source data, before folding
+    raw_names: UstrMap<String>,
+    // validated coordinates, and what validation changed
+    coordinates: UstrMap<Coordinates>,
+    coordinate_issues: Vec<CoordinateIssue>,
+    // the LOCODE of the place each airport serves
+    airport_locodes: UstrMap<Ustr>,
+    locode_statuses: UstrMap<LocodeStatus>,
+    // user-defined groupings, such as "eu" or "customer-port"
+    tags: UstrMap<Vec<String>>,
+    // populations, port sizes or other measures of prominence, and how
+    // much they count in ranking
+    importance: UstrMap<f64>,
+    importance_weight: f64,
+    // identifiers of locations in other gazetteers
+    external_ids: UstrMap<ExternalIds>,
+    // the continent of each state, by state code
+    continents: UstrMap<Ustr>,
+    // state keys by their ISO 3166-1 alpha-3 and numeric codes
+    country_codes: HashMap<String, Ustr>,
+    // the locations within each state, by state code
+    by_state: UstrMap<UstrSet>,
+    stop_words: Vec<String>,
+    normalizer: Arc<Normalizer>,
+    rewrite_rules: RewriteRules,
+    version: Option<DatasetVersion>,
+    // every encoding in the data, for keys given without one
+    encodings: Vec<Ustr>,
+    // how long the indexes took to build, for `capabilities`
+    fst_build_time: Duration,
+    exact_build_time: Duration,
+    phonetic_build_time: Duration,
+    revision: u64,
+    // recent search results, if caching is enabled
+    cache: Option<Mutex<ResultCache>>,
+    // the word index, if moved out of memory, in which case the core's is
+    // left empty
+    disk_index: Option<Arc<DiskIndex>>,
+}
+
+/// A change to the locations of a database.
+pub enum Change {
+    Insert(Location),
+    /// Remove a location, along with every location under it.
+    Remove(Ustr),
+}
+
+/// The level of the hierarchy that search results are grouped by.
+#[derive(Clone, Copy)]
+pub enum GroupBy {
+    State,
+    Subdiv,
+}
+
+/// What the code list says of a LOCODE, beyond what berlin-core keeps.
+#[derive(Clone)]
+pub struct LocodeStatus {
+    pub status: String,
+    pub date: String,
+}
+
+/// Identifiers of a location in other gazetteers, for joining results with
+/// linked data.
+#[derive(Clone, Default, Deserialize)]
+pub struct ExternalIds {
+    pub geonames: Option<String>,
+    pub wikidata: Option<String>,
+}
+
+/// The release of the gazetteer data a database was built from, for
+/// auditing which data tagged what.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct DatasetVersion {
+    pub version: String,
+    #[serde(default)]
+    pub date: Option<String>,
+}
+
+pub struct IndexStats {
+    pub words: usize,
+    pub average_postings: f64,
+    pub most_ambiguous: Vec<(String, usize)>,
+}
+
+pub struct Country {
+    pub key: Ustr,
+    pub alpha2: String,
+    pub alpha3: String,
+    pub name: String,
+}
+
+/// An index that searches use, and what it costs.
+pub struct IndexInfo {
+    pub name: &'static str,
+    /// Whether the index has been built, as some are built on first use.
+    pub present: bool,
+    pub entries: usize,
+    /// An estimate of the memory it takes.
+    pub bytes: usize,
+    pub build_time: Option<Duration>,
+    /// Whether it is in memory-mapped files rather than memory.
+    pub on_disk: bool,
+}
+
+impl BerlinDb {
+    /// Wrap a core database, building its FST and the indexes kept here.
+    pub fn new(db: LocationsDb) -> Self {
+        let started = Instant::now();
+        let db = db.mk_fst();
+        let fst_build_time = started.elapsed();
+        let mut keys = db.all.keys().cloned().collect::<Vec<_>>();
+        keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        let int_ids = keys
+            .iter()
+            .enumerate()
+            .map(|(n, key)| (*key, n as u32))
+            .collect::<UstrMap<_>>();
+        let mut berlin_db = BerlinDb {
+            db,
+            int_ids,
+            keys_by_int_id: keys,
+            exact_index: HashMap::new(),
+            aliases: HashMap::new(),
+            alt_names: UstrMap::default(),
+            alt_index: HashMap::new(),
+            compound_index: HashMap::new(),
+            part_index: HashMap::new(),
+            short_word_index: HashMap::new(),
+            phonetic_index: HashMap::new(),
+            infix_index: OnceLock::new(),
+            raw_names: UstrMap::default(),
+            coordinates: UstrMap::default(),
+            coordinate_issues: vec![],
+            airport_locodes: UstrMap::default(),
+            locode_statuses: UstrMap::default(),
+            tags: UstrMap::default(),
+            importance: UstrMap::default(),
+            importance_weight: DEFAULT_IMPORTANCE_WEIGHT,
+            external_ids: UstrMap::default(),
+            continents: UstrMap::default(),
+            country_codes: HashMap::new(),
+            by_state: UstrMap::default(),
+            stop_words: DEFAULT_STOP_WORDS.iter().map(|w| w.to_string()).collect(),
+            normalizer: Arc::default(),
+            rewrite_rules: RewriteRules::default(),
+            version: None,
+            encodings: vec![],
+            fst_build_time,
+            exact_build_time: Duration::ZERO,
+            phonetic_build_time: Duration::ZERO,
+            revision: 0,
+            cache: None,
+            disk_index: None,
+        };
+        let locs = berlin_db.db.all.values().cloned().collect::<Vec<_>>();
+        let started = Instant::now();
+        locs.iter().for_each(|loc| berlin_db.index_exact(loc));
+        locs.iter().for_each(|loc| berlin_db.index_compound(loc));
+        locs.iter().for_each(|loc| berlin_db.index_country(loc));
+        locs.iter().for_each(|loc| berlin_db.index_state(loc));
+        berlin_db.exact_build_time = started.elapsed();
+        let started = Instant::now();
+        locs.iter().for_each(|loc| berlin_db.index_phonetic(loc));
+        berlin_db.phonetic_build_time = started.elapsed();
+        berlin_db.encodings = locs
+            .iter()
+            .map(|loc| loc.encoding)
+            .collect::<UstrSet>()
+            .into_iter()
+            .collect();
+        berlin_db
+    }
+
+    fn index_exact(&mut self, loc: &Location) {
+        for term in loc.get_names().iter().chain(loc.get_codes().iter()) {
+            self.exact_index
+                .entry(term.to_string())
+                .or_default()
+                .insert(loc.key);
+        }
+    }
+
+    fn index_compound(&mut self, loc: &Location) {
+        for name in loc.get_names().iter() {
+            let (spellings, parts) = compound_forms(name);
+            for spelling in spellings {
+                self.compound_index
+                    .entry(spelling)
+                    .or_default()
+                    .insert(loc.key);
+            }
+            for part in parts {
+                self.part_index.entry(part).or_default().insert(loc.key);
+            }
+            for word in short_words(name) {
+                self.short_word_index
+                    .entry(word.to_string())
+                    .or_default()
+                    .insert(loc.key);
+            }
+        }
+    }
+
+    fn index_country(&mut self, loc: &Location) {
+        if let LocData::St(_) = loc.data {
+            let continent = data_field(loc, "St", "continent");
+            match continent.as_ref().and_then(|c| c.as_str()) {
+                Some(continent) => self.continents.insert(loc.get_state(), continent.into()),
+                None => self.continents.remove(&loc.get_state()),
+            };
+            let alpha3 = data_field(loc, "St", "alpha3");
+            if let Some(alpha3) = alpha3.as_ref().and_then(|c| c.as_str()) {
+                self.country_codes.insert(alpha3.to_string(), loc.key);
+            }
+        }
+    }
+
+    fn index_state(&mut self, loc: &Location) {
+        if !matches!(loc.data, LocData::St(_)) {
+            let keys = self.by_state.entry(loc.get_state()).or_default();
+            keys.insert(loc.key);
+        }
+    }
+
+    fn unindex_state(&mut self, loc: &Location) {
+        if let Some(keys) = self.by_state.get_mut(&loc.get_state()) {
+            keys.remove(&loc.key);
+        }
+    }
+
+    fn index_phonetic(&mut self, loc: &Location) {
+        for name in loc.get_names().iter() {
+            let code = phonetic_key(name);
+            if code.len() >= PHONETIC_MIN_CODE_LENGTH {
+                self.phonetic_index.entry(code).or_default().insert(loc.key);
+            }
+        }
+    }
+
+    fn unindex_exact(&mut self, loc: &Location) {
+        for term in loc.get_names().iter().chain(loc.get_codes().iter()) {
+            if let Some(keys) = self.exact_index.get_mut(term.as_str()) {
+                keys.remove(&loc.key);
+            }
+        }
+        for name in loc.get_names().iter() {
+            if let Some(keys) = self.phonetic_index.get_mut(&phonetic_key(name)) {
+                keys.remove(&loc.key);
+            }
+            let (spellings, parts) = compound_forms(name);
+            for spelling in spellings {
+                if let Some(keys) = self.compound_index.get_mut(&spelling) {
+                    keys.remove(&loc.key);
+                }
+            }
+            for part in parts {
+                if let Some(keys) = self.part_index.get_mut(&part) {
+                    keys.remove(&loc.key);
+                }
+            }
+            for word in short_words(name) {
+                if let Some(keys) = self.short_word_index.get_mut(word) {
+                    keys.remove(&loc.key);
+                }
+            }
+        }
+    }
+
+    /// The state or subdivision a location is under that is not in the
+    /// database, if any.
+    pub fn missing_parent(&self, loc: &Location) -> Option<Ustr> {
+        let (state, subdiv) = loc.get_parents();
+        [state, subdiv]
+            .into_iter()
+            .flatten()
+            .find(|key| !self.db.all.contains_key(key))
+    }
+
+    /// Insert a location, replacing any existing location with the same key,
+    /// and rebuild the search indexes to include it.
+    pub fn insert(&mut self, loc: Location) {
+        self.apply_changes(vec![Change::Insert(loc)]);
+    }
+
+    /// Apply changes in order, rebuilding the core indexes once at the end.
+    pub fn apply_changes(&mut self, changes: Vec<Change>) {
+        for change in changes {
+            match change {
+                Change::Insert(loc) => self.insert_location(loc),
+                Change::Remove(key) => self.remove_location(&key),
+            }
+        }
+        let started = Instant::now();
+        self.db = std::mem::take(&mut self.db).mk_fst();
+        // if the index cannot be written again, it stays in memory
+        if let Some(disk_index) = self.disk_index.take() {
+            let _ = self.move_index_to_disk(disk_index.dir());
+        }
+        self.fst_build_time = started.elapsed();
+        self.infix_index = OnceLock::new();
+        self.revision += 1;
+    }
+
+    /// Move the word FST and the locations under each word out of memory,
+    /// into files in `dir` that are memory-mapped. The files are rewritten
+    /// whenever changes are applied, and removed once no longer used.
+    pub fn move_index_to_disk(&mut self, dir: &Path) -> io::Result<()> {
+        let disk_index = DiskIndex::build(dir, &self.db, self.keys_by_int_id.clone())?;
+        self.db.fst = fst::Map::default();
+        self.db.by_word_map = UstrMap::default();
+        self.db.by_word_vec = vec![];
+        self.disk_index = Some(Arc::new(disk_index));
+        Ok(())
+    }
+
+    // Locations whose parents are missing are skipped, as berlin-core
+    // cannot index them
+    fn insert_location(&mut self, loc: Location) {
+        if self.missing_parent(&loc).is_some() {
+            return;
+        }
+        if !self.encodings.contains(&loc.encoding) {
+            self.encodings.push(loc.encoding);
+        }
+        if let Some(old) = self.db.all.get(&loc.key).cloned() {
+            self.unindex_exact(&old);
+            self.unindex_state(&old);
+            self.country_codes.retain(|_, key| *key != old.key);
+            let node_id = self.db.indices[&old.key];
+            node_id.remove(&mut self.db.arena);
+        }
+        if !self.int_ids.contains_key(&loc.key) {
+            self.int_ids
+                .insert(loc.key, self.keys_by_int_id.len() as u32);
+            self.keys_by_int_id.push(loc.key);
+        }
+        self.index_exact(&loc);
+        self.index_compound(&loc);
+        self.index_phonetic(&loc);
+        self.index_country(&loc);
+        self.index_state(&loc);
+        match data_coordinates(&loc) {
+            Some(c) => self.coordinates.insert(loc.key, c),
+            None => self.coordinates.remove(&loc.key),
+        };
+        self.db.insert(loc);
+    }
+
+    // Locations under the one removed would be left without a parent
+    fn remove_location(&mut self, key: &Ustr) {
+        let mut keys = self
+            .db
+            .all
+            .values()
+            .filter(|loc| {
+                let (state, subdiv) = loc.get_parents();
+                state == Some(*key) || subdiv == Some(*key)
+            })
+            .map(|loc| loc.key)
+            .collect::<Vec<_>>();
+        keys.push(*key);
+        for key in keys {
+            let Some(loc) = self.db.all.remove(&key) else {
+                continue;
+            };
+            self.unindex_exact(&loc);
+            self.unindex_state(&loc);
+            if let LocData::St(_) = loc.data {
+                self.continents.remove(&loc.get_state());
+            }
+            if let Some(node_id) = self.db.indices.remove(&key) {
+                node_id.remove(&mut self.db.arena);
+            }
+            self.db.state_by_code.retain(|_, k| *k != key);
+            self.country_codes.retain(|_, k| *k != key);
+            self.db.subdiv_by_code.retain(|_, k| *k != key);
+            // aliases, alternative and raw names add exact entries that
+            // unindex_exact cannot find from the location itself
+            for index in [
+                &mut self.aliases,
+                &mut self.alt_index,
+                &mut self.exact_index,
+            ] {
+                index.values_mut().for_each(|keys| {
+                    keys.remove(&key);
+                });
+            }
+            self.alt_names.remove(&key);
+            self.raw_names.remove(&key);
+            self.coordinates.remove(&key);
+            self.airport_locodes.remove(&key);
+            self.airport_locodes.retain(|_, locode| *locode != key);
+            self.locode_statuses.remove(&key);
+            self.tags.remove(&key);
+            self.importance.remove(&key);
+            self.external_ids.remove(&key);
+        }
+    }
+
+    /// A copy of the database, for changes to be applied to while this one
+    /// goes on serving queries. Its core indexes are only built once
+    /// changes are applied.
+    pub fn snapshot(&self) -> BerlinDb {
+        let mut db = LocationsDb::default();
+        self.db.all.values().for_each(|loc| db.insert(loc.clone()));
+        BerlinDb {
+            db,
+            int_ids: self.int_ids.clone(),
+            keys_by_int_id: self.keys_by_int_id.clone(),
+            exact_index: self.exact_index.clone(),
+            aliases: self.aliases.clone(),
+            alt_names: self.alt_names.clone(),
+            alt_index: self.alt_index.clone(),
+            compound_index: self.compound_index.clone(),
+            part_index: self.part_index.clone(),
+            short_word_index: self.short_word_index.clone(),
+            phonetic_index: self.phonetic_index.clone(),
+            infix_index: OnceLock::new(),
+            raw_names: self.raw_names.clone(),
+            coordinates: self.coordinates.clone(),
+            coordinate_issues: self.coordinate_issues.clone(),
+            airport_locodes: self.airport_locodes.clone(),
+            locode_statuses: self.locode_statuses.clone(),
+            tags: self.tags.clone(),
+            importance: self.importance.clone(),
+            importance_weight: self.importance_weight,
+            external_ids: self.external_ids.clone(),
+            continents: self.continents.clone(),
+            country_codes: self.country_codes.clone(),
+            by_state: self.by_state.clone(),
+            stop_words: self.stop_words.clone(),
+            normalizer: self.normalizer.clone(),
+            rewrite_rules: self.rewrite_rules.clone(),
+            version: self.version.clone(),
+            encodings: self.encodings.clone(),
+            fst_build_time: self.fst_build_time,
+            exact_build_time: self.exact_build_time,
+            phonetic_build_time: self.phonetic_build_time,
+            revision: self.revision,
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| Mutex::new(cache.lock().unwrap().emptied())),
+            disk_index: self.disk_index.clone(),
+        }
+    }
+
+    /// Counts changes, so that a copy can tell whether it is still current.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Index a state by its ISO 3166-1 numeric code, such as "826", which
+    /// berlin-core does not keep.
+    pub fn add_numeric_code(&mut self, key: Ustr, numeric: &str) {
+        let is_state = self
+            .db
+            .all
+            .get(&key)
+            .is_some_and(|loc| matches!(loc.data, LocData::St(_)));
+        if is_state {
+            self.revision += 1;
+            self.country_codes.insert(pad_numeric(numeric), key);
+        }
+    }
+
+    /// The key of a state by its ISO 3166-1 alpha-2, alpha-3 or numeric
+    /// code, in any case, as in "gb", "GBR" or "826".
+    pub fn resolve_country(&self, code: &str) -> Option<Ustr> {
+        let code = code.trim().to_lowercase();
+        match code.len() {
+            2 => Ustr::from_existing(&code)
+                .and_then(|code| self.db.state_by_code.get(&code).copied()),
+            _ if code.chars().all(|c| c.is_ascii_digit()) => {
+                self.country_codes.get(&pad_numeric(&code)).copied()
+            }
+            _ => self.country_codes.get(&code).copied(),
+        }
+    }
+
+    /// Replace the folded name of a location in the exact index with its
+    /// name as it appeared in the source data.
+    pub fn add_raw_name(&mut self, key: Ustr, raw_name: &str) {
+        self.revision += 1;
+        self.raw_names.insert(key, raw_name.to_string());
+        if let Some(keys) = self.exact_index.get_mut(&normalize(raw_name)) {
+            keys.remove(&key);
+        }
+        self.exact_index
+            .entry(raw_name.to_lowercase())
+            .or_default()
+            .insert(key);
+    }
+
+    /// Search for names or codes appearing verbatim (up to case) in the query,
+    /// without diacritic folding or fuzzy matching. Offsets refer to the
+    /// lower-cased query.
+    pub fn exact_search(
+        &self,
+        query: &str,
+        state_filter: Option<&str>,
+        limit: usize,
+        opts: &SearchOptions,
+    ) -> Vec<(Ustr, Score)> {
+        let lowered = query.to_lowercase();
+        let state_filter = state_filter.map(|sf| sf.to_lowercase());
+        let keep = |key: &Ustr| self.passes_filters(key, state_filter.as_deref(), opts);
+        let scores = self.match_spans(&lowered, &self.exact_index, None, 0, &keep);
+        let mut res = scores.into_iter().collect::<Vec<_>>();
+        res.sort_unstable_by_key(|(_, score)| std::cmp::Reverse(*score));
+        res.truncate(limit);
+        res
+    }
+
+    /// The search term for a query, dropping the stop words of the
+    /// language `profile` on top of the configured ones.
+    pub fn search_term(
+        &self,
+        raw: String,
+        state_filter: Option<String>,
+        limit: usize,
+        lev_dist: u32,
+        profile: Option<&Profile>,
+    ) -> SearchTerm {
+        let rewrite = self.rewrite(&raw);
+        self.rewritten_search_term(raw, state_filter, limit, lev_dist, profile, &rewrite)
+    }
+
+    /// Build a search term for `raw` as changed by `rewrite`, within its
+    /// state, if it forces one, in place of `state_filter`.
+    pub fn rewritten_search_term(
+        &self,
+        raw: String,
+        state_filter: Option<String>,
+        limit: usize,
+        lev_dist: u32,
+        profile: Option<&Profile>,
+        rewrite: &Rewrite,
+    ) -> SearchTerm {
+        let mut stop_words = Cow::Borrowed(&self.stop_words);
+        if profile.is_some() || !rewrite.drop.is_empty() {
+            let extra = profile
+                .into_iter()
+                .flat_map(Profile::stop_words)
+                .map(String::from);
+            let dropped = rewrite
+                .drop
+                .iter()
+                .map(|word| self.normalizer.text(word).trim().to_string());
+            stop_words.to_mut().extend(extra.chain(dropped));
+        }
+        parse_query(
+            raw,
+            rewrite.state.clone().or(state_filter),
+            limit,
+            lev_dist,
+            &stop_words,
+            &rewrite.normalizer(&self.normalizer),
+        )
+    }
+
+    pub fn stop_words(&self) -> &[String] {
+        &self.stop_words
+    }
+
+    pub fn set_stop_words(&mut self, stop_words: impl Iterator<Item = String>) {
+        self.revision += 1;
+        self.stop_words = stop_words.map(|w| normalize(w.trim())).collect();
+    }
+
+    /// Rewrite every query searched for by `rules`.
+    pub fn set_rewrite_rules(&mut self, rules: RewriteRules) {
+        self.revision += 1;
+        self.rewrite_rules = rules;
+    }
+
+    /// How the rewrite rules change `query`.
+    pub fn rewrite(&self, query: &str) -> Rewrite {
+        let normalized = self.normalizer.text(query);
+        let words = normalized.unicode_words().collect::<Vec<_>>();
+        self.rewrite_rules.rewrite(&words, &self.normalizer)
+    }
+
+    pub fn normalizer(&self) -> &Arc<Normalizer> {
+        &self.normalizer
+    }
+
+    /// Replace the normalizer of queries and of the names this crate
+    /// indexes. The names berlin-core indexes must already have been
+    /// normalized by it, as `LocationsDbBuilder` does.
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.revision += 1;
+        self.normalizer = Arc::new(normalizer);
+    }
+
+    /// Expand the word `short` to `long` in queries, and in names indexed
+    /// from now on. Names already indexed keep their form.
+    pub fn add_abbreviation(&mut self, short: &str, long: &str) {
+        self.revision += 1;
+        Arc::make_mut(&mut self.normalizer).add_expansion(short, long);
+    }
+
+    /// Search for the term, from the cache of recent results if enabled.
+    /// Timed searches, and those recording parent boosts, always run in
+    /// full.
+    pub fn search(&self, st: &SearchTerm, opts: &SearchOptions) -> Vec<(Ustr, Score)> {
+        let cache = match &self.cache {
+            Some(cache) if opts.timings.is_none() && opts.parent_boosts.is_none() => cache,
+            _ => return self.search_uncached(st, opts),
+        };
+        let key = CacheKey::new(st, opts);
+        if let Some(res) = cache.lock().unwrap().get(&key, self.revision) {
+            return res;
+        }
+        let res = self.search_uncached(st, opts);
+        cache.lock().unwrap().put(key, self.revision, res.clone());
+        res
+    }
+
+    /// Keep the results of up to `capacity` recent searches, each for at
+    /// most `ttl`, or stop caching if `capacity` is zero. Any cached results
+    /// are dropped.
+    pub fn set_cache(&mut self, capacity: usize, ttl: Option<Duration>) {
+        self.cache =
+            NonZeroUsize::new(capacity).map(|capacity| Mutex::new(ResultCache::new(capacity, ttl)));
+    }
+
+    /// Run `f` on the result cache, if enabled.
+    pub fn with_cache<T>(&self, f: impl FnOnce(&ResultCache) -> T) -> Option<T> {
+        self.cache.as_ref().map(|cache| f(&cache.lock().unwrap()))
+    }
+
+    // Search the core indexes, adding any registered aliases found in the
+    // normalized query as exact name matches
+    fn search_uncached(&self, st: &SearchTerm, opts: &SearchOptions) -> Vec<(Ustr, Score)> {
+        let state_filter = st.state_filter.map(|sf| sf.to_string());
+        let keep = |key: &Ustr| self.passes_filters(key, state_filter.as_deref(), opts);
+        let mut res = crate::search::search(&self.db, self.disk_index.as_deref(), st, opts, &keep);
+        let extra_matching = opts.phonetic || opts.infix;
+        let indexes = [
+            (&self.aliases, 0),
+            (&self.compound_index, 0),
+            (&self.alt_index, ALT_NAME_PENALTY),
+            (&self.part_index, PART_PENALTY),
+            (&self.short_word_index, PART_PENALTY),
+        ];
+        if indexes.iter().all(|(index, _)| index.is_empty()) && !extra_matching {
+            return self.rank(st, opts, res);
+        }
+        let text = &st.normalized;
+        let mut scores = UstrMap::default();
+        let span_scores = indexes
+            .into_iter()
+            .flat_map(|(index, penalty)| self.match_spans(text, index, None, penalty, &keep));
+        let phonetic_scores = match opts.phonetic {
+            true => self.match_spans(
+                text,
+                &self.phonetic_index,
+                Some(phonetic_key),
+                PHONETIC_PENALTY,
+                &keep,
+            ),
+            false => UstrMap::default(),
+        };
+        let infix_scores = match opts.infix {
+            true => self.match_infix(text, &keep),
+            false => UstrMap::default(),
+        };
+        for (key, score) in span_scores
+            .chain(phonetic_scores)
+            .chain(infix_scores)
+            .chain(res.drain(..))
+        {
+            let best = scores.entry(key).or_insert(score);
+            *best = max(*best, score);
+        }
+        self.rank(st, opts, scores.into_iter().collect())
+    }
+
+    // Weak matches are dropped on the evidence of the match alone, before
+    // the boosts for context and importance
+    fn rank(
+        &self,
+        st: &SearchTerm,
+        opts: &SearchOptions,
+        mut res: Vec<(Ustr, Score)>,
+    ) -> Vec<(Ustr, Score)> {
+        let started = Instant::now();
+        self.drop_weak_matches(st, opts, &mut res);
+        boost_context(st, opts, &mut res);
+        if !self.importance.is_empty() {
+            for (key, score) in res.iter_mut() {
+                score.score += self.importance_boost(key);
+            }
+        }
+        res.sort_unstable_by_key(|(_, score)| std::cmp::Reverse(*score));
+        res.truncate(st.limit);
+        opts.record(started, |t| &mut t.sorting);
+        res
+    }
+
+    // With a strict search, results below the threshold that rest on a
+    // single word of the query, other than a word or code of the location
+    fn drop_weak_matches(
+        &self,
+        st: &SearchTerm,
+        opts: &SearchOptions,
+        res: &mut Vec<(Ustr, Score)>,
+    ) {
+        let Some(threshold) = opts.strict else {
+            return;
+        };
+        res.retain(|(key, score)| {
+            let Some(matched) = st.normalized.get(score.offset.start..score.offset.end) else {
+                return true;
+            };
+            let matched = matched.trim();
+            let single_word = !matched.is_empty() && !matched.contains(char::is_whitespace);
+            let loc = &self.db.all[key];
+            let known = loc.words.iter().any(|word| word.as_str() == matched)
+                || loc.get_codes().iter().any(|code| code.as_str() == matched);
+            score.score >= threshold || !single_word || known
+        });
+    }
+
+    // Whether a location satisfies the state filter and search options
+    fn passes_filters(&self, key: &Ustr, state_filter: Option<&str>, opts: &SearchOptions) -> bool {
+        let Some(loc) = self.db.all.get(key) else {
+            return false;
+        };
+        if let Some(sf) = state_filter {
+            if loc.get_state().as_str() != sf {
+                return false;
+            }
+        }
+        if let Some(continent) = &opts.continent {
+            if self.continent(&loc.get_state()) != Some(continent.as_str()) {
+                return false;
+            }
+        }
+        if !opts.tags.is_empty() {
+            let tags = self.tags(key);
+            if !opts.tags.iter().all(|tag| tags.contains(tag)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Score every run of words in the text that appears in the index as an
+    // exact match, keeping the best span for each location. If `encode` is
+    // given, spans are looked up by their encoding instead.
+    fn match_spans(
+        &self,
+        text: &str,
+        index: &HashMap<String, UstrSet>,
+        encode: Option<fn(&str) -> String>,
+        penalty: i64,
+        keep: &dyn Fn(&Ustr) -> bool,
+    ) -> UstrMap<Score> {
+        let words = text.unicode_word_indices().collect::<Vec<_>>();
+        let mut scores: UstrMap<Score> = UstrMap::default();
+        for (i, (start, _)) in words.iter().enumerate() {
+            for (end_start, end_word) in words.iter().skip(i).take(EXACT_MAX_SPAN_WORDS) {
+                let end = end_start + end_word.len();
+                let span = &text[*start..end];
+                let found = match encode {
+                    Some(encode) => index.get(&encode(span)),
+                    None => index.get(span),
+                };
+                let Some(keys) = found else {
+                    continue;
+                };
+                let score = Score {
+                    score: EXACT_SCORE_BASE + (end - start) as i64 - penalty,
+                    offset: Offset { start: *start, end },
+                };
+                for key in keys.iter().filter(|key| keep(key)) {
+                    let best = scores.entry(*key).or_insert(score);
+                    *best = max(*best, score);
+                }
+            }
+        }
+        scores
+    }
+
+    // Score every run of words in the text found inside a name, building the
+    // trigram index on first use.
+    fn match_infix(&self, text: &str, keep: &dyn Fn(&Ustr) -> bool) -> UstrMap<Score> {
+        let (index, _) = self.infix_index.get_or_init(|| {
+            let started = Instant::now();
+            let index = InfixIndex::build(self.db.all.values());
+            (index, started.elapsed())
+        });
+        let words = text.unicode_word_indices().collect::<Vec<_>>();
+        let mut scores: UstrMap<Score> = UstrMap::default();
+        for (i, (start, _)) in words.iter().enumerate() {
+            for (end_start, end_word) in words.iter().skip(i).take(EXACT_MAX_SPAN_WORDS) {
+                let end = end_start + end_word.len();
+                let score = Score {
+                    score: EXACT_SCORE_BASE + (end - start) as i64 - INFIX_PENALTY,
+                    offset: Offset { start: *start, end },
+                };
+                for key in index
+                    .find(&text[*start..end])
+                    .iter()
+                    .filter(|key| keep(key))
+                {
+                    let best = scores.entry(*key).or_insert(score);
+                    *best = max(*best, score);
+                }
+            }
+        }
+        scores
+    }
+
+    /// Every state, as its key, alpha-2 and alpha-3 codes and name, in
+    /// alpha-2 order. Names are as in the source data, where known.
+    pub fn countries(&self) -> Vec<Country> {
+        let mut countries = self
+            .db
+            .state_by_code
+            .values()
+            .filter_map(|key| {
+                let loc = self.db.all.get(key)?;
+                let data = serde_json::to_value(loc.data).ok()?;
+                let state = data.get("St")?;
+                let name = match self.raw_names.get(key) {
+                    Some(name) => name.clone(),
+                    None => state.get("name")?.as_str()?.to_string(),
+                };
+                Some(Country {
+                    key: *key,
+                    alpha2: state.get("alpha2")?.as_str()?.to_uppercase(),
+                    alpha3: state.get("alpha3")?.as_str()?.to_uppercase(),
+                    name,
+                })
+            })
+            .collect::<Vec<_>>();
+        countries.sort_unstable_by(|a, b| a.alpha2.cmp(&b.alpha2));
+        countries
+    }
+
+    /// The continent of a state, such as "eu", by its state code.
+    pub fn continent(&self, state: &Ustr) -> Option<&str> {
+        self.continents
+            .get(state)
+            .map(|continent| continent.as_str())
+    }
+
+    /// The keys of the states on a continent, such as "eu", in key order.
+    pub fn states_by_continent(&self, continent: &str) -> Vec<Ustr> {
+        let continent = continent.to_lowercase();
+        let mut keys = self
+            .continents
+            .iter()
+            .filter(|(_, c)| c.as_str() == continent)
+            .filter_map(|(state, _)| self.db.state_by_code.get(state).copied())
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        keys
+    }
+
+    pub fn version(&self) -> Option<&DatasetVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn set_version(&mut self, version: Option<DatasetVersion>) {
+        self.version = version;
+    }
+
+    pub fn external_ids(&self, key: &Ustr) -> Option<&ExternalIds> {
+        self.external_ids.get(key)
+    }
+
+    pub fn set_external_ids(&mut self, key: Ustr, ids: ExternalIds) {
+        self.revision += 1;
+        self.external_ids.insert(key, ids);
+    }
+
+    /// The keys of the subdivisions of a state, by its state code, in key
+    /// order.
+    pub fn subdivisions_of(&self, state: &Ustr) -> Vec<Ustr> {
+        self.in_state(state, |loc| matches!(loc.data, LocData::Subdv(_)))
+    }
+
+    /// The keys of the LOCODEs of a state, by its state code, in key order,
+    /// only those in the subdivision with code `subdiv` if given.
+    pub fn locodes_of(&self, state: &Ustr, subdiv: Option<&Ustr>) -> Vec<Ustr> {
+        self.in_state(state, |loc| {
+            matches!(loc.data, LocData::Locd(_))
+                && subdiv.is_none_or(|subdiv| loc.get_subdiv().as_ref() == Some(subdiv))
+        })
+    }
+
+    fn in_state(&self, state: &Ustr, keep: impl Fn(&Location) -> bool) -> Vec<Ustr> {
+        let Some(keys) = self.by_state.get(state) else {
+            return vec![];
+        };
+        let mut keys = keys
+            .iter()
+            .filter(|key| self.db.all.get(key).is_some_and(&keep))
+            .copied()
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        keys
+    }
+
+    /// Validate the coordinates of every location, taking those of LOCODEs
+    /// from the code list, as berlin-core does not keep them.
+    pub fn load_coordinates(&mut self, locode_coordinates: Vec<(Ustr, Coordinates)>) {
+        self.revision += 1;
+        let points = self
+            .db
+            .all
+            .values()
+            .filter_map(|loc| data_coordinates(loc).map(|c| (loc.key, c)))
+            .chain(locode_coordinates)
+            .filter_map(|(key, c)| Some((key, self.db.all.get(&key)?.get_state(), c)))
+            .collect::<Vec<_>>();
+        (self.coordinates, self.coordinate_issues) = validate(points);
+    }
+
+    pub fn coordinates(&self, key: &Ustr) -> Option<Coordinates> {
+        self.coordinates.get(key).cloned()
+    }
+
+    /// The great-circle distance between two locations, in kilometres, if
+    /// both have coordinates.
+    pub fn distance(&self, a: &Ustr, b: &Ustr) -> Option<f64> {
+        Some(distance_km(
+            self.coordinates.get(a)?,
+            self.coordinates.get(b)?,
+        ))
+    }
+
+    pub fn load_locode_statuses(&mut self, statuses: Vec<(Ustr, LocodeStatus)>) {
+        self.revision += 1;
+        for (key, status) in statuses {
+            if self.db.all.contains_key(&key) {
+                self.locode_statuses.insert(key, status);
+            }
+        }
+    }
+
+    pub fn locode_status(&self, key: &Ustr) -> Option<&LocodeStatus> {
+        self.locode_statuses.get(key)
+    }
+
+    /// Coordinates that were corrected, dropped or flagged while loading.
+    pub fn coordinate_issues(&self) -> &[CoordinateIssue] {
+        &self.coordinate_issues
+    }
+
+    /// Link each airport to the LOCODE of the place it serves: the LOCODE
+    /// listing its IATA code in the code list, or else the LOCODE in its
+    /// country with the same code, as the code list only gives IATA codes
+    /// that differ.
+    pub fn link_airports(&mut self, locode_iata_codes: Vec<(Ustr, String)>) {
+        self.revision += 1;
+        let mut by_iata: UstrMap<Ustr> = UstrMap::default();
+        let mut overridden = UstrSet::default();
+        for (locode_key, iata) in locode_iata_codes {
+            let Some(locode) = self.db.all.get(&locode_key) else {
+                continue;
+            };
+            let state = locode.get_state();
+            by_iata.insert(Ustr::from(&format!("{state}:{iata}")), locode_key);
+            overridden.insert(locode_key);
+        }
+        self.airport_locodes = self
+            .db
+            .all
+            .values()
+            .filter(|loc| matches!(loc.data, LocData::Airp(_)))
+            .filter_map(|airport| {
+                let iata = airport.get_codes().into_iter().next()?;
+                let id = Ustr::from(&format!("{}:{iata}", airport.get_state()));
+                let locode_key = match by_iata.get(&id) {
+                    Some(key) => *key,
+                    None => Ustr::from_existing(&format!("{LOCODE_ENCODING}-{id}"))
+                        .filter(|key| self.db.all.contains_key(key))
+                        .filter(|key| !overridden.contains(key))?,
+                };
+                Some((airport.key, locode_key))
+            })
+            .collect();
+    }
+
+    /// Group results that represent the same place: an airport with the
+    /// LOCODE of the place it serves, or a location with a parent that
+    /// matched the same words. Each group is led by its best-ranked member,
+    /// and groups keep the order of the results.
+    pub fn group_results(&self, results: Vec<(Ustr, Score)>) -> Vec<Vec<(Ustr, Score)>> {
+        let ranks = results
+            .iter()
+            .enumerate()
+            .map(|(i, (key, _))| (*key, i))
+            .collect::<UstrMap<_>>();
+        let mut leaders = (0..results.len()).collect::<Vec<_>>();
+        fn leader(leaders: &[usize], mut i: usize) -> usize {
+            while leaders[i] != i {
+                i = leaders[i];
+            }
+            i
+        }
+        for (i, (key, score)) in results.iter().enumerate() {
+            let loc = self.db.all.get(key).expect("loc should be in db");
+            let airport_locode = self.airport_locodes.get(key).cloned();
+            let (state, subdiv) = loc.get_parents();
+            // an airport is always the place it serves, but a parent only
+            // if it matched the same words
+            for (j, linked) in [(airport_locode, true), (state, false), (subdiv, false)]
+                .into_iter()
+                .filter_map(|(other, linked)| Some((*ranks.get(&other?)?, linked)))
+            {
+                if linked || results[j].1.offset == score.offset {
+                    let (a, b) = (leader(&leaders, i), leader(&leaders, j));
+                    leaders[max(a, b)] = min(a, b);
+                }
+            }
+        }
+        let mut groups: Vec<Vec<(Ustr, Score)>> = vec![vec![]; results.len()];
+        for (i, result) in results.into_iter().enumerate() {
+            groups[leader(&leaders, i)].push(result);
+        }
+        groups.retain(|group| !group.is_empty());
+        groups
+    }
+
+    pub fn importance(&self, key: &Ustr) -> Option<f64> {
+        self.importance.get(key).copied()
+    }
+
+    pub fn set_importance(&mut self, key: Ustr, importance: f64) {
+        self.revision += 1;
+        self.importance.insert(key, importance);
+    }
+
+    pub fn importance_weight(&self) -> f64 {
+        self.importance_weight
+    }
+
+    pub fn set_importance_weight(&mut self, weight: f64) {
+        self.revision += 1;
+        self.importance_weight = weight;
+    }
+
+    // The score a location gains from its importance
+    fn importance_boost(&self, key: &Ustr) -> i64 {
+        match self.importance.get(key) {
+            Some(importance) => {
+                (self.importance_weight * (1.0 + importance.max(0.0)).log10()) as i64
+            }
+            None => 0,
+        }
+    }
+
+    pub fn tags(&self, key: &Ustr) -> &[String] {
+        self.tags.get(key).map_or(&[], |tags| tags.as_slice())
+    }
+
+    pub fn add_tag(&mut self, key: Ustr, tag: &str) {
+        self.revision += 1;
+        let tags = self.tags.entry(key).or_default();
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+
+    pub fn remove_tag(&mut self, key: &Ustr, tag: &str) {
+        self.revision += 1;
+        if let Some(tags) = self.tags.get_mut(key) {
+            tags.retain(|t| t != tag);
+        }
+    }
+
+    /// Explain the score a location gets for a search term, or `None` if
+    /// the search does not find it at all.
+    pub fn explain(
+        &self,
+        st: &SearchTerm,
+        opts: &SearchOptions,
+        key: &Ustr,
+    ) -> Option<ScoreBreakdown> {
+        let loc = self.db.all.get(key)?;
+        let state_filter = st.state_filter.map(|sf| sf.to_string());
+        let keep = |k: &Ustr| self.passes_filters(k, state_filter.as_deref(), opts);
+        if !keep(key) {
+            return None;
+        }
+        let text = &st.normalized;
+        let mut explanations = vec![];
+
+        // the graph boost depends on which parents matched too
+        let fuzzy = crate::search::search(&self.db, self.disk_index.as_deref(), st, opts, &keep)
+            .into_iter()
+            .find(|(k, _)| k == key);
+        if let (Some((_, score)), Some(base)) = (fuzzy, loc.search(st)) {
+            let explanation = ScoreBreakdown::new(MatchTier::Fuzzy, base.score)
+                .graph_boost(score.score - base.score)
+                .coverage(score.offset.start, score.offset.end, text.len());
+            explanations.push(explanation);
+        }
+
+        let only = |k: &Ustr| k == key;
+        let mut spans = vec![
+            (
+                MatchTier::Alias,
+                self.match_spans(text, &self.aliases, None, 0, &only),
+                0,
+            ),
+            (
+                MatchTier::Exact,
+                self.match_spans(text, &self.compound_index, None, 0, &only),
+                0,
+            ),
+            (
+                MatchTier::AltName,
+                self.match_spans(text, &self.alt_index, None, ALT_NAME_PENALTY, &only),
+                ALT_NAME_PENALTY,
+            ),
+            (
+                MatchTier::Infix,
+                self.match_spans(text, &self.part_index, None, PART_PENALTY, &only),
+                PART_PENALTY,
+            ),
+            (
+                MatchTier::Infix,
+                self.match_spans(text, &self.short_word_index, None, PART_PENALTY, &only),
+                PART_PENALTY,
+            ),
+        ];
+        if opts.phonetic {
+            let scores = self.match_spans(
+                text,
+                &self.phonetic_index,
+                Some(phonetic_key),
+                PHONETIC_PENALTY,
+                &only,
+            );
+            spans.push((MatchTier::Phonetic, scores, PHONETIC_PENALTY));
+        }
+        if opts.infix {
+            spans.push((
+                MatchTier::Infix,
+                self.match_infix(text, &only),
+                INFIX_PENALTY,
+            ));
+        }
+        for (tier, scores, penalty) in spans {
+            let Some(score) = scores.get(key) else {
+                continue;
+            };
+            let mut explanation = ScoreBreakdown::new(tier, EXACT_SCORE_BASE)
+                .boost(
+                    "matched length",
+                    (score.offset.end - score.offset.start) as i64,
+                )
+                .coverage(score.offset.start, score.offset.end, text.len());
+            if penalty > 0 {
+                explanation = explanation.penalty("weaker match", penalty);
+            }
+            explanations.push(explanation);
+        }
+        let importance = self.importance_boost(key);
+        explanations
+            .into_iter()
+            .max_by_key(|explanation| explanation.total)
+            .map(|explanation| match importance {
+                0 => explanation,
+                _ => explanation.boost("importance", importance),
+            })
+    }
+
+    /// Register an alternative name for a location. Unlike the location's
+    /// own names, it is not in the core word index, so it is only matched
+    /// exactly, after normalization, and never fuzzily.
+    pub fn add_alias(&mut self, key: Ustr, name: &str) {
+        self.revision += 1;
+        self.aliases
+            .entry(self.normalizer.text(name))
+            .or_default()
+            .insert(key);
+        self.exact_index
+            .entry(name.to_lowercase())
+            .or_default()
+            .insert(key);
+    }
+
+    /// Register a name for a location in another language. These are
+    /// matched exactly, scoring slightly below the location's own names.
+    pub fn add_alt_name(&mut self, key: Ustr, lang: &str, name: &str) {
+        self.revision += 1;
+        let lang = Ustr::from(&lang.to_lowercase());
+        self.alt_names
+            .entry(key)
+            .or_default()
+            .push((lang, name.to_string()));
+        // both the English name a name in another script is known by and
+        // its romanization, so "Москва" is found as "moscow" and "moskva"
+        for form in alt_forms(&self.normalizer, name) {
+            self.alt_index.entry(form).or_default().insert(key);
+        }
+        self.exact_index
+            .entry(name.to_lowercase())
+            .or_default()
+            .insert(key);
+    }
+
+    /// Register `native`, a name in another script, as a name of each
+    /// location named `name` in the data. The language is guessed from the
+    /// script. Returns the number of locations named.
+    pub fn add_transliteration(&mut self, native: &str, name: &str) -> usize {
+        let name = normalize(name);
+        let keys = self
+            .db
+            .all
+            .iter()
+            .filter(|(_, loc)| loc.get_names().iter().any(|n| n.as_str() == name))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        let lang = language::detect(native).map_or("und", |profile| profile.language);
+        for key in &keys {
+            self.add_alt_name(*key, lang, native);
+        }
+        keys.len()
+    }
+
+    pub fn alt_names(&self, key: &Ustr) -> &[(Ustr, String)] {
+        self.alt_names
+            .get(key)
+            .map_or(&[], |names| names.as_slice())
+    }
+
+    /// The language of the alternate name of this location that the text
+    /// contains at the offset, if that is what matched.
+    pub fn matched_language(&self, key: &Ustr, text: &str, offset: Offset) -> Option<Ustr> {
+        let matched = self.normalizer.text(text.get(offset.start..offset.end)?);
+        self.alt_names(key)
+            .iter()
+            .find(|(_, name)| alt_forms(&self.normalizer, name).contains(&matched))
+            .map(|(lang, _)| *lang)
+    }
+
+    /// Run each phrase of a corpus of known non-locations through search,
+    /// returning the best match for any phrase that scores above the
+    /// threshold.
+    pub fn false_positives(
+        &self,
+        corpus: &[String],
+        state_filter: Option<String>,
+        lev_dist: u32,
+        threshold: i64,
+    ) -> Vec<(String, Ustr, Score)> {
+        corpus
+            .par_iter()
+            .filter_map(|phrase| {
+                let st = self.search_term(phrase.clone(), state_filter.clone(), 1, lev_dist, None);
+                self.search(&st, &SearchOptions::default())
+                    .into_iter()
+                    .next()
+                    .filter(|(_, score)| score.score > threshold)
+                    .map(|(key, score)| {
+                        let (_, char_indices) = self.normalizer.with_offsets(phrase);
+                        (phrase.clone(), key, raw_score(&char_indices, score))
+                    })
+            })
+            .collect()
+    }
+
+    /// Find the locations mentioned in a long document, by searching
+    /// overlapping windows of it. Each location is returned once, with its
+    /// best-scoring mention, in order of mention; offsets are characters of
+    /// the document.
+    pub fn search_text(
+        &self,
+        document: &str,
+        state_filter: Option<String>,
+        window_limit: usize,
+        lev_dist: u32,
+        threshold: i64,
+    ) -> Vec<(Ustr, Score)> {
+        let words = document.unicode_word_indices().collect::<Vec<_>>();
+        let step = TEXT_WINDOW_WORDS - TEXT_WINDOW_OVERLAP;
+        let windows = (0..words.len())
+            .step_by(step)
+            .take_while(|i| *i == 0 || i + TEXT_WINDOW_OVERLAP < words.len())
+            .map(|i| {
+                let last = min(i + TEXT_WINDOW_WORDS, words.len()) - 1;
+                let (start, _) = words[i];
+                let (last_start, last_word) = words[last];
+                (start, last_start + last_word.len())
+            })
+            .collect::<Vec<_>>();
+        let mentions = windows
+            .par_iter()
+            .flat_map_iter(|(start, end)| {
+                let window = &document[*start..*end];
+                let window_chars = document[..*start].chars().count();
+                let (_, char_indices) = self.normalizer.with_offsets(window);
+                let st = self.search_term(
+                    window.to_string(),
+                    state_filter.clone(),
+                    window_limit,
+                    lev_dist,
+                    None,
+                );
+                self.search(&st, &SearchOptions::default())
+                    .into_iter()
+                    .filter(|(_, score)| score.score > threshold)
+                    .map(move |(key, score)| {
+                        let score = raw_score(&char_indices, score);
+                        let offset = Offset {
+                            start: window_chars + score.offset.start,
+                            end: window_chars + score.offset.end,
+                        };
+                        (key, Score { offset, ..score })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut best: UstrMap<Score> = UstrMap::default();
+        for (key, score) in mentions {
+            let current = best.entry(key).or_insert(score);
+            if (score.score, Reverse(score.offset)) > (current.score, Reverse(current.offset)) {
+                *current = score;
+            }
+        }
+        let mut res = best.into_iter().collect::<Vec<_>>();
+        res.sort_unstable_by_key(|(key, score)| (score.offset, Reverse(score.score), *key));
+        res
+    }
+
+    /// Locations with a name, word or code starting with the prefix, as
+    /// (key, name) pairs. Only the index is scanned, with no fuzzy matching,
+    /// so this is fast enough to run on each keystroke. States come first,
+    /// then subdivisions, then other locations, and shorter names first.
+    pub fn autocomplete(
+        &self,
+        prefix: &str,
+        state_filter: Option<&str>,
+        limit: usize,
+    ) -> Vec<(Ustr, String)> {
+        let prefix = self.normalizer.text(prefix);
+        let prefix = prefix.trim();
+        if prefix.is_empty() {
+            return vec![];
+        }
+        let mut candidates = UstrSet::default();
+        // whether there are enough candidates, once these are added
+        let mut add = |keys: &mut dyn Iterator<Item = Ustr>| {
+            candidates.extend(keys.filter(|key| match state_filter {
+                Some(state) => self.db.all[key].get_state() == state,
+                None => true,
+            }));
+            candidates.len() >= AUTOCOMPLETE_MAX_CANDIDATES
+        };
+        let automaton = fst::automaton::Str::new(prefix).starts_with();
+        match &self.disk_index {
+            None => {
+                let mut stream = self.db.fst.search(automaton).into_stream();
+                while let Some((_, n)) = stream.next() {
+                    let (_, keys) = &self.db.by_word_vec[n as usize];
+                    if add(&mut keys.iter().cloned()) {
+                        break;
+                    }
+                }
+            }
+            Some(disk_index) => {
+                let mut stream = disk_index.fst().search(automaton).into_stream();
+                while let Some((_, n)) = stream.next() {
+                    if add(&mut disk_index.postings(n)) {
+                        break;
+                    }
+                }
+            }
+        }
+        let mut completions = candidates
+            .into_iter()
+            .filter_map(|key| {
+                let loc = self.db.all.get(&key)?;
+                let level = match loc.data {
+                    LocData::St(_) => 0,
+                    LocData::Subdv(_) => 1,
+                    _ => 2,
+                };
+                // names that start with the prefix beat those where a
+                // later word or a code does
+                let starts = loc.get_names().iter().any(|name| name.starts_with(prefix));
+                let name = self.display_name(&key)?;
+                Some(((!starts, level, name.len()), key, name))
+            })
+            .collect::<Vec<_>>();
+        completions.sort_unstable_by(|a, b| (a.0, a.1.as_str()).cmp(&(b.0, b.1.as_str())));
+        completions
+            .into_iter()
+            .take(limit)
+            .map(|(_, key, name)| (key, name))
+            .collect()
+    }
+
+    /// The number of words in the core index, the mean number of locations
+    /// each word leads to, and the `top` words leading to the most.
+    pub fn index_stats(&self, top: usize) -> IndexStats {
+        let mut most_ambiguous = match &self.disk_index {
+            None => self
+                .db
+                .by_word_vec
+                .iter()
+                .map(|(word, keys)| (word.to_string(), keys.len()))
+                .collect::<Vec<_>>(),
+            Some(disk_index) => disk_index.word_counts(),
+        };
+        let words = most_ambiguous.len();
+        let postings = most_ambiguous.iter().map(|(_, n)| n).sum::<usize>();
+        most_ambiguous.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        most_ambiguous.truncate(top);
+        IndexStats {
+            words,
+            average_postings: match words {
+                0 => 0.0,
+                _ => postings as f64 / words as f64,
+            },
+            most_ambiguous,
+        }
+    }
+
+    /// The indexes searches use: the core FST, the exact and phonetic
+    /// indexes, and the infix index, which is built by the first search
+    /// that asks for infix matches.
+    pub fn capabilities(&self) -> Vec<IndexInfo> {
+        let set_map_bytes = |map: &HashMap<String, UstrSet>| {
+            map.iter()
+                .map(|(term, keys)| {
+                    size_of::<(String, UstrSet)>() + term.len() + keys.len() * size_of::<Ustr>()
+                })
+                .sum::<usize>()
+        };
+        let infix = self.infix_index.get();
+        vec![
+            IndexInfo {
+                name: "fst",
+                present: true,
+                entries: match &self.disk_index {
+                    Some(disk_index) => disk_index.fst().len(),
+                    None => self.db.fst.len(),
+                },
+                bytes: match &self.disk_index {
+                    Some(disk_index) => disk_index.bytes(),
+                    None => self.db.fst.as_fst().as_bytes().len(),
+                },
+                build_time: Some(self.fst_build_time),
+                on_disk: self.disk_index.is_some(),
+            },
+            IndexInfo {
+                name: "exact",
+                present: true,
+                entries: self.exact_index.len(),
+                bytes: set_map_bytes(&self.exact_index),
+                build_time: Some(self.exact_build_time),
+                on_disk: false,
+            },
+            IndexInfo {
+                name: "phonetic",
+                present: true,
+                entries: self.phonetic_index.len(),
+                bytes: set_map_bytes(&self.phonetic_index),
+                build_time: Some(self.phonetic_build_time),
+                on_disk: false,
+            },
+            IndexInfo {
+                name: "infix",
+                present: infix.is_some(),
+                entries: infix.map_or(0, |(index, _)| index.name_count()),
+                bytes: infix.map_or(0, |(index, _)| index.size_bytes()),
+                build_time: infix.map(|(_, build_time)| *build_time),
+                on_disk: false,
+            },
+        ]
+    }
+
+    /// Keys of the locations directly under `key`, in key order.
+    pub fn child_keys(&self, key: &Ustr) -> Vec<Ustr> {
+        let Some(node_id) = self.db.indices.get(key) else {
+            return vec![];
+        };
+        let mut keys = node_id
+            .children(&self.db.arena)
+            .filter_map(|node_id| self.db.arena.get(node_id))
+            .map(|node| *node.get())
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        keys
+    }
+
+    /// Keys of the locations under `key`, level by level, down to `depth`
+    /// levels below it, or to the bottom if `None`.
+    pub fn descendant_keys(&self, key: &Ustr, depth: Option<usize>) -> Vec<Ustr> {
+        let mut keys = vec![];
+        let mut level = vec![*key];
+        for _ in 0..depth.unwrap_or(usize::MAX) {
+            level = level.iter().flat_map(|key| self.child_keys(key)).collect();
+            if level.is_empty() {
+                break;
+            }
+            keys.extend(&level);
+        }
+        keys
+    }
+
+    /// Whether a location lies inside a state or subdivision, by the
+    /// hierarchy or, for a location placed in no subdivision of its state,
+    /// by the subdivision of the nearest location with coordinates.
+    pub fn is_within(&self, key: &Ustr, ancestor: &Ustr) -> bool {
+        let (Some(loc), Some(outer)) = (self.db.all.get(key), self.db.all.get(ancestor)) else {
+            return false;
+        };
+        if key == ancestor {
+            return false;
+        }
+        let (state, subdiv) = loc.get_parents();
+        let in_hierarchy = self.db.indices.get(key).is_some_and(|node_id| {
+            node_id
+                .ancestors(&self.db.arena)
+                .skip(1)
+                .filter_map(|node_id| self.db.arena.get(node_id))
+                .any(|node| node.get() == ancestor)
+        });
+        if in_hierarchy || state == Some(*ancestor) || subdiv == Some(*ancestor) {
+            return true;
+        }
+        let undecided = matches!(outer.data, LocData::Subdv(_))
+            && subdiv.is_none()
+            && outer.get_state() == loc.get_state();
+        undecided && self.nearest_subdivision(key) == Some(*ancestor)
+    }
+
+    // The subdivision of the nearest location in the same state that has
+    // coordinates and a subdivision
+    fn nearest_subdivision(&self, key: &Ustr) -> Option<Ustr> {
+        let c = self.coordinates.get(key)?;
+        let state = self.db.all.get(key)?.get_state();
+        self.by_state
+            .get(&state)?
+            .iter()
+            .filter(|other| *other != key)
+            .filter_map(|other| {
+                let subdiv = self.db.all.get(other)?.get_parents().1?;
+                Some((distance_km(c, self.coordinates.get(other)?), subdiv))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, subdiv)| subdiv)
+    }
+
+    /// Look up a location by key, as `LocationsDb::retrieve` does, but also
+    /// accepting keys as other systems write them. See `resolve_key`.
+    pub fn retrieve(&self, term: &str) -> Option<&Location> {
+        let key = self.resolve_key(term)?;
+        self.db.all.get(&key)
+    }
+
+    /// The key of the location a term refers to: a key in any case, or an
+    /// id with or without its encoding, with a space, hyphen or nothing in
+    /// place of the colon ("GB:LON", "gb lon", "GBLON"). Without an
+    /// encoding, UN-LOCODEs are preferred, or ISO 3166-2 subdivisions where
+    /// the id is hyphenated as in "GB-ABD".
+    pub fn resolve_key(&self, term: &str) -> Option<Ustr> {
+        let found =
+            |key: &str| Ustr::from_existing(key).filter(|key| self.db.all.contains_key(key));
+        if let Some(key) = found(term) {
+            return Some(key);
+        }
+        let term = term.trim().to_lowercase();
+        let prefixed = self.encodings.iter().find_map(|encoding| {
+            let id = term.strip_prefix(&encoding.to_lowercase())?;
+            Some((vec![*encoding], id.strip_prefix(['-', ' ', ':'])?))
+        });
+        let (encodings, id) = match prefixed {
+            Some((encodings, id)) => (encodings, id),
+            None => {
+                let preferred = match term.contains('-') {
+                    true => [SUBDIVISION_ENCODING, LOCODE_ENCODING, STATE_ENCODING],
+                    false => [LOCODE_ENCODING, SUBDIVISION_ENCODING, STATE_ENCODING],
+                };
+                let mut encodings = self.encodings.clone();
+                encodings.sort_unstable_by_key(|encoding| {
+                    let rank = preferred.iter().position(|p| encoding == p);
+                    (rank.unwrap_or(preferred.len()), encoding.as_str())
+                });
+                (encodings, term.as_str())
+            }
+        };
+        let parts = id
+            .split([':', ' ', '-', '_'])
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        let mut ids = vec![normalize(&parts.join(":"))];
+        // UN/LOCODEs are often written without a separator, as in "GBLON"
+        if let [id] = parts.as_slice() {
+            if id.len() == 5 && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+                ids.push(format!("{}:{}", &id[..2], &id[2..]));
+            }
+        }
+        encodings
+            .iter()
+            .flat_map(|encoding| ids.iter().map(move |id| format!("{encoding}-{id}")))
+            .find_map(|key| found(&key))
+            .or_else(|| self.resolve_country(&term))
+    }
+
+    /// The name of a location as in the source data, where known, or else
+    /// its first folded name.
+    pub fn display_name(&self, key: &Ustr) -> Option<String> {
+        match self.raw_names.get(key) {
+            Some(name) => Some(name.clone()),
+            None => Some(self.db.all.get(key)?.get_names().first()?.to_string()),
+        }
+    }
+
+    /// The values a text template can refer to, for the location `key`.
+    pub fn text_fields(&self, key: &Ustr) -> Option<TextFields> {
+        let loc = self.db.all.get(key)?;
+        let (state, subdiv) = loc.get_parents();
+        let name_of = |key: Option<Ustr>| {
+            key.and_then(|key| self.display_name(&key))
+                .unwrap_or_default()
+        };
+        Some(TextFields {
+            name: self.display_name(key)?,
+            subdiv: name_of(subdiv),
+            state: name_of(state),
+            encoding: loc.encoding.to_string(),
+            id: loc.id.to_string(),
+            key: key.to_string(),
+            code: loc.id.to_uppercase().replace(':', " "),
+            state_code: loc.get_state().to_uppercase(),
+            subdiv_code: loc
+                .get_subdiv()
+                .map(|sd| sd.to_uppercase())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The key of the group a location falls in: its state or subdivision,
+    /// or itself if it is one. None for locations without a subdivision.
+    pub fn group_key(&self, loc: &Location, by: GroupBy) -> Option<Ustr> {
+        let (state, subdiv) = loc.get_parents();
+        match (by, loc.data) {
+            (GroupBy::State, LocData::St(_)) | (GroupBy::Subdiv, LocData::Subdv(_)) => {
+                Some(loc.key)
+            }
+            (GroupBy::State, _) => state,
+            (GroupBy::Subdiv, _) => subdiv,
+        }
+    }
+
+    pub fn int_id(&self, key: &Ustr) -> Option<u32> {
+        // removed locations keep their ids, in case they are added back
+        self.int_ids
+            .get(key)
+            .filter(|_| self.db.all.contains_key(key))
+            .cloned()
+    }
+
+    pub fn key_by_int_id(&self, int_id: u32) -> Option<Ustr> {
+        self.keys_by_int_id.get(int_id as usize).cloned()
+    }
+
+    /// Every key given an integer id, including those of removed locations,
+    /// in order of their ids.
+    pub fn keys_by_int_id(&self) -> &[Ustr] {
+        &self.keys_by_int_id
+    }
+
+    /// Assign integer ids as recorded by `keys_by_int_id` on an earlier
+    /// load, so that locations keep their ids. Recorded keys not in the
+    /// database keep theirs too, and locations not recorded get new ids
+    /// after them, in key order. Returns the first id missing from, or
+    /// repeated in, the record, if the ids do not run from 0 without gaps.
+    pub fn restore_int_ids(&mut self, recorded: HashMap<String, u32>) -> Result<(), u32> {
+        let mut keys = vec![None; recorded.len()];
+        for (key, int_id) in recorded {
+            match keys.get_mut(int_id as usize) {
+                Some(slot @ None) => *slot = Some(Ustr::from(&key)),
+                _ => return Err(int_id),
+            }
+        }
+        if let Some(missing) = keys.iter().position(Option::is_none) {
+            return Err(missing as u32);
+        }
+        let mut keys = keys.into_iter().flatten().collect::<Vec<_>>();
+        let recorded = keys.iter().copied().collect::<UstrSet>();
+        let mut new_keys = self
+            .db
+            .all
+            .keys()
+            .filter(|key| !recorded.contains(key))
+            .copied()
+            .collect::<Vec<_>>();
+        new_keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        keys.extend(new_keys);
+        self.int_ids = keys
+            .iter()
+            .enumerate()
+            .map(|(n, key)| (*key, n as u32))
+            .collect();
+        self.keys_by_int_id = keys;
+        // the postings on disk refer to locations by their integer ids, so
+        // are written again from a rebuilt index
+        if let Some(disk_index) = self.disk_index.take() {
+            self.db = std::mem::take(&mut self.db).mk_fst();
+            let _ = self.move_index_to_disk(disk_index.dir());
+        }
+        Ok(())
+    }
+}
+
+// Boost results whose span follows one of the context words of the search
+fn boost_context(st: &SearchTerm, opts: &SearchOptions, res: &mut [(Ustr, Score)]) {
+    if opts.context.is_empty() {
+        return;
+    }
+    for (_, score) in res.iter_mut() {
+        let Some(before) = st.normalized.get(..score.offset.start) else {
+            continue;
+        };
+        let before = before.trim_end();
+        let follows_cue = opts.context.iter().any(|cue| {
+            before
+                .strip_suffix(cue.as_str())
+                .is_some_and(|rest| rest.chars().last().is_none_or(|c| !c.is_alphanumeric()))
+        });
+        if follows_cue {
+            score.score += CONTEXT_BOOST;
+        }
+    }
+}
+
+/// Up to `limit` separate locations mentioned in a query, from its ranked
+/// results: the best result, then the best whose span overlaps none already
+/// taken, and so on, returned in order of mention.
+pub fn mentions(results: Vec<(Ustr, Score)>, limit: usize) -> Vec<(Ustr, Score)> {
+    let mut taken: Vec<(Ustr, Score)> = vec![];
+    for (key, score) in results {
+        if taken.len() == limit {
+            break;
+        }
+        let overlaps = taken.iter().any(|(_, other)| {
+            score.offset.start < other.offset.end && other.offset.start < score.offset.end
+        });
+        if !overlaps {
+            taken.push((key, score));
+        }
+    }
+    taken.sort_unstable_by_key(|(_, score)| score.offset);
+    taken
+}
+
+// Numeric codes are three digits, though often written without leading
+// zeros, as in "36" for Australia
+fn pad_numeric(numeric: &str) -> String {
+    format!("{:0>3}", numeric.trim())
+}
+
+// The other spellings of a name joined by hyphens or apostrophes, with
+// them replaced by spaces or dropped, and its parts long enough for
+// berlin-core to have indexed, were they words of their own
+fn compound_forms(name: &str) -> (Vec<String>, Vec<String>) {
+    let joined = |c: char| c == '-' || c == '\'';
+    if !name.contains(joined) {
+        return (vec![], vec![]);
+    }
+    let mut spellings = vec![
+        name.replace('-', " ").replace('\'', ""),
+        name.replace(['-', '\''], " "),
+        name.replace('\'', ""),
+    ];
+    spellings.retain(|spelling| spelling != name);
+    spellings.sort_unstable();
+    spellings.dedup();
+    let parts = name
+        .split(' ')
+        .filter(|word| word.contains(joined))
+        .flat_map(|word| word.split(joined))
+        .filter(|part| part.len() > 3)
+        .map(String::from)
+        .collect();
+    (spellings, parts)
+}
+
+// The words of two or three letters of a name of more than one word, other
+// than stop words and the particles of names in many languages, which
+// would match far too many places
+fn short_words(name: &str) -> Vec<&str> {
+    let words = name.split(' ').collect::<Vec<_>>();
+    if words.len() < 2 {
+        return vec![];
+    }
+    words
+        .into_iter()
+        .filter(|word| (2..=3).contains(&word.len()))
+        .filter(|word| word.chars().all(char::is_alphanumeric))
+        .filter(|word| !DEFAULT_STOP_WORDS.contains(word) && !NAME_PARTICLES.contains(word))
+        .collect()
+}
+
+// The forms an alternate name is indexed under: as queries are normalized,
+// and as plainly romanized, where that differs
+fn alt_forms(normalizer: &Normalizer, name: &str) -> Vec<String> {
+    let mut forms = vec![normalizer.text(name), normalize(name)];
+    forms.dedup();
+    forms
+}
+
+/// The level of a NUTS region, from 1 to 3, as the number of characters
+/// of its code after the country.
+pub fn nuts_level(loc: &Location) -> Option<usize> {
+    match loc.data {
+        LocData::Gen(_) if loc.encoding == NUTS_ENCODING => Some(loc.id.len().saturating_sub(2)),
+        _ => None,
+    }
+}
+
+/// A field of the type-specific data of a location, such as `("St",
+/// "continent")`, read through serde as berlin-core keeps these private.
+pub fn data_field(loc: &Location, variant: &str, field: &str) -> Option<serde_json::Value> {
+    let data = serde_json::to_value(loc.data).ok()?;
+    data.get(variant)?
+        .get(field)
+        .filter(|v| !v.is_null())
+        .cloned()
+}
+
+// Coordinates given in a location's own data, which berlin-core keeps for
+// generic locations but does not expose
+fn data_coordinates(loc: &Location) -> Option<Coordinates> {
+    let data = serde_json::to_value(loc.data).ok()?;
+    let c = data.get("Gen")?.get("coordinates")?;
+    Some(Coordinates {
+        lat: c.get("lat")?.as_f64()?,
+        lon: c.get("lon")?.as_f64()?,
+    })
+}
+
+impl Deref for BerlinDb {
+    type Target = LocationsDb;
+
+    fn deref(&self) -> &LocationsDb {
+        &self.db
+    }
+}
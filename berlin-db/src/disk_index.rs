@@ -0,0 +1,133 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use berlin_core::locations_db::LocationsDb;
+use berlin_core::ustr::{Ustr, UstrMap, UstrSet};
+use fst::Streamer;
+use memmap2::Mmap;
+
+// Distinguishes the files of indexes built by one process, so that a
+// rebuild never overwrites a file another copy of the database has mapped
+static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+/// The word FST and the locations under each word, written to files and
+/// memory-mapped, so that the operating system can page them out. The
+/// files are removed when the index is dropped.
+pub struct DiskIndex {
+    fst: fst::Map<Mmap>,
+    // offsets of each word's postings, then the postings, as integer ids
+    // of locations in `keys`
+    postings: Mmap,
+    keys: Vec<Ustr>,
+    paths: [PathBuf; 2],
+}
+
+impl DiskIndex {
+    /// Write the word index of `db` to files in `dir`, and map them.
+    /// `keys` are the locations by their integer ids.
+    pub fn build(dir: &Path, db: &LocationsDb, keys: Vec<Ustr>) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+        let fst_path = dir.join(format!["words-{}-{}.fst", process::id(), build]);
+        let postings_path = dir.join(format!["postings-{}-{}.bin", process::id(), build]);
+        fs::write(&fst_path, db.fst.as_fst().as_bytes())?;
+
+        let int_ids = keys
+            .iter()
+            .enumerate()
+            .map(|(n, key)| (*key, n as u32))
+            .collect::<UstrMap<_>>();
+        let mut writer = BufWriter::new(File::create(&postings_path)?);
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_le_bytes())?;
+        for (_, locs) in db.by_word_vec.iter() {
+            offset += locs.len() as u64;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for (_, locs) in db.by_word_vec.iter() {
+            for key in locs {
+                writer.write_all(&int_ids[key].to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let index = DiskIndex {
+            fst: fst::Map::new(map_file(&fst_path)?).map_err(io::Error::other)?,
+            postings: map_file(&postings_path)?,
+            keys,
+            paths: [fst_path, postings_path],
+        };
+        Ok(index)
+    }
+
+    /// The directory the files are in.
+    pub fn dir(&self) -> &Path {
+        self.paths[0]
+            .parent()
+            .expect("index files are in a directory")
+    }
+
+    pub fn fst(&self) -> &fst::Map<Mmap> {
+        &self.fst
+    }
+
+    /// The locations under the word with the given value in the FST.
+    pub fn postings(&self, word: u64) -> impl Iterator<Item = Ustr> + '_ {
+        let base = (self.fst.len() + 1) * 8;
+        let (start, end) = (
+            base + self.offset(word) * 4,
+            base + self.offset(word + 1) * 4,
+        );
+        self.postings[start..end].chunks_exact(4).map(|id| {
+            let id = u32::from_le_bytes(id.try_into().unwrap());
+            self.keys[id as usize]
+        })
+    }
+
+    /// Each word with its number of locations.
+    pub fn word_counts(&self) -> Vec<(String, usize)> {
+        let mut counts = Vec::with_capacity(self.fst.len());
+        let mut stream = self.fst.stream();
+        while let Some((word, n)) = stream.next() {
+            let word = String::from_utf8_lossy(word).into_owned();
+            counts.push((word, self.offset(n + 1) - self.offset(n)));
+        }
+        counts
+    }
+
+    // where the postings of the word with the given value start, counted
+    // in postings
+    fn offset(&self, word: u64) -> usize {
+        let start = word as usize * 8;
+        u64::from_le_bytes(self.postings[start..start + 8].try_into().unwrap()) as usize
+    }
+
+    /// The locations under a word, if it is in the index.
+    pub fn get(&self, word: &str) -> Option<UstrSet> {
+        self.fst.get(word).map(|n| self.postings(n).collect())
+    }
+
+    /// The size of the mapped files.
+    pub fn bytes(&self) -> usize {
+        self.fst.as_fst().as_bytes().len() + self.postings.len()
+    }
+}
+
+impl Drop for DiskIndex {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn map_file(path: &Path) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the file is only ever written before it is mapped, under a
+    // name no other index uses
+    unsafe { Mmap::map(&file) }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use berlin_core::location::Location;
+use berlin_core::ustr::Ustr;
+
+/// Spans shorter than this match too many names to be worth looking up.
+pub const INFIX_MIN_LENGTH: usize = 4;
+
+/// A trigram index over location names, finding names that contain a
+/// string anywhere rather than only at the start of a word.
+pub struct InfixIndex {
+    names: Vec<(Ustr, String)>,
+    trigrams: HashMap<String, Vec<u32>>,
+}
+
+impl InfixIndex {
+    pub fn build<'a>(locs: impl Iterator<Item = &'a Location>) -> Self {
+        let mut index = InfixIndex {
+            names: vec![],
+            trigrams: HashMap::new(),
+        };
+        for loc in locs {
+            for name in loc.get_names().iter() {
+                let name = fold(name);
+                let n = index.names.len() as u32;
+                for trigram in trigrams(&name) {
+                    let postings = index.trigrams.entry(trigram).or_default();
+                    if postings.last() != Some(&n) {
+                        postings.push(n);
+                    }
+                }
+                index.names.push((loc.key, name));
+            }
+        }
+        index
+    }
+
+    /// The number of names indexed.
+    pub fn name_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// An estimate of the memory the index takes.
+    pub fn size_bytes(&self) -> usize {
+        let names = self
+            .names
+            .iter()
+            .map(|(_, name)| size_of::<(Ustr, String)>() + name.len())
+            .sum::<usize>();
+        let trigrams = self
+            .trigrams
+            .iter()
+            .map(|(trigram, postings)| {
+                size_of::<(String, Vec<u32>)>() + trigram.len() + postings.len() * size_of::<u32>()
+            })
+            .sum::<usize>();
+        names + trigrams
+    }
+
+    /// Keys of the locations with a name containing the text, once folded.
+    pub fn find(&self, text: &str) -> Vec<Ustr> {
+        let text = fold(text);
+        if text.chars().count() < INFIX_MIN_LENGTH {
+            return vec![];
+        }
+        // every trigram must occur, so checking the rarest one's names suffices
+        let rarest = trigrams(&text)
+            .map(|trigram| {
+                self.trigrams
+                    .get(&trigram)
+                    .map_or(&[][..], |p| p.as_slice())
+            })
+            .min_by_key(|postings| postings.len())
+            .unwrap_or_default();
+        let mut keys = rarest
+            .iter()
+            .map(|n| &self.names[*n as usize])
+            .filter(|(_, name)| name.contains(&text))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        keys.dedup();
+        keys
+    }
+}
+
+// Separators such as the hyphens in "stratford-upon-avon" become single
+// spaces, so that they match the words of a query.
+fn fold(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn trigrams(text: &str) -> impl Iterator<Item = String> + '_ {
+    let chars = text.chars().collect::<Vec<_>>();
+    (0..chars.len().saturating_sub(2)).map(move |i| chars[i..i + 3].iter().collect())
+}
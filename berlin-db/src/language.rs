@@ -0,0 +1,186 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use berlin_core::normalize;
+
+/// How queries in a language are normalized: on top of the configured stop
+/// words, the words of the language that carry no place name are dropped.
+/// Scripts other than Latin are already romanized by `normalize_text`.
+pub struct Profile {
+    pub language: &'static str,
+    // stop words, as normalized, which also tell the language apart
+    stop_words: &'static [&'static str],
+    // letters that suggest the language, in lower case
+    letters: &'static str,
+}
+
+impl Profile {
+    pub fn stop_words(&self) -> impl Iterator<Item = &'static str> {
+        self.stop_words.iter().copied()
+    }
+}
+
+// Each stop word found counts for less than a letter particular to the
+// language, as short stop words are shared between languages
+const STOP_WORD_WEIGHT: usize = 2;
+const LETTER_WEIGHT: usize = 3;
+
+const LATIN_PROFILES: [Profile; 7] = [
+    Profile {
+        language: "en",
+        stop_words: &[
+            "the", "in", "of", "near", "at", "to", "and", "from", "with", "for", "where", "is",
+            "are",
+        ],
+        letters: "",
+    },
+    Profile {
+        language: "de",
+        stop_words: &[
+            "der", "die", "das", "den", "dem", "des", "im", "und", "von", "vom", "bei", "beim",
+            "nach", "aus", "mit", "fur", "nahe", "zum", "zur", "am", "auf", "wo", "ist", "sind",
+        ],
+        letters: "äöüß",
+    },
+    Profile {
+        language: "fr",
+        stop_words: &[
+            "a", "au", "aux", "de", "des", "du", "la", "le", "les", "et", "en", "dans", "pres",
+            "sur", "pour", "chez", "ou", "est",
+        ],
+        letters: "àâçèéêëîïôœùûÿ",
+    },
+    Profile {
+        language: "es",
+        stop_words: &[
+            "en", "de", "del", "la", "el", "los", "las", "y", "cerca", "para", "por", "con",
+            "donde", "esta",
+        ],
+        letters: "ñ¿¡",
+    },
+    Profile {
+        language: "it",
+        stop_words: &[
+            "a", "in", "di", "del", "della", "dei", "il", "lo", "la", "gli", "e", "vicino", "per",
+            "con", "dove",
+        ],
+        letters: "ìò",
+    },
+    Profile {
+        language: "pt",
+        stop_words: &[
+            "em", "no", "na", "nos", "nas", "de", "do", "da", "dos", "das", "e", "perto", "para",
+            "com", "onde",
+        ],
+        letters: "ãõ",
+    },
+    Profile {
+        language: "nl",
+        stop_words: &[
+            "de", "het", "van", "en", "bij", "naar", "met", "voor", "een", "waar", "nabij", "te",
+        ],
+        letters: "ĳ",
+    },
+];
+
+// Languages told apart by their script alone
+const SCRIPT_PROFILES: [Profile; 7] = [
+    Profile {
+        language: "ja",
+        stop_words: &[],
+        letters: "",
+    },
+    Profile {
+        language: "ko",
+        stop_words: &[],
+        letters: "",
+    },
+    Profile {
+        language: "zh",
+        stop_words: &[],
+        letters: "",
+    },
+    Profile {
+        language: "ar",
+        stop_words: &[],
+        letters: "",
+    },
+    Profile {
+        language: "el",
+        stop_words: &[],
+        letters: "",
+    },
+    Profile {
+        language: "ru",
+        stop_words: &["v", "na", "iz", "do", "ot", "po", "u", "okolo", "gde"],
+        letters: "",
+    },
+    Profile {
+        language: "uk",
+        stop_words: &["v", "u", "na", "z", "iz", "do", "vid", "po", "bilia", "de"],
+        letters: "",
+    },
+];
+
+fn script_profile(language: &str) -> &'static Profile {
+    SCRIPT_PROFILES
+        .iter()
+        .find(|profile| profile.language == language)
+        .expect("script profiles cover every script detected")
+}
+
+/// The probable language of a query, from its script or, for Latin
+/// script, from its stop words and letters. None if nothing suggests one.
+pub fn detect(raw: &str) -> Option<&'static Profile> {
+    let (mut kana, mut hangul, mut han, mut arabic, mut greek, mut cyrillic, mut latin) =
+        (0, 0, 0, 0, 0, 0, 0);
+    let mut ukrainian = false;
+    for c in raw.chars().filter(|c| c.is_alphabetic()) {
+        match c {
+            '\u{3040}'..='\u{30ff}' => kana += 1,
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => hangul += 1,
+            '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' => han += 1,
+            '\u{0600}'..='\u{06ff}' | '\u{0750}'..='\u{077f}' => arabic += 1,
+            '\u{0370}'..='\u{03ff}' => greek += 1,
+            '\u{0400}'..='\u{04ff}' => {
+                cyrillic += 1;
+                ukrainian |= "іїєґІЇЄҐ".contains(c);
+            }
+            _ => latin += 1,
+        }
+    }
+    // kana mark Japanese even among more kanji
+    let scripts = [
+        (kana * 2 + han, if kana > 0 { "ja" } else { "zh" }),
+        (hangul, "ko"),
+        (arabic, "ar"),
+        (greek, "el"),
+        (cyrillic, if ukrainian { "uk" } else { "ru" }),
+    ];
+    let (count, language) = scripts.into_iter().max_by_key(|(count, _)| *count)?;
+    if count > 0 && count >= latin {
+        return Some(script_profile(language));
+    }
+
+    let lowercase = raw.to_lowercase();
+    let words = lowercase.unicode_words().map(normalize).collect::<Vec<_>>();
+    LATIN_PROFILES
+        .iter()
+        .map(|profile| {
+            let stop_words = words
+                .iter()
+                .filter(|word| profile.stop_words.contains(&word.as_str()))
+                .count();
+            let letters = lowercase
+                .chars()
+                .filter(|c| profile.letters.contains(*c))
+                .count();
+            (
+                stop_words * STOP_WORD_WEIGHT + letters * LETTER_WEIGHT,
+                profile,
+            )
+        })
+        .filter(|(score, _)| *score > 0)
+        // the first of equally likely languages, English before the rest
+        .min_by_key(|(score, _)| std::cmp::Reverse(*score))
+        .map(|(_, profile)| profile)
+}
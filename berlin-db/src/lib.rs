@@ -0,0 +1,17 @@
+//! The location database behind both the Python bindings and the `berlin`
+//! command line: loading data directories, searching and explaining
+//! scores, so that both give the same results for the same data.
+
+pub mod builder;
+pub mod cache;
+pub mod coords;
+pub mod db;
+pub mod disk_index;
+pub mod infix;
+pub mod language;
+pub mod phonetic;
+pub mod query;
+pub mod render;
+pub mod script;
+pub mod search;
+pub mod updater;
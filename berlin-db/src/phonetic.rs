@@ -0,0 +1,123 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The phonetic key of a run of words: the Metaphone code of each word,
+/// separated by spaces. Words without a code (no ASCII letters) are skipped.
+pub fn phonetic_key(text: &str) -> String {
+    text.unicode_words()
+        .map(metaphone)
+        .filter(|code| !code.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lawrence Philips' Metaphone code of a word, so that words that sound
+/// alike, such as "birmingham" and "burmyngum", share a code.
+pub fn metaphone(word: &str) -> String {
+    let mut chars = word
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    chars.dedup_by(|a, b| a == b && *a != 'C');
+    // initial letter groups that are pronounced differently
+    match chars.as_slice() {
+        ['A', 'E', ..] | ['G', 'N', ..] | ['K', 'N', ..] | ['P', 'N', ..] | ['W', 'R', ..] => {
+            chars.remove(0);
+        }
+        ['X', ..] => chars[0] = 'S',
+        ['W', 'H', ..] => {
+            chars.remove(1);
+        }
+        _ => {}
+    }
+
+    let at = |i: usize| chars.get(i).copied();
+    let is_vowel = |c: Option<char>| matches!(c, Some('A' | 'E' | 'I' | 'O' | 'U'));
+    let is_front = |c: Option<char>| matches!(c, Some('E' | 'I' | 'Y'));
+    let mut code = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).and_then(at);
+        let next = at(i + 1);
+        match c {
+            'A' | 'E' | 'I' | 'O' | 'U' => {
+                if i == 0 {
+                    code.push(c)
+                }
+            }
+            'B' => {
+                if !(prev == Some('M') && next.is_none()) {
+                    code.push('B')
+                }
+            }
+            'C' => {
+                if next == Some('I') && at(i + 2) == Some('A') {
+                    code.push('X')
+                } else if next == Some('H') {
+                    code.push(if prev == Some('S') { 'K' } else { 'X' })
+                } else if is_front(next) {
+                    if prev != Some('S') {
+                        code.push('S')
+                    }
+                } else {
+                    code.push('K')
+                }
+            }
+            'D' => {
+                if next == Some('G') && is_front(at(i + 2)) {
+                    code.push('J')
+                } else {
+                    code.push('T')
+                }
+            }
+            'G' => {
+                let silent = (next == Some('H') && at(i + 2).is_some() && !is_vowel(at(i + 2)))
+                    || (next == Some('N') && (at(i + 2).is_none() || chars[i + 2..] == ['E', 'D']))
+                    || (prev == Some('D') && is_front(next));
+                if !silent {
+                    code.push(if is_front(next) { 'J' } else { 'K' })
+                }
+            }
+            'H' => {
+                let after_modifier = matches!(prev, Some('C' | 'G' | 'P' | 'S' | 'T'));
+                let trailing = is_vowel(prev) && !is_vowel(next);
+                if !after_modifier && !trailing {
+                    code.push('H')
+                }
+            }
+            'K' => {
+                if prev != Some('C') {
+                    code.push('K')
+                }
+            }
+            'P' => code.push(if next == Some('H') { 'F' } else { 'P' }),
+            'Q' => code.push('K'),
+            'S' => {
+                if next == Some('H') || (next == Some('I') && matches!(at(i + 2), Some('O' | 'A')))
+                {
+                    code.push('X')
+                } else {
+                    code.push('S')
+                }
+            }
+            'T' => {
+                if next == Some('I') && matches!(at(i + 2), Some('O' | 'A')) {
+                    code.push('X')
+                } else if next == Some('H') {
+                    code.push('0')
+                } else if !(next == Some('C') && at(i + 2) == Some('H')) {
+                    code.push('T')
+                }
+            }
+            'V' => code.push('F'),
+            'W' | 'Y' => {
+                if is_vowel(next) {
+                    code.push(c)
+                }
+            }
+            'X' => code.push_str("KS"),
+            'Z' => code.push('S'),
+            _ => code.push(c),
+        }
+    }
+    code
+}
@@ -0,0 +1,440 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use berlin_core::normalize;
+use berlin_core::search::{Offset, Score, SearchTerm, SearchableStringSet};
+use berlin_core::ustr::Ustr;
+use serde::Deserialize;
+use serde_json::Value;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::script::{exonym_at, romanize_char, Script};
+
+/// The stop words berlin-core uses when none are configured.
+pub const DEFAULT_STOP_WORDS: [&str; 18] = [
+    "any", "all", "are", "is", "at", "to", "in", "on", "of", "for", "by", "and", "was", "did",
+    "the", "city", "that", "with",
+];
+
+/// Build a search term as `SearchTerm::from_raw_query` does, but with the
+/// given stop words in place of berlin-core's built-in list, and with each
+/// word, doublet and triplet spanning the words it was made from.
+pub fn parse_query(
+    raw: String,
+    state_filter: Option<String>,
+    limit: usize,
+    lev_dist: u32,
+    stop_words: &[String],
+    normalizer: &Normalizer,
+) -> SearchTerm {
+    inspect_query(raw, state_filter, limit, lev_dist, stop_words, normalizer).0
+}
+
+/// The terms a query was broken into, which berlin-core keeps to itself,
+/// with their spans in the normalized query.
+#[derive(Default)]
+pub struct QueryTerms {
+    pub exact: Vec<(String, Offset)>,
+    pub fuzzy: Vec<(String, Offset)>,
+    pub stop_words: Vec<String>,
+}
+
+impl QueryTerms {
+    // Add a term to the search term, noting whether berlin-core kept it as
+    // an exact or fuzzy match by the same rules as `SearchableStringSet::add`
+    fn add(&mut self, st: &mut SearchTerm, term: &str, masked: &String, allow_inexact: bool) {
+        st.matches.add(term, masked, allow_inexact);
+        let Some(start) = masked.find(term) else {
+            return;
+        };
+        let offset = Offset {
+            start,
+            end: start + term.len(),
+        };
+        match Ustr::from_existing(term) {
+            Some(u) if term.len() > 1 && !st.matches.stop_words.contains(&u) => {
+                self.exact.push((term.to_string(), offset))
+            }
+            None if allow_inexact && term.chars().count() < LEV_LENGTH_MAX => {
+                self.fuzzy.push((term.to_string(), offset))
+            }
+            _ => {}
+        }
+    }
+}
+
+// The longest term berlin-core matches fuzzily
+const LEV_LENGTH_MAX: usize = 40;
+
+/// Parse a query as `parse_query` does, also returning the terms it was
+/// broken into, to show why a query matches what it does.
+pub fn inspect_query(
+    raw: String,
+    state_filter: Option<String>,
+    limit: usize,
+    lev_dist: u32,
+    stop_words: &[String],
+    normalizer: &Normalizer,
+) -> (SearchTerm, QueryTerms) {
+    let mut terms = QueryTerms::default();
+    let is_stop_word = |w: &str| stop_words.iter().any(|sw| sw == w);
+    let normalized = normalizer.text(&raw);
+    let tokens = normalized
+        .unicode_word_indices()
+        .map(|(start, word)| Token {
+            start,
+            end: start + word.len(),
+        })
+        .collect::<Vec<_>>();
+    terms.stop_words = tokens
+        .iter()
+        .map(|token| normalized[token.start..token.end].to_string())
+        .filter(|w| is_stop_word(w))
+        .collect();
+    let query_stop_words: Vec<Ustr> = terms
+        .stop_words
+        .iter()
+        .filter_map(|w| Ustr::from_existing(w))
+        .collect();
+    let tokens = tokens
+        .into_iter()
+        .filter(|token| !is_stop_word(&normalized[token.start..token.end]))
+        .collect::<Vec<_>>();
+    let mut st = SearchTerm {
+        raw,
+        normalized: normalized.clone(),
+        state_filter: state_filter.and_then(|s| Ustr::from_existing(&s)),
+        lev_dist,
+        limit,
+        codes: vec![],
+        matches: SearchableStringSet::new(query_stop_words),
+    };
+    // whether each word is joined to the next by hyphens or apostrophes,
+    // with no space between, as in "saint-remy-de-provence"
+    let joined = tokens
+        .windows(2)
+        .map(|pair| !normalized[pair[0].end..pair[1].start].contains(char::is_whitespace))
+        .collect::<Vec<_>>();
+    let mut masked = normalized.clone();
+    for (i, token) in tokens.iter().enumerate() {
+        // berlin-core places each term at its first occurrence in the text
+        // it is given, so everything before the term is blanked out
+        let masked_to = tokens.get(i.wrapping_sub(1)).map_or(0, |prev| prev.start);
+        masked.replace_range(masked_to..token.start, &" ".repeat(token.start - masked_to));
+        // joined names too long for a triplet are a term of their own,
+        // as berlin-core indexes them as one word
+        if i == 0 || !joined[i - 1] {
+            let length = joined[i..].iter().take_while(|joined| **joined).count() + 1;
+            if length > 3 {
+                let last = &tokens[i + length - 1];
+                terms.add(&mut st, &normalized[token.start..last.end], &masked, false);
+            }
+        }
+        if let Some(next) = tokens.get(i + 1) {
+            let doublet = &normalized[token.start..next.end];
+            terms.add(&mut st, doublet, &masked, true);
+            if let Some(third) = tokens.get(i + 2) {
+                let triplet = &normalized[token.start..third.end];
+                terms.add(&mut st, triplet, &masked, false);
+            }
+        }
+        terms.add(&mut st, &normalized[token.start..token.end], &masked, true)
+    }
+    (st, terms)
+}
+
+/// Changes made to a query before it is parsed, by the rewrite rules of a
+/// database or a rewriting hook: words dropped as stop words, words
+/// expanded to synonyms as abbreviations are, and a state to search within.
+#[derive(Clone, Default)]
+pub struct Rewrite {
+    pub drop: Vec<String>,
+    pub synonyms: Vec<(String, String)>,
+    pub state: Option<String>,
+}
+
+impl Rewrite {
+    /// Make the changes of `other` too, with its state, if any, in place of
+    /// this one's.
+    pub fn extend(&mut self, other: Rewrite) {
+        self.drop.extend(other.drop);
+        self.synonyms.extend(other.synonyms);
+        self.state = other.state.or(self.state.take());
+    }
+
+    /// The normalizer for the rewritten query, expanding its synonyms.
+    pub fn normalizer<'a>(&self, normalizer: &'a Normalizer) -> Cow<'a, Normalizer> {
+        let mut normalizer = Cow::Borrowed(normalizer);
+        for (word, synonym) in &self.synonyms {
+            normalizer.to_mut().add_expansion(word, synonym);
+        }
+        normalizer
+    }
+}
+
+/// Rewrites of every query, as read from a rules file: words always
+/// dropped, words expanded to synonyms, and words that force a state, such
+/// as "scotland" to "gb".
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRules {
+    #[serde(default)]
+    pub drop: Vec<String>,
+    #[serde(default)]
+    pub synonyms: HashMap<String, String>,
+    #[serde(default)]
+    pub states: HashMap<String, String>,
+}
+
+impl RewriteRules {
+    /// How a query is rewritten, given its normalized words.
+    pub fn rewrite(&self, words: &[&str], normalizer: &Normalizer) -> Rewrite {
+        let state = self
+            .states
+            .iter()
+            .find(|(word, _)| words.contains(&normalizer.text(word).trim()))
+            .map(|(_, state)| state.to_lowercase());
+        Rewrite {
+            drop: self.drop.clone(),
+            synonyms: self
+                .synonyms
+                .iter()
+                .map(|(word, synonym)| (word.clone(), synonym.clone()))
+                .collect(),
+            state,
+        }
+    }
+}
+
+// The byte span of a word of the normalized query
+struct Token {
+    start: usize,
+    end: usize,
+}
+
+/// Abbreviations expanded by every normalizer, so that "St Albans" and "Mt
+/// Gambier" meet "Saint Albans" and "Mount Gambier" in the data. Single
+/// letters only stand for directions when followed by ".".
+pub const ABBREVIATIONS: [(&str, &str); 10] = [
+    ("st", "saint"),
+    ("ste", "sainte"),
+    ("mt", "mount"),
+    ("ft", "fort"),
+    ("n.", "north"),
+    ("s.", "south"),
+    ("e.", "east"),
+    ("w.", "west"),
+    ("u.k.", "uk"),
+    ("u.s.", "us"),
+];
+
+/// Customizations of how names and queries are normalized, such as
+/// folding "ø" to "oe" or expanding "st" to "saint". A database applies
+/// the same normalizer to the names it indexes and to the queries it
+/// searches for.
+#[derive(Clone)]
+pub struct Normalizer {
+    // lower-cased text, with the romanized text it is replaced by
+    folds: Vec<(Vec<char>, String)>,
+    // normalized words, ending in "." if only expanded when followed by
+    // one, with what they are expanded to
+    expansions: HashMap<String, String>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        let mut normalizer = Normalizer::bare();
+        for (word, expansion) in ABBREVIATIONS {
+            normalizer.add_expansion(word, expansion);
+        }
+        normalizer
+    }
+}
+
+impl Normalizer {
+    // A normalizer that expands nothing
+    fn bare() -> Self {
+        Normalizer {
+            folds: vec![],
+            expansions: HashMap::new(),
+        }
+    }
+
+    /// Replace `text`, wherever it appears and regardless of case, before
+    /// romanizing.
+    pub fn add_fold(&mut self, text: &str, replacement: &str) {
+        let text = text
+            .chars()
+            .flat_map(char::to_lowercase)
+            .collect::<Vec<_>>();
+        if !text.is_empty() {
+            self.folds.retain(|(existing, _)| *existing != text);
+            self.folds.push((text, normalize(replacement)));
+        }
+    }
+
+    /// Expand the whole word `word`, and any "." following it, to
+    /// `expansion`, in place of any expansion it already has. A word ending
+    /// in "." is only expanded when followed by one, so that "n." can stand
+    /// for "north" without "n" doing so.
+    pub fn add_expansion(&mut self, word: &str, expansion: &str) {
+        let bare = Normalizer::bare();
+        let word = bare.text(word).trim().to_string();
+        let expansion = bare.text(expansion).trim().to_string();
+        if !word.is_empty() && !expansion.is_empty() {
+            self.expansions.insert(word, expansion);
+        }
+    }
+
+    /// Normalize as `berlin_core::normalize` does, except that CJK
+    /// syllables are joined into words and well-known names in CJK,
+    /// Arabic, Cyrillic or Greek script are replaced by their English
+    /// exonyms, so "北京" becomes "beijing" rather than "bei jing", and
+    /// "Москва" "moscow" rather than "moskva". Folds and expansions are
+    /// then applied. Used for queries and for names indexed by this crate.
+    pub fn text(&self, raw: &str) -> String {
+        self.with_offsets(raw).0
+    }
+
+    /// Normalize as `text` does, along with the index of the character of
+    /// `raw` that produced each byte of the result.
+    pub fn with_offsets(&self, raw: &str) -> (String, Vec<usize>) {
+        let chars = raw.chars().collect::<Vec<_>>();
+        let mut text = String::new();
+        let mut char_indices = vec![];
+        let mut last_script = Script::Other;
+        let mut i = 0;
+        while i < chars.len() {
+            let (n, script, piece) = match (self.fold_at(&chars[i..]), exonym_at(&chars, i)) {
+                (Some((n, folded)), _) => (n, Script::Other, folded.to_string()),
+                (None, Some((n, exonym))) => (n, Script::Exonym, exonym.to_string()),
+                (None, None) => {
+                    let (script, piece) = romanize_char(chars[i]);
+                    (1, script, piece)
+                }
+            };
+            // exonyms and runs of each CJK script are words of their own
+            let boundary = script != last_script || script == Script::Exonym;
+            if boundary && (script, last_script) != (Script::Other, Script::Other) {
+                let spaced = text.is_empty() || text.ends_with(' ') || piece.starts_with(' ');
+                if !spaced {
+                    text.push(' ');
+                    char_indices.push(i);
+                }
+            }
+            // the last byte maps to the last character replaced
+            text.push_str(&piece);
+            char_indices.extend(std::iter::repeat_n(i, piece.len().saturating_sub(1)));
+            if !piece.is_empty() {
+                char_indices.push(i + n - 1);
+            }
+            last_script = script;
+            i += n;
+        }
+        self.expand(text, char_indices)
+    }
+
+    // The replacement of the longest folded text the characters begin
+    // with, along with the number of characters it replaces
+    fn fold_at(&self, chars: &[char]) -> Option<(usize, &str)> {
+        self.folds
+            .iter()
+            .filter(|(text, _)| {
+                let lowered = chars.iter().take(text.len()).flat_map(|c| c.to_lowercase());
+                lowered.eq(text.iter().copied())
+            })
+            .max_by_key(|(text, _)| text.len())
+            .map(|(text, folded)| (text.len(), folded.as_str()))
+    }
+
+    fn expand(&self, text: String, char_indices: Vec<usize>) -> (String, Vec<usize>) {
+        if self.expansions.is_empty() {
+            return (text, char_indices);
+        }
+        let mut expanded = String::with_capacity(text.len());
+        let mut indices = Vec::with_capacity(char_indices.len());
+        let mut copied = 0;
+        for (start, word) in text.unicode_word_indices() {
+            let end = start + word.len();
+            // a following "." is part of the abbreviation
+            let dot = text[end..].starts_with('.');
+            let dotted = dot
+                .then(|| self.expansions.get(&format!["{word}."]))
+                .flatten();
+            let (expansion, end) = match (dotted, self.expansions.get(word)) {
+                (Some(expansion), _) | (None, Some(expansion)) => (expansion, end + dot as usize),
+                (None, None) => continue,
+            };
+            expanded.push_str(&text[copied..start]);
+            indices.extend_from_slice(&char_indices[copied..start]);
+            expanded.push_str(expansion);
+            indices.extend(std::iter::repeat_n(
+                char_indices[start],
+                expansion.len() - 1,
+            ));
+            indices.push(char_indices[end - 1]);
+            copied = end;
+        }
+        expanded.push_str(&text[copied..]);
+        indices.extend_from_slice(&char_indices[copied..]);
+        (expanded, indices)
+    }
+
+    /// Normalize the names of a location, given as in the JSON data, so
+    /// that berlin-core indexes them as this normalizer would.
+    pub fn apply_to_entry(&self, entry: &mut Value) {
+        let Some(data) = entry.get_mut("d").and_then(Value::as_object_mut) else {
+            return;
+        };
+        for field in ["name", "short"] {
+            match data.get_mut(field) {
+                // berlin-core takes short names of up to three letters, such
+                // as "UK", as codes, which are left alone
+                Some(Value::String(name)) if field == "name" || name.chars().count() > 3 => {
+                    // berlin-core normalizes the name again, to the same text
+                    *name = self.text(name);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Lower-case `raw`, along with the index of the character of `raw` that
+/// produced each byte of the result.
+pub fn lowercase_with_offsets(raw: &str) -> (String, Vec<usize>) {
+    with_offsets(raw.chars().map(|c| c.to_lowercase().collect::<String>()))
+}
+
+fn with_offsets(pieces: impl Iterator<Item = String>) -> (String, Vec<usize>) {
+    let mut text = String::new();
+    let mut char_indices = vec![];
+    for (n, piece) in pieces.enumerate() {
+        text.push_str(&piece);
+        char_indices.extend(std::iter::repeat_n(n, piece.len()));
+    }
+    (text, char_indices)
+}
+
+/// The characters of the raw text that produced `start..end` of its
+/// normalized form, given the indices from `Normalizer::with_offsets`.
+fn raw_span(char_indices: &[usize], start: usize, end: usize) -> (usize, usize) {
+    let Some(last) = char_indices.last() else {
+        return (0, 0);
+    };
+    let raw_start = char_indices.get(start).copied().unwrap_or(last + 1);
+    let raw_end = match end.checked_sub(1).and_then(|i| char_indices.get(i)) {
+        Some(n) => n + 1,
+        None => last + 1,
+    };
+    (raw_start, raw_end.max(raw_start))
+}
+
+/// A score with its offset moved from the transformed text to the raw text.
+pub fn raw_score(char_indices: &[usize], score: Score) -> Score {
+    let (start, end) = raw_span(char_indices, score.offset.start, score.offset.end);
+    Score {
+        offset: Offset { start, end },
+        ..score
+    }
+}
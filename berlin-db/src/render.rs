@@ -0,0 +1,146 @@
+/// The template used when none is given.
+pub const DEFAULT_TEMPLATE: &str = "{name}[, {subdiv}][, {state}] — {encoding} {code}";
+
+/// A value a template can refer to, written in braces: `{name}`, `{subdiv}`
+/// and `{state}` are names as in the source data, `{encoding}`, `{id}` and
+/// `{key}` identify the location, and `{code}`, `{state_code}` and
+/// `{subdiv_code}` are upper-cased codes, as in "GB LON", "GB" and "ENG".
+#[derive(Clone, Copy)]
+enum Field {
+    Name,
+    Subdiv,
+    State,
+    Encoding,
+    Id,
+    Key,
+    Code,
+    StateCode,
+    SubdivCode,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "name" => Field::Name,
+            "subdiv" => Field::Subdiv,
+            "state" => Field::State,
+            "encoding" => Field::Encoding,
+            "id" => Field::Id,
+            "key" => Field::Key,
+            "code" => Field::Code,
+            "state_code" => Field::StateCode,
+            "subdiv_code" => Field::SubdivCode,
+            _ => return None,
+        })
+    }
+}
+
+/// The values of the fields for one location. The subdivision and state
+/// are left empty for locations that have none, or that are one.
+#[derive(Default)]
+pub struct TextFields {
+    pub name: String,
+    pub subdiv: String,
+    pub state: String,
+    pub encoding: String,
+    pub id: String,
+    pub key: String,
+    pub code: String,
+    pub state_code: String,
+    pub subdiv_code: String,
+}
+
+impl TextFields {
+    fn get(&self, field: Field) -> &str {
+        match field {
+            Field::Name => &self.name,
+            Field::Subdiv => &self.subdiv,
+            Field::State => &self.state,
+            Field::Encoding => &self.encoding,
+            Field::Id => &self.id,
+            Field::Key => &self.key,
+            Field::Code => &self.code,
+            Field::StateCode => &self.state_code,
+            Field::SubdivCode => &self.subdiv_code,
+        }
+    }
+}
+
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+impl Part {
+    fn value<'a>(&'a self, fields: &'a TextFields) -> &'a str {
+        match self {
+            Part::Literal(literal) => literal,
+            Part::Field(field) => fields.get(*field),
+        }
+    }
+}
+
+/// A parsed template for rendering locations as text. Fields are written
+/// in braces, and a group in square brackets is left out if any field in
+/// it is empty, so "{name}[, {subdiv}]" gives just the name of a location
+/// without a subdivision.
+pub struct TextTemplate {
+    // runs of parts, and whether each is an optional group
+    groups: Vec<(bool, Vec<Part>)>,
+}
+
+impl TextTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut groups = vec![(false, vec![])];
+        let mut rest = template;
+        while let Some(i) = rest.find(['{', '[', ']']) {
+            let (optional, parts) = groups.last_mut().unwrap();
+            let optional = *optional;
+            if i > 0 {
+                parts.push(Part::Literal(rest[..i].to_string()));
+            }
+            rest = &rest[i..];
+            match rest.as_bytes()[0] {
+                b'{' => {
+                    let end = rest.find('}').ok_or("Unclosed { in template")?;
+                    let field = Field::parse(&rest[1..end])
+                        .ok_or_else(|| format!("Unknown field {} in template", &rest[..=end]))?;
+                    parts.push(Part::Field(field));
+                    rest = &rest[end + 1..];
+                }
+                b'[' if !optional => {
+                    groups.push((true, vec![]));
+                    rest = &rest[1..];
+                }
+                b']' if optional => {
+                    groups.push((false, vec![]));
+                    rest = &rest[1..];
+                }
+                _ => return Err(format!("Unexpected {} in template", &rest[..1])),
+            }
+        }
+        let (optional, parts) = groups.last_mut().unwrap();
+        if *optional {
+            return Err("Unclosed [ in template".to_string());
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(TextTemplate { groups })
+    }
+
+    pub fn render(&self, fields: &TextFields) -> String {
+        let mut text = String::new();
+        for (optional, parts) in &self.groups {
+            let empty = parts
+                .iter()
+                .any(|part| matches!(part, Part::Field(_)) && part.value(fields).is_empty());
+            if !(*optional && empty) {
+                parts
+                    .iter()
+                    .for_each(|part| text.push_str(part.value(fields)));
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,155 @@
+use deunicode::deunicode_char;
+
+/// English exonyms of names that romanization does not bring close to the
+/// English name, whether from CJK or from Arabic script (whose vowels are
+/// not written).
+const EXONYMS: [(&str, &str); 44] = [
+    ("東京", "tokyo"),
+    ("东京", "tokyo"),
+    ("とうきょう", "tokyo"),
+    ("京都", "kyoto"),
+    ("大阪", "osaka"),
+    ("横浜", "yokohama"),
+    ("名古屋", "nagoya"),
+    ("札幌", "sapporo"),
+    ("神戸", "kobe"),
+    ("広島", "hiroshima"),
+    ("福岡", "fukuoka"),
+    ("日本", "japan"),
+    ("香港", "hong kong"),
+    ("澳門", "macau"),
+    ("澳门", "macau"),
+    ("台北", "taipei"),
+    ("臺北", "taipei"),
+    ("高雄", "kaohsiung"),
+    ("台灣", "taiwan"),
+    ("台湾", "taiwan"),
+    ("中国", "china"),
+    ("中國", "china"),
+    ("한국", "korea"),
+    ("القاهرة", "cairo"),
+    ("الإسكندرية", "alexandria"),
+    ("مصر", "egypt"),
+    ("الرياض", "riyadh"),
+    ("جدة", "jeddah"),
+    ("مكة", "mecca"),
+    ("المدينة", "medina"),
+    ("السعودية", "saudi arabia"),
+    ("دبي", "dubai"),
+    ("أبوظبي", "abu dhabi"),
+    ("أبو ظبي", "abu dhabi"),
+    ("الدوحة", "doha"),
+    ("الكويت", "kuwait"),
+    ("بغداد", "baghdad"),
+    ("دمشق", "damascus"),
+    ("بيروت", "beirut"),
+    ("القدس", "jerusalem"),
+    ("تونس", "tunis"),
+    ("الجزائر", "algiers"),
+    ("الرباط", "rabat"),
+    ("الدار البيضاء", "casablanca"),
+];
+
+/// English names of places in Cyrillic or Greek script, whose
+/// romanizations ("moskva", "athina") are too far from the names in the
+/// data to match. As these scripts are written with spaces and capitals,
+/// names are only matched as whole words, regardless of case.
+const WORD_EXONYMS: [(&str, &str); 36] = [
+    ("москва", "moscow"),
+    ("санкт-петербург", "saint petersburg"),
+    ("россия", "russia"),
+    ("киев", "kyiv"),
+    ("київ", "kyiv"),
+    ("одесса", "odesa"),
+    ("одеса", "odesa"),
+    ("харьков", "kharkiv"),
+    ("харків", "kharkiv"),
+    ("львов", "lviv"),
+    ("львів", "lviv"),
+    ("украина", "ukraine"),
+    ("україна", "ukraine"),
+    ("беларусь", "belarus"),
+    ("софия", "sofia"),
+    ("пловдив", "plovdiv"),
+    ("варна", "varna"),
+    ("болгария", "bulgaria"),
+    ("българия", "bulgaria"),
+    ("белград", "belgrade"),
+    ("београд", "belgrade"),
+    ("србија", "serbia"),
+    ("варшава", "warsaw"),
+    ("прага", "prague"),
+    ("вена", "vienna"),
+    ("рим", "rome"),
+    ("германия", "germany"),
+    ("великобритания", "united kingdom"),
+    ("αθήνα", "athens"),
+    ("αθηνα", "athens"),
+    ("θεσσαλονίκη", "thessaloniki"),
+    ("θεσσαλονικη", "thessaloniki"),
+    ("ελλάδα", "greece"),
+    ("ελλαδα", "greece"),
+    ("κύπρος", "cyprus"),
+    ("λευκωσία", "nicosia"),
+];
+
+/// How a piece of text is romanized.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    /// Replaced with an English exonym.
+    Exonym,
+    // Scripts romanized a syllable per character, without spaces between
+    // words. A change between them is taken as a word boundary, as in
+    // Japanese names followed by kana particles.
+    Han,
+    Kana,
+    Hangul,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c {
+        '\u{3040}'..='\u{30ff}' => Script::Kana,
+        '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' => Script::Han,
+        '\u{ac00}'..='\u{d7af}' => Script::Hangul,
+        _ => Script::Other,
+    }
+}
+
+/// The exonym of the longest name the characters from `start` begin with,
+/// along with the number of characters it replaces.
+pub fn exonym_at(chars: &[char], start: usize) -> Option<(usize, &'static str)> {
+    // every name is in a script other than Latin, and most text is ASCII
+    if chars[start].is_ascii() {
+        return None;
+    }
+    let rest = &chars[start..];
+    let joined = EXONYMS
+        .iter()
+        .map(|(name, exonym)| (name.chars().count(), *exonym, name))
+        .filter(|(n, _, name)| rest.iter().take(*n).copied().eq(name.chars()));
+    let word_start = start == 0 || !chars[start - 1].is_alphanumeric();
+    let words = WORD_EXONYMS
+        .iter()
+        .filter(|_| word_start)
+        .map(|(name, exonym)| (name.chars().count(), *exonym, name))
+        .filter(|(n, _, name)| {
+            let lowered = rest.iter().take(*n).flat_map(|c| c.to_lowercase());
+            lowered.eq(name.chars()) && !rest.get(*n).is_some_and(|c| c.is_alphanumeric())
+        });
+    joined
+        .chain(words)
+        .max_by_key(|(n, _, _)| *n)
+        .map(|(n, exonym, _)| (n, exonym))
+}
+
+/// The romanization of a single character, with syllables stripped of the
+/// separating spaces deunicode gives them.
+pub fn romanize_char(c: char) -> (Script, String) {
+    // deunicode's placeholder for characters it cannot convert
+    let ascii = deunicode_char(c).unwrap_or("[?]");
+    match script(c) {
+        Script::Other => (Script::Other, ascii.to_lowercase()),
+        script => (script, ascii.trim().to_lowercase()),
+    }
+}
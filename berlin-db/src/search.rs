@@ -0,0 +1,472 @@
+use std::cmp::{max, min, Reverse};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "graph")]
+use berlin_core::location::LocData;
+use berlin_core::location::Location;
+use berlin_core::locations_db::LocationsDb;
+use berlin_core::rayon::prelude::*;
+use berlin_core::search::{Score, SearchTerm};
+use berlin_core::ustr::{Ustr, UstrMap, UstrSet};
+use fst::{Automaton, Streamer};
+use levenshtein_automata::LevenshteinAutomatonBuilder;
+#[cfg(feature = "graph")]
+use petgraph::graphmap::DiGraphMap;
+
+use crate::disk_index::DiskIndex;
+
+// These mirror the thresholds in berlin-core's own search
+const LEV_3_LENGTH_MAX: usize = 10;
+const LEV_2_LENGTH_MAX: usize = 20;
+const SEARCH_INCLUSION_THRESHOLD: i64 = 400;
+
+/// The largest Levenshtein distance a search may request.
+pub const MAX_LEV_DISTANCE: u32 = 4;
+// Above distance 2, each edit must be paid for by this many characters of
+// the term, as a high distance on a short term matches much of the index.
+const LEV_CHARS_PER_EDIT: usize = 3;
+
+/// The score below which a strict search drops results resting on one
+/// fuzzily matched word of the query. Exact matches of a name score 1000
+/// or more, and fuzzy matches of a single word around 700 to 900.
+pub const STRICT_THRESHOLD: i64 = 900;
+
+// Candidates kept per encoding, as a multiple of the limit, before the
+// graph analysis. Broad queries can otherwise put thousands of candidates
+// into the graph when only a handful will be returned.
+const GRAPH_CANDIDATE_FACTOR: usize = 10;
+
+/// Options that adjust how a search is carried out, beyond the search term.
+#[derive(Default, Clone)]
+pub struct SearchOptions {
+    /// Number of ranked candidates kept internally, before results are
+    /// grouped and truncated to the search term's limit of distinct
+    /// locations. Defaults to a multiple of that limit.
+    pub candidate_limit: Option<usize>,
+    /// Only locations carrying all of these tags are returned.
+    pub tags: Vec<String>,
+    /// Also match names that sound like words in the query, catching
+    /// misspellings beyond the Levenshtein distance.
+    pub phonetic: bool,
+    /// Also match runs of query words found inside names, such as "upon
+    /// avon" in "stratford-upon-avon". The index this needs is built on
+    /// first use, as it takes considerable memory.
+    pub infix: bool,
+    /// Drop results whose only evidence is one word of the query that is
+    /// not a word or code of the location, scoring below this threshold,
+    /// so that queries such as "poverty" return nothing rather than junk.
+    pub strict: Option<i64>,
+    /// Words that suggest a location follows, such as "near" or "based in",
+    /// normalized. Results right after one of them are boosted.
+    pub context: Vec<String>,
+    /// Only locations in states on this continent, such as "eu", lower-cased,
+    /// are returned.
+    pub continent: Option<String>,
+    /// Where to record how long each phase of the search takes, if at all.
+    pub timings: Option<Arc<Mutex<SearchTimings>>>,
+    /// Where to record, if at all, the parent that boosted each candidate
+    /// in the graph analysis, and by how much.
+    pub parent_boosts: Option<Arc<Mutex<ParentBoosts>>>,
+    /// How locations whose parents also matched are boosted.
+    pub graph: GraphConfig,
+}
+
+/// How the graph analysis boosts a location whose state or subdivision
+/// also matched the query. The defaults are berlin-core's.
+#[derive(Clone, Copy, PartialEq)]
+pub struct GraphConfig {
+    /// Whether to boost at all. Without the graph feature, it never does.
+    pub enabled: bool,
+    /// A parent only boosts its child if both score above this.
+    pub edge_threshold: i64,
+    /// The share of a parent's score added to its child's, for a parent
+    /// that is a state, a subdivision, a LOCODE or another location.
+    pub state_share: f64,
+    pub subdiv_share: f64,
+    pub locode_share: f64,
+    pub generic_share: f64,
+    /// Whether the boosts of a location's state and subdivision add up when
+    /// both matched, as in "Stonehaven, Aberdeenshire, UK", rather than the
+    /// larger alone counting.
+    pub chains: bool,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            enabled: true,
+            edge_threshold: 600,
+            state_share: 1.0 / 2.0,
+            subdiv_share: 1.0 / 3.0,
+            locode_share: 1.0 / 4.0,
+            generic_share: 1.0 / 8.0,
+            chains: false,
+        }
+    }
+}
+
+impl GraphConfig {
+    /// The boost a parent of the given score gives its children.
+    #[cfg(feature = "graph")]
+    pub fn parent_boost(&self, parent: &Location, score: i64) -> i64 {
+        let share = match parent.data {
+            LocData::St(_) => self.state_share,
+            LocData::Subdv(_) => self.subdiv_share,
+            LocData::Locd(_) => self.locode_share,
+            LocData::Gen(_) => self.generic_share,
+            LocData::Airp(_) => 0.0,
+        };
+        (score as f64 * share) as i64
+    }
+
+    /// The configuration as a value that can be hashed, for cache keys.
+    pub fn key(&self) -> (bool, i64, [u64; 4], bool) {
+        let shares = [
+            self.state_share,
+            self.subdiv_share,
+            self.locode_share,
+            self.generic_share,
+        ];
+        (
+            self.enabled,
+            self.edge_threshold,
+            shares.map(f64::to_bits),
+            self.chains,
+        )
+    }
+}
+
+/// The parent that boosted each candidate, by its key, and the boost.
+pub type ParentBoosts = UstrMap<(Ustr, i64)>;
+
+/// How long each phase of a search took. Phases a search skips, such as
+/// the FST scan of an exact search, take no time.
+#[derive(Default, Clone, Copy)]
+pub struct SearchTimings {
+    /// Building the automata and scanning the FST for candidates.
+    pub fst_scan: Duration,
+    /// Scoring the candidates against the search term.
+    pub scoring: Duration,
+    /// Cutting candidates by encoding and boosting them by their parents.
+    pub graph: Duration,
+    /// Ranking, sorting and truncating the results.
+    pub sorting: Duration,
+}
+
+impl SearchOptions {
+    /// Add the time since `started` to a phase of the timings, if recorded.
+    pub fn record(
+        &self,
+        started: Instant,
+        phase: impl FnOnce(&mut SearchTimings) -> &mut Duration,
+    ) {
+        if let Some(timings) = &self.timings {
+            *phase(&mut timings.lock().unwrap()) += started.elapsed();
+        }
+    }
+
+    pub fn candidate_limit(&self, limit: usize) -> usize {
+        match self.candidate_limit {
+            Some(candidate_limit) => max(candidate_limit, limit),
+            None => limit.saturating_mul(GRAPH_CANDIDATE_FACTOR),
+        }
+    }
+}
+
+/// Search the database, as `LocationsDb::search` does, but truncating the
+/// candidate set by rank before the graph analysis. Up to the candidate
+/// limit of ranked results are returned, rather than the search term's limit,
+/// and only candidates that `keep` accepts are returned, though the others
+/// still boost the locations under them. Words are looked up in `disk`, if
+/// the word index has been moved there, or else in `db`.
+pub fn search(
+    db: &LocationsDb,
+    disk: Option<&DiskIndex>,
+    st: &SearchTerm,
+    opts: &SearchOptions,
+    keep: &(dyn Fn(&Ustr) -> bool + Sync),
+) -> Vec<(Ustr, Score)> {
+    let candidate_limit = opts.candidate_limit(st.limit);
+    let started = Instant::now();
+    let pre_filtered = match disk {
+        None => scan(
+            &db.fst,
+            st,
+            |term| db.by_word_map.get(term),
+            |word, locs| locs.extend(&db.by_word_vec[word as usize].1),
+        ),
+        Some(disk) => {
+            // the words to grab must be looked up before the scan borrows
+            // them
+            let mut terms = vec![];
+            st.build_search(
+                fst::map::OpBuilder::new(),
+                |op, _| op,
+                |term| {
+                    terms.push(*term);
+                    None::<&UstrSet>
+                },
+            );
+            let grabbed = terms
+                .into_iter()
+                .filter_map(|term| Some((term, disk.get(&term)?)))
+                .collect::<UstrMap<_>>();
+            scan(
+                disk.fst(),
+                st,
+                |term| grabbed.get(term),
+                |word, locs| locs.extend(disk.postings(word)),
+            )
+        }
+    };
+    opts.record(started, |t| &mut t.fst_scan);
+
+    let started = Instant::now();
+    let res = pre_filtered
+        .par_iter()
+        .filter_map(|key| {
+            let loc = db.all.get(key).unwrap();
+            loc.search(st)
+                .filter(|score| score.score > SEARCH_INCLUSION_THRESHOLD)
+                .map(|score| (*key, score))
+        })
+        .collect::<UstrMap<_>>();
+    opts.record(started, |t| &mut t.scoring);
+
+    let started = Instant::now();
+    let res = truncate_by_encoding(res, db, candidate_limit, keep, &opts.graph);
+    let mut res = graph_boost(res, db, opts)
+        .into_iter()
+        .filter(|(key, _)| keep(key))
+        .collect::<Vec<_>>();
+    opts.record(started, |t| &mut t.graph);
+    let started = Instant::now();
+    res.sort_unstable_by_key(|(_, score)| Reverse(*score));
+    res.truncate(candidate_limit);
+    opts.record(started, |t| &mut t.sorting);
+    res
+}
+
+// The candidates for a search term: the locations under words of the query
+// that `grab_action` finds, and under words of the FST that match the rest
+fn scan<'c, D: AsRef<[u8]>>(
+    fst: &'c fst::Map<D>,
+    st: &'c SearchTerm,
+    grab_action: impl FnMut(&'c Ustr) -> Option<&'c UstrSet>,
+    postings: impl Fn(u64, &mut UstrSet),
+) -> UstrSet {
+    let search_action = |op: fst::map::OpBuilder<'c>, term: &'c str| match term.len() > 3 {
+        true => {
+            let prefix_matcher = fst::automaton::Str::new(term).starts_with();
+            let lev_dist = term_lev_distance(term.chars().count(), st.lev_dist);
+            let autom = lev_builder(lev_dist).build_dfa(term).union(prefix_matcher);
+            op.add(fst.search(autom))
+        }
+        false => op,
+    };
+
+    // Grab is for strings we believe we know, searches for those
+    // we do not.
+    let (builder, mut pre_filtered) =
+        st.build_search(fst::map::OpBuilder::new(), search_action, grab_action);
+
+    let mut stream = builder.union();
+    while let Some((_, v)) = stream.next() {
+        postings(v[0].value, &mut pre_filtered);
+    }
+    pre_filtered
+}
+
+/// The Levenshtein distance used for a query term of `count` characters.
+/// Up to distance 2 this follows berlin-core; larger requested distances
+/// are allowed one edit per `LEV_CHARS_PER_EDIT` characters of the term.
+pub fn term_lev_distance(count: usize, requested: u32) -> u32 {
+    let requested = min(requested, MAX_LEV_DISTANCE);
+    if requested <= 2 {
+        return match count {
+            count if count < LEV_3_LENGTH_MAX => requested,
+            count if count < LEV_2_LENGTH_MAX => min(requested, 2),
+            _ => min(requested, 1),
+        };
+    }
+    min(requested, max(2, (count / LEV_CHARS_PER_EDIT) as u32))
+}
+
+// Building the parametric automaton for a distance is costly, particularly
+// at distances 3 and 4, so each is built once and reused for every term.
+fn lev_builder(distance: u32) -> &'static LevenshteinAutomatonBuilder {
+    static BUILDERS: [OnceLock<LevenshteinAutomatonBuilder>; MAX_LEV_DISTANCE as usize + 1] =
+        [const { OnceLock::new() }; MAX_LEV_DISTANCE as usize + 1];
+    BUILDERS[distance as usize]
+        .get_or_init(|| LevenshteinAutomatonBuilder::new(distance as u8, false))
+}
+
+// Keep only the best `limit` candidates of each encoding that `keep`
+// accepts, by the score the graph analysis will give them, along with any
+// others ranked above them and the parents of all of them, so that parents
+// (states, subdivisions) are not crowded out by more numerous locodes.
+//
+// This does not change the ranking. Each candidate kept keeps the parents
+// that boost it, so reaches the same score as without truncating. Each
+// candidate dropped has `limit` candidates of its encoding that `keep`
+// accepts scoring at least as well, so would not have been returned.
+fn truncate_by_encoding(
+    res: UstrMap<Score>,
+    db: &LocationsDb,
+    limit: usize,
+    keep: &(dyn Fn(&Ustr) -> bool + Sync),
+    config: &GraphConfig,
+) -> UstrMap<Score> {
+    if res.len() <= limit {
+        return res;
+    }
+    let mut by_encoding: UstrMap<Vec<(&Location, Score)>> = UstrMap::default();
+    for (key, score) in res.iter() {
+        let loc = db.all.get(key).expect("location in db");
+        let boosted = Score {
+            score: boosted_score(loc, score.score, &res, db, config),
+            offset: score.offset,
+        };
+        by_encoding
+            .entry(loc.encoding)
+            .or_default()
+            .push((loc, boosted));
+    }
+    let mut truncated = UstrMap::default();
+    for mut candidates in by_encoding.into_values() {
+        if candidates.len() > limit {
+            candidates.sort_unstable_by_key(|(_, boosted)| Reverse(*boosted));
+            let mut kept = 0;
+            let end = candidates.iter().position(|(loc, _)| {
+                kept += keep(&loc.key) as usize;
+                kept > limit
+            });
+            candidates.truncate(end.unwrap_or(candidates.len()));
+        }
+        for (loc, _) in candidates {
+            let (state_key, subdiv_key) = loc.get_parents();
+            for key in [Some(loc.key), state_key, subdiv_key].into_iter().flatten() {
+                if let Some(score) = res.get(&key) {
+                    truncated.insert(key, *score);
+                }
+            }
+        }
+    }
+    truncated
+}
+
+// The score graph_boost gives a location, boosted by those of its parents
+// among the candidates
+#[cfg(feature = "graph")]
+fn boosted_score(
+    loc: &Location,
+    score: i64,
+    candidates: &UstrMap<Score>,
+    db: &LocationsDb,
+    config: &GraphConfig,
+) -> i64 {
+    if !config.enabled {
+        return score;
+    }
+    let (state_key, subdiv_key) = loc.get_parents();
+    let boosts = [state_key, subdiv_key]
+        .into_iter()
+        .flatten()
+        .filter_map(|parent_key| {
+            let parent_score = candidates.get(&parent_key)?.score;
+            let parent = db.all.get(&parent_key)?;
+            (min(parent_score, score) > config.edge_threshold)
+                .then(|| config.parent_boost(parent, parent_score))
+        });
+    match config.chains {
+        true => score + boosts.sum::<i64>(),
+        false => score + boosts.max().unwrap_or(0).max(0),
+    }
+}
+
+#[cfg(not(feature = "graph"))]
+fn boosted_score(
+    _loc: &Location,
+    score: i64,
+    _candidates: &UstrMap<Score>,
+    _db: &LocationsDb,
+    _config: &GraphConfig,
+) -> i64 {
+    score
+}
+
+// Boost locations whose parents also matched, as berlin-core's results
+// graph does.
+#[cfg(feature = "graph")]
+fn graph_boost(
+    mut results: UstrMap<Score>,
+    db: &LocationsDb,
+    opts: &SearchOptions,
+) -> UstrMap<Score> {
+    let config = &opts.graph;
+    if !config.enabled {
+        return results;
+    }
+    let mut graph: DiGraphMap<Ustr, _> = DiGraphMap::new();
+    results.iter().for_each(|(key, score)| {
+        let loc = db.all.get(key).expect("location in db");
+        graph.add_node(loc.key);
+        let (state_key, subdiv_key) = loc.get_parents();
+        for superkey in [state_key, subdiv_key].into_iter().flatten() {
+            if let Some(superkey_score) = results.get(&superkey) {
+                if min(superkey_score.score, score.score) > config.edge_threshold {
+                    let weight = (superkey_score.score, score.score);
+                    graph.add_edge(superkey, loc.key, weight);
+                }
+            }
+        }
+    });
+    let mut edges = graph
+        .all_edges()
+        .map(|(parent_key, key, weight)| (parent_key, key, *weight))
+        .collect::<Vec<_>>();
+    // strongest first, ties broken by the keys' text, as `Ustr`'s `Ord`
+    // compares pointers, which differ from run to run
+    let order = |e: &(Ustr, Ustr, (i64, i64))| (Reverse(e.2), e.0.as_str(), e.1.as_str());
+    edges.sort_unstable_by(|a, b| order(a).cmp(&order(b)));
+    edges.into_iter().for_each(|(parent_key, key, weight)| {
+        let parent = db.all.get(&parent_key).unwrap();
+        let boost = config.parent_boost(parent, weight.0);
+        let old = results.get(&key).cloned().unwrap();
+        let total_score = match config.chains {
+            true => old.score + boost,
+            false => boost + weight.1,
+        };
+        if let Some(parent_boosts) = opts
+            .parent_boosts
+            .as_ref()
+            .filter(|_| total_score > old.score)
+        {
+            // chained boosts are credited to the best scoring parent
+            let mut parent_boosts = parent_boosts.lock().unwrap();
+            let credited = parent_boosts.entry(key).or_insert((parent_key, 0));
+            if !config.chains {
+                credited.0 = parent_key;
+            }
+            credited.1 = total_score - weight.1;
+        }
+        results.insert(
+            key,
+            Score {
+                offset: old.offset,
+                score: max(total_score, old.score),
+            },
+        );
+    });
+    results
+}
+
+#[cfg(not(feature = "graph"))]
+fn graph_boost(
+    results: UstrMap<Score>,
+    _db: &LocationsDb,
+    _opts: &SearchOptions,
+) -> UstrMap<Score> {
+    results
+}
@@ -0,0 +1,155 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use berlin_core::location::Location;
+use berlin_core::ustr::Ustr;
+
+use crate::builder::SourceNames;
+use crate::db::{BerlinDb, Change};
+
+// How long the updater waits for more mutations before applying a batch
+const BATCH_WINDOW: Duration = Duration::from_millis(50);
+
+/// A change queued for the background updater.
+#[derive(Clone)]
+pub enum Mutation {
+    Alias {
+        key: Ustr,
+        name: String,
+    },
+    Location {
+        loc: Location,
+        names: SourceNames,
+    },
+    /// Remove a location, along with every location under it.
+    Tombstone(Ustr),
+}
+
+#[derive(Default)]
+struct Queue {
+    mutations: Vec<Mutation>,
+    // whether a worker thread is waiting for or applying a batch
+    running: bool,
+    applied: u64,
+    failed: u64,
+    last_applied: Option<SystemTime>,
+}
+
+pub struct UpdateStats {
+    pub queue_depth: usize,
+    pub applied: u64,
+    pub failed: u64,
+    pub last_applied: Option<SystemTime>,
+}
+
+/// Applies queued mutations to a database in batches, on a worker thread.
+/// Each batch is applied to a copy of the database, whose indexes are then
+/// rebuilt while the original goes on serving queries, and swapped in.
+pub struct Updater {
+    db: Arc<Mutex<BerlinDb>>,
+    queue: Mutex<Queue>,
+    idle: Condvar,
+}
+
+impl Updater {
+    pub fn new(db: Arc<Mutex<BerlinDb>>) -> Self {
+        Updater {
+            db,
+            queue: Mutex::new(Queue::default()),
+            idle: Condvar::new(),
+        }
+    }
+
+    /// Queue a mutation, starting a worker to apply it if none is running.
+    pub fn enqueue(self: &Arc<Self>, mutation: Mutation) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.mutations.push(mutation);
+        if !queue.running {
+            queue.running = true;
+            let updater = self.clone();
+            thread::spawn(move || updater.run());
+        }
+    }
+
+    /// Wait until every queued mutation has been applied.
+    pub fn flush(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        while queue.running {
+            queue = self.idle.wait(queue).unwrap();
+        }
+    }
+
+    pub fn stats(&self) -> UpdateStats {
+        let queue = self.queue.lock().unwrap();
+        UpdateStats {
+            queue_depth: queue.mutations.len(),
+            applied: queue.applied,
+            failed: queue.failed,
+            last_applied: queue.last_applied,
+        }
+    }
+
+    // The worker exits once the queue is empty, so that it does not keep
+    // the database alive
+    fn run(&self) {
+        loop {
+            thread::sleep(BATCH_WINDOW);
+            let batch = {
+                let mut queue = self.queue.lock().unwrap();
+                if queue.mutations.is_empty() {
+                    queue.running = false;
+                    self.idle.notify_all();
+                    return;
+                }
+                std::mem::take(&mut queue.mutations)
+            };
+            let count = batch.len() as u64;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| self.apply(batch)));
+            let mut queue = self.queue.lock().unwrap();
+            match result {
+                Ok(()) => {
+                    queue.applied += count;
+                    queue.last_applied = Some(SystemTime::now());
+                }
+                Err(_) => queue.failed += count,
+            }
+        }
+    }
+
+    fn apply(&self, batch: Vec<Mutation>) {
+        let (mut copy, revision) = {
+            let db = self.db.lock().unwrap();
+            (db.snapshot(), db.revision())
+        };
+        apply_mutations(&mut copy, batch.clone());
+        let mut db = self.db.lock().unwrap();
+        // changes made directly while the copy was rebuilt would be lost by
+        // swapping it in, so the batch is applied to the original instead
+        match db.revision() == revision {
+            true => *db = copy,
+            false => apply_mutations(&mut db, batch),
+        }
+    }
+}
+
+// Aliases and names are added once the locations they refer to are in
+fn apply_mutations(db: &mut BerlinDb, batch: Vec<Mutation>) {
+    let changes = batch
+        .iter()
+        .filter_map(|mutation| match mutation {
+            Mutation::Location { loc, .. } => Some(Change::Insert(loc.clone())),
+            Mutation::Tombstone(key) => Some(Change::Remove(*key)),
+            Mutation::Alias { .. } => None,
+        })
+        .collect();
+    db.apply_changes(changes);
+    for mutation in batch {
+        match mutation {
+            Mutation::Alias { key, name } if db.all.contains_key(&key) => db.add_alias(key, &name),
+            Mutation::Location { loc, names } if db.all.contains_key(&loc.key) => names.apply(db),
+            _ => {}
+        }
+    }
+}
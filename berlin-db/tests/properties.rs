@@ -0,0 +1,140 @@
+//! Properties of the query parsing this crate does on top of berlin-core:
+//! normalization with offsets, search terms and the mapping of their spans
+//! back to the raw query. These look for panics on input such as non-ASCII
+//! or empty text, and for offsets that do not point back into the query.
+use berlin_core::search::{Offset, Score};
+use berlin_core::ustr::Ustr;
+use berlin_db::query::{
+    inspect_query, lowercase_with_offsets, parse_query, raw_score, Normalizer, DEFAULT_STOP_WORDS,
+};
+use proptest::prelude::*;
+
+// Words that are interned, as the names of locations are once loaded, so
+// that queries containing them are matched exactly rather than fuzzily
+const VOCABULARY: [&str; 8] = [
+    "abercarn",
+    "bognor",
+    "regis",
+    "bognor regis",
+    "sofia",
+    "gb",
+    "lon",
+    "ile de france",
+];
+
+fn intern_vocabulary() {
+    for word in VOCABULARY {
+        Ustr::from(word);
+    }
+}
+
+fn stop_words() -> Vec<String> {
+    DEFAULT_STOP_WORDS.iter().map(|w| w.to_string()).collect()
+}
+
+// Queries mixing known words, stop words, abbreviations, punctuation and
+// text in other scripts, including exonyms
+fn query() -> impl Strategy<Value = String> {
+    let piece = prop_oneof![
+        prop::sample::select(VOCABULARY.to_vec()).prop_map(String::from),
+        prop::sample::select(vec![
+            "in", "the", "of", "st", "St.", "mt", " ", ", ", "-", "'"
+        ])
+        .prop_map(String::from),
+        prop::sample::select(vec![
+            "Île",
+            "BOGNOR",
+            "北京市",
+            "東京",
+            "Москва",
+            "مصر",
+            "ß",
+            "İ",
+            "ﬁ",
+            "🙂",
+            "e\u{301}"
+        ])
+        .prop_map(String::from),
+        any::<String>(),
+    ];
+    prop::collection::vec(piece, 0..8).prop_map(|pieces| pieces.join(" "))
+}
+
+proptest! {
+    #[test]
+    fn offsets_point_into_the_raw_query(raw in query()) {
+        let (text, char_indices) = Normalizer::default().with_offsets(&raw);
+        let chars = raw.chars().count();
+        prop_assert_eq!(char_indices.len(), text.len());
+        prop_assert!(char_indices.iter().all(|&i| i < chars), "{:?} beyond {} characters", char_indices, chars);
+        prop_assert!(char_indices.windows(2).all(|pair| pair[0] <= pair[1]), "{:?} out of order", char_indices);
+    }
+
+    #[test]
+    fn search_terms_point_into_the_normalized_query(
+        raw in query(),
+        limit in 1usize..100,
+        lev_dist in 0u32..3,
+    ) {
+        intern_vocabulary();
+        let normalizer = Normalizer::default();
+        let (st, terms) = inspect_query(raw.clone(), None, limit, lev_dist, &stop_words(), &normalizer);
+        prop_assert_eq!(&st.normalized, &normalizer.text(&raw));
+        let parsed = parse_query(raw, None, limit, lev_dist, &stop_words(), &normalizer);
+        prop_assert_eq!(&parsed.normalized, &st.normalized);
+        for (term, offset) in terms.exact.iter().chain(&terms.fuzzy) {
+            prop_assert_eq!(&st.normalized[offset.start..offset.end], term.as_str());
+        }
+    }
+
+    #[test]
+    fn raw_scores_are_spans_of_the_raw_query(
+        raw in query(),
+        start in 0usize..200,
+        len in 0usize..200,
+    ) {
+        let (text, char_indices) = Normalizer::default().with_offsets(&raw);
+        let start = start.min(text.len());
+        let end = (start + len).min(text.len());
+        let score = raw_score(&char_indices, Score { score: 1, offset: Offset { start, end } });
+        prop_assert_eq!(score.score, 1);
+        prop_assert!(score.offset.start <= score.offset.end);
+        prop_assert!(score.offset.end <= raw.chars().count());
+        if start < end {
+            prop_assert!(score.offset.start < score.offset.end, "{}..{} of {:?} is empty in {:?}", start, end, text, raw);
+        }
+    }
+
+    #[test]
+    fn raw_scores_of_lowercased_text_are_character_offsets(
+        raw in "[a-zA-Zéß ]{1,20}",
+        start in 0usize..20,
+        len in 1usize..20,
+    ) {
+        let (text, char_indices) = lowercase_with_offsets(&raw);
+        let bytes = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect::<Vec<_>>();
+        // matches are never empty
+        let start = start.min(bytes.len() - 2);
+        let end = (start + len).min(bytes.len() - 1);
+        let offset = Offset { start: bytes[start], end: bytes[end] };
+        let score = raw_score(&char_indices, Score { score: 0, offset });
+        prop_assert_eq!((score.offset.start, score.offset.end), (start, end));
+    }
+
+    #[test]
+    fn known_words_map_back_to_the_raw_query(word in prop::sample::select(VOCABULARY.to_vec())) {
+        intern_vocabulary();
+        let raw = format!["Hotels in {}", word.to_uppercase()];
+        let normalizer = Normalizer::default();
+        let (_, char_indices) = normalizer.with_offsets(&raw);
+        let st = parse_query(raw.clone(), None, 5, 2, &stop_words(), &normalizer);
+        let score = st.match_str(word).expect("a word of the query should match");
+        let score = raw_score(&char_indices, score);
+        let matched = raw
+            .chars()
+            .skip(score.offset.start)
+            .take(score.offset.end - score.offset.start)
+            .collect::<String>();
+        prop_assert_eq!(matched.to_lowercase(), word);
+    }
+}
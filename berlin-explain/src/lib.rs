@@ -0,0 +1,199 @@
+//! The explanation of a search score, and the representation of a
+//! location, as reported by both the Python bindings and the `berlin`
+//! command line, so that tooling analysing either needs only one parser.
+
+use berlin_core::location::Location;
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// How a location came to match the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchTier {
+    /// A name or code appearing verbatim in the query.
+    Exact,
+    /// A registered colloquial name.
+    Alias,
+    /// A name in another language.
+    AltName,
+    /// A name sounding like words of the query.
+    Phonetic,
+    /// Words of the query found inside a name.
+    Infix,
+    /// berlin-core's own scoring, with prefix and fuzzy matching.
+    Fuzzy,
+}
+
+/// A named change to the score.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Adjustment {
+    pub reason: String,
+    pub amount: i64,
+}
+
+/// The parts a score is made of. `total` is `base`, plus `boosts` and
+/// `graph_boost`, less `penalties`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ScoreBreakdown {
+    pub tier: MatchTier,
+    pub base: i64,
+    pub boosts: Vec<Adjustment>,
+    pub penalties: Vec<Adjustment>,
+    /// Fraction of the normalized query covered by the matched span.
+    pub coverage: f64,
+    /// Boost for parents (states, subdivisions) that also matched.
+    pub graph_boost: i64,
+    pub total: i64,
+}
+
+impl ScoreBreakdown {
+    pub fn new(tier: MatchTier, base: i64) -> Self {
+        ScoreBreakdown {
+            tier,
+            base,
+            boosts: vec![],
+            penalties: vec![],
+            coverage: 0.0,
+            graph_boost: 0,
+            total: base,
+        }
+    }
+
+    pub fn boost(mut self, reason: &str, amount: i64) -> Self {
+        self.total += amount;
+        self.boosts.push(Adjustment {
+            reason: reason.to_string(),
+            amount,
+        });
+        self
+    }
+
+    pub fn penalty(mut self, reason: &str, amount: i64) -> Self {
+        self.total -= amount;
+        self.penalties.push(Adjustment {
+            reason: reason.to_string(),
+            amount,
+        });
+        self
+    }
+
+    pub fn graph_boost(mut self, amount: i64) -> Self {
+        self.total += amount;
+        self.graph_boost = amount;
+        self
+    }
+
+    /// Record the matched span of `end - start` characters of a normalized
+    /// query of `length` characters.
+    pub fn coverage(mut self, start: usize, end: usize, length: usize) -> Self {
+        self.coverage = match length {
+            0 => 0.0,
+            _ => end.saturating_sub(start) as f64 / length as f64,
+        };
+        self
+    }
+
+    /// The JSON schema of an explanation.
+    pub fn schema() -> RootSchema {
+        schema_for!(ScoreBreakdown)
+    }
+}
+
+/// The share of the words of a normalized query, other than stop words,
+/// that lie within the span `start..end` of a match. A match on one word of
+/// a long query covers little of it, and may be a coincidence. A query of
+/// stop words alone is fully covered.
+pub fn word_coverage(
+    normalized: &str,
+    start: usize,
+    end: usize,
+    is_stop_word: impl Fn(&str) -> bool,
+) -> f64 {
+    let words = normalized
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !is_stop_word(word))
+        .map(|word| {
+            let offset = word.as_ptr() as usize - normalized.as_ptr() as usize;
+            (offset, offset + word.len())
+        })
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return 1.0;
+    }
+    let covered = words
+        .iter()
+        .filter(|(word_start, word_end)| start <= *word_start && *word_end <= end)
+        .count();
+    covered as f64 / words.len() as f64
+}
+
+/// A location as output by the Python bindings and the command line. The
+/// brief form has its key, names, codes and state and subdivision codes;
+/// the full form adds the fields of its type, such as coordinates or
+/// function codes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LocationRepr {
+    pub key: String,
+    pub encoding: String,
+    pub id: String,
+    pub names: Vec<String>,
+    pub codes: Vec<String>,
+    pub state: String,
+    pub subdiv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl LocationRepr {
+    pub fn brief(loc: &Location) -> Self {
+        LocationRepr {
+            key: loc.key.to_string(),
+            encoding: loc.encoding.to_string(),
+            id: loc.id.to_string(),
+            names: loc
+                .get_names()
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            codes: loc
+                .get_codes()
+                .iter()
+                .map(|code| code.to_string())
+                .collect(),
+            state: loc.get_state().to_string(),
+            subdiv: loc.get_subdiv().map(|subdiv| subdiv.to_string()),
+            data: None,
+        }
+    }
+
+    pub fn full(loc: &Location) -> Self {
+        // the data serializes as an object of one entry, by type
+        let data = serde_json::to_value(loc.data)
+            .ok()
+            .and_then(|data| data.as_object()?.values().next().cloned());
+        LocationRepr {
+            data,
+            ..LocationRepr::brief(loc)
+        }
+    }
+
+    /// Set the coordinates in the data of the full form, as a latitude and
+    /// longitude, in place of the unparsed coordinates of berlin-core, or
+    /// leave them out if there are none.
+    pub fn with_coordinates(mut self, coordinates: Option<(f64, f64)>) -> Self {
+        if let Some(data) = self.data.as_mut().and_then(|data| data.as_object_mut()) {
+            match coordinates {
+                Some((lat, lon)) => data.insert("coordinates".to_string(), json!([lat, lon])),
+                None => data.remove("coordinates"),
+            };
+        }
+        self
+    }
+
+    /// The JSON schema of a location.
+    pub fn schema() -> RootSchema {
+        schema_for!(LocationRepr)
+    }
+}
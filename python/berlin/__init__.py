@@ -1,7 +1,13 @@
 from berlin._berlin import (
     load as load,
     load_from_json as load_from_json,
-    Location as Location
+    load_from_csv as load_from_csv,
+    score_breakdown_schema as score_breakdown_schema,
+    location_schema as location_schema,
+    parse_query as parse_query,
+    Location as Location,
+    LoadError as LoadError
 )
+from berlin.data import fetch_data as fetch_data
 
 __version__ = "0.3.15"
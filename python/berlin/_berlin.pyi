@@ -0,0 +1,430 @@
+# Generated from src/lib.rs by scripts/generate_stubs.py; do not edit.
+from typing import Any, Callable, Dict, Iterable, List, Optional, Tuple, Union
+
+# A block of locations, as a JSON string or the parsed dict
+Block = Union[str, Dict[str, Any]]
+# Called as on_progress(stage, done, total) while loading
+OnProgress = Callable[[str, int, Optional[int]], Any]
+
+class LoadError(TypeError):
+    """Raised when records of location data cannot be parsed, with `errors`, a
+    list of dicts of the block (or file) and the index (for rows) or key
+    (for JSON) of each bad record, the field at fault if known, and the
+    message.
+    """
+    errors: List[Dict[str, Any]]
+
+class LocationsDbProxy:
+    slow_query_ms: Optional[float]
+    strict_threshold: int
+    def retrieve(self, term: str) -> Location: ...
+    def retrieve_many(self, keys: List[str]) -> Any:
+        """Look up many keys at once, as `retrieve` does, returning a dict from
+        each key to its location, or to None if it is not found.
+        """
+    def retrieve_by_int_id(self, int_id: int) -> Location: ...
+    def get_children(self, key: str) -> List[Location]: ...
+    def get_descendants(self, key: str, depth: Optional[int] = None) -> List[Location]:
+        """Every location under `key`, level by level, down to `depth` levels
+        below it (or to the bottom).
+        """
+    def states_by_continent(self, continent: str) -> List[Location]:
+        """The states on a continent, such as "eu", in key order."""
+    def subdivisions_of(self, state: str) -> List[Location]:
+        """The subdivisions of a state, such as "gb", in key order."""
+    def locodes_of(self, state: str, subdiv: Optional[str] = None) -> List[Location]:
+        """The LOCODEs of a state, such as "gb", in key order, only those in the
+        subdivision with code `subdiv`, such as "abd", if given.
+        """
+    def is_within(self, key: str, ancestor: str) -> bool:
+        """Whether the location `key` lies inside the state or subdivision
+        `ancestor`, by the hierarchy or, for a location placed in no
+        subdivision, by the subdivision of the nearest location, as when
+        checking a (LOCODE, country) pair.
+        """
+    def get_subtree(self, key: str) -> Any:
+        """The hierarchy under `key` as nested dicts, each with the key,
+        encoding, id and name of a location and a list of its children.
+        """
+    def save_int_ids(self, path: Any) -> None:
+        """Write the integer id of every location, and of every location
+        removed since loading, as a JSON object from key to id. Placed in the
+        data directory as `int-ids.json`, it makes `load` give locations the
+        same ids again.
+        """
+    def load_int_ids(self, path: Any) -> None:
+        """Assign integer ids as written by `save_int_ids`, as `load` does with
+        `int-ids.json` in the data directory.
+        """
+    def get_int_id(self, key: str) -> int: ...
+    def add_location(self, encoding: str, id: str, fields: Dict[str, Any]) -> Location: ...
+    def queue_location(self, encoding: str, id: str, fields: Dict[str, Any]) -> None:
+        """Queue a location to be added, or to replace the location with the
+        same key, in the background. Queued changes are applied in batches,
+        without blocking queries while the indexes are rebuilt.
+        """
+    def queue_alias(self, key: str, name: str) -> None:
+        """Queue an alias to be added in the background. It is dropped if the
+        location does not exist by the time it is applied.
+        """
+    def queue_removal(self, key: str) -> None:
+        """Queue a location, and every location under it, to be removed in the
+        background.
+        """
+    def flush_updates(self) -> None:
+        """Wait until every queued change has been applied."""
+    def update_stats(self) -> Any:
+        """The number of queued changes, the number applied (or failed) so far,
+        and when a batch was last applied, as a Unix timestamp.
+        """
+    def add_abbreviation(self, short: str, long: str) -> None:
+        """Expand the word `short`, such as "St" or "N.", to `long` in queries,
+        as with the built-in abbreviations. Names already in the database
+        keep their form; to expand those too, pass the abbreviation to
+        `load` in `normalization`.
+        """
+    def add_transliteration(self, native: str, name: str) -> int:
+        """Register `native`, a name in another script such as "Москва", as a
+        name of each location named `name` in the data. Returns the number
+        of locations named.
+        """
+    def add_alias(self, key: str, name: str) -> None:
+        """Register another name for a location. Aliases are only matched
+        exactly, after normalization, never fuzzily.
+        """
+    def countries(self) -> List[Tuple[str, str, str, str]]:
+        """Every state as an `(alpha2, alpha3, name, key)` tuple, in alpha-2 order."""
+    def load_report(self) -> List[Any]:
+        """Coordinates that were corrected, dropped or flagged on load, each as a
+        dict with the key, the original coordinates, any correction, whether
+        they were dropped and a reason.
+        """
+    def add_tag(self, key: str, tag: str) -> None: ...
+    def set_importance(self, importance: Dict[str, float]) -> None:
+        """Set the importance of locations, such as their population or port
+        size, from a dict of key to importance. Each tenfold increase adds
+        `importance_weight` to the score of a location in search results.
+        """
+    @property
+    def importance_weight(self) -> float: ...
+    @importance_weight.setter
+    def importance_weight(self, weight: float) -> None: ...
+    def remove_tag(self, key: str, tag: str) -> None: ...
+    def load_tags(self, path: str) -> None:
+        """Tag locations from a JSON file mapping location keys to lists of tags."""
+    def load_external_ids(self, path: str) -> None:
+        """Load GeoNames IDs and Wikidata QIDs of locations from a TSV file with
+        columns key, geonames and wikidata.
+        """
+    def configure_graph(self, enabled: Optional[bool] = None, edge_threshold: Optional[int] = None, state_share: Optional[float] = None, subdiv_share: Optional[float] = None, locode_share: Optional[float] = None, generic_share: Optional[float] = None, chains: Optional[bool] = None) -> None:
+        """Change how results whose state or subdivision also matched are
+        boosted: whether at all, the score above which both must be, the
+        share of the parent's score added, by the parent's kind, and whether
+        the boosts of a location's state and subdivision add up when both
+        matched, rather than the larger alone counting.
+        Settings left as None are kept.
+        """
+    @property
+    def graph_config(self) -> Any:
+        """How results are boosted by parents that also matched, as a dict of
+        the settings of `configure_graph`.
+        """
+    @property
+    def version(self) -> Optional[Any]:
+        """The release of the data, from the version.json in the data directory,
+        as a dict of "version" and "date", or None if it records none.
+        """
+    def load_rewrites(self, path: str) -> None:
+        """Rewrite every query by the rules in a JSON file: "drop", a list of
+        words dropped as stop words, "synonyms", from words to what they are
+        searched for as, such as {"nyc": "new york"}, and "states", from
+        words to the state searched within when they appear, such as
+        {"scotland": "gb"}. These replace any rules already loaded.
+        """
+    def set_rewriter(self, rewriter: Optional[Any] = None) -> None:
+        """Call `rewriter` on each query before `query` searches for it, with a
+        dict of the "query", its "normalized" text and "words", and the
+        "state" asked for. It returns None, or a dict of words to "drop",
+        "synonyms" from words to what they are searched for as, and a
+        "state" to search within in place of the one asked for. These apply
+        on top of any rewrite rules. None removes the hook.
+        """
+    def get_stop_words(self) -> List[str]: ...
+    def set_stop_words(self, stop_words: List[str]) -> None: ...
+    def get_state_key(self, state: str) -> str:
+        """The key of a state by its ISO 3166-1 alpha-2, alpha-3 or numeric code."""
+    def resolve_country(self, code: str) -> Location:
+        """The state with an ISO 3166-1 alpha-2, alpha-3 or numeric code, such
+        as "GB", "GBR" or "826".
+        """
+    def get_subdiv_key(self, state: str, subdiv: str) -> str: ...
+    def query(self, query: str, limit: int, lev_distance: int, state: Optional[str] = None, exact: bool = False, candidate_limit: Optional[int] = None, tags: Optional[List[str]] = None, phonetic: bool = False, infix: bool = False, dedupe: bool = False, group_by: Optional[str] = None, detect_language: bool = False, extract: bool = False, strict: bool = False, context: Optional[List[str]] = None, continent: Optional[str] = None, timing: bool = False, parent_boosts: bool = False, graph: bool = True) -> Any:
+        """Search for up to `limit` distinct locations in the query. Internally,
+        `candidate_limit` ranked candidates are considered before truncating.
+        If `tags` are given, only locations carrying all of them are returned.
+        With `phonetic`, names that sound like words in the query also match,
+        and with `infix`, names containing words of the query. With `dedupe`,
+        results for the same place, such as an airport and the LOCODE it
+        serves, are returned once, with the others in `also`. Result offsets
+        are characters of `query` as given. With `group_by` of "state" or
+        "subdiv", results come back as a dict from the key of each state or
+        subdivision to its results, best first, with None for results
+        outside any subdivision. With `detect_language`, the stop words of
+        the language the query appears to be in are dropped too, and each
+        result reports that language as `query_language`. With `extract`,
+        the results are up to `limit` separate locations mentioned in the
+        query, with spans that do not overlap, in order of mention, as for
+        "flights from Dublin to Berlin". With `strict`, results whose only
+        evidence is one fuzzily matched word, scoring below
+        `strict_threshold`, are dropped, so that "poverty" returns nothing.
+        Results right after one of the `context` words, such as "near",
+        "based in" or "travelling to", are boosted. With `continent`, such as
+        "eu", only locations in states on that continent are returned.
+        With `timing`, a tuple of the results and a dict of the milliseconds
+        taken by each phase of the query is returned instead. With
+        `parent_boosts`, each result's `parent_boost` tells which matching
+        parent boosted it, so that "London, GB" reads as one interpretation.
+        Without `graph`, no result is boosted by its parents, which saves
+        time on latency-critical queries.
+        """
+    def to_texts(self, keys: List[str], template: Optional[str] = None) -> List[str]:
+        """Render locations as text, parsing the template once. Fields are
+        written in braces ({name}, {subdiv}, {state}, {encoding}, {id}, {key},
+        {code}, {state_code}, {subdiv_code}), and a group in square brackets
+        is left out if any field in it is empty.
+        """
+    def query_geojson(self, query: str, limit: int, lev_distance: int, state: Optional[str] = None, exact: bool = False, tags: Optional[List[str]] = None, strict: bool = False, continent: Optional[str] = None) -> Any:
+        """Search as `query` does, returning the results as a GeoJSON
+        FeatureCollection, as a dict. Each feature has a point geometry where
+        the location has coordinates, or none, and the location's fields,
+        score and span as properties. For other options of `query`, pass its
+        results to `to_geojson`.
+        """
+    def to_geojson(self, locations: List[Location]) -> Any:
+        """Locations, such as the results of `query`, as a GeoJSON
+        FeatureCollection, as a dict.
+        """
+    def index_stats(self, top: int = 10) -> Any:
+        """How the index is made up: the number of indexed words, the mean
+        number of locations per word, and the `top` most ambiguous words
+        with their location counts. Very ambiguous words make for large
+        candidate sets, and may be better as stop words.
+        """
+    def detect_language(self, query: str) -> Optional[str]:
+        """The probable language of a query, as an ISO 639-1 code, from its
+        script or, for Latin script, its stop words and letters. None if
+        nothing suggests one.
+        """
+    def normalize(self, text: str) -> str:
+        """The text as queries are normalized before searching, including the
+        database's folds and expansions.
+        """
+    def capabilities(self) -> Any:
+        """The indexes searches use, as a dict from name ("fst", "exact",
+        "phonetic" and "infix") to whether it is present, its number of
+        entries, its approximate size in bytes, how long it took to build and
+        whether it is on disk. The infix index is built by the first search
+        with `infix`.
+        """
+    def move_index_to_disk(self, dir: Any) -> None:
+        """Move the word index into memory-mapped files in `dir`, as `load` does
+        with `low_memory`, so that it need not all be held in memory.
+        """
+    def set_cache(self, capacity: int, ttl: Optional[float] = None) -> None:
+        """Keep the results of up to `capacity` recent fuzzy searches, each for
+        at most `ttl` seconds if given, so that repeated queries are not
+        searched again. A `capacity` of 0 stops caching. Any change to the
+        database invalidates the cache.
+        """
+    def cache_info(self) -> Optional[Any]:
+        """How the result cache is doing, as a dict of its hits, misses,
+        current size, capacity and ttl in seconds, or None if not caching.
+        """
+    def autocomplete(self, prefix: str, limit: int = 10, state: Optional[str] = None) -> List[Tuple[str, str]]:
+        """Complete a prefix typed into a search box, returning (key, name)
+        pairs of the locations with a name, word or code starting with it.
+        """
+    def explain(self, query: str, key: str, lev_distance: int, state: Optional[str] = None, phonetic: bool = False, infix: bool = False) -> Any:
+        """Explain the score the location `key` gets for the query, as a dict
+        following `score_breakdown_schema()`, or None if it is not found.
+        """
+    def search_text(self, document: str, lev_distance: int = 2, state: Optional[str] = None, window_limit: int = 3, threshold: int = 0) -> List[Location]:
+        """Find the locations mentioned anywhere in a long document, returning
+        each once, in order of mention. Up to `window_limit` locations are
+        taken from each window of the document, and offsets are characters
+        of the document.
+        """
+    def false_positives(self, corpus: List[str], lev_distance: int, threshold: int = 0, state: Optional[str] = None) -> List[Any]: ...
+    def list(self, encoding: Optional[str] = None, state: Optional[str] = None, subdiv: Optional[str] = None) -> List[Location]: ...
+    def list_by_key(self, encoding: Optional[str] = None, state: Optional[str] = None, subdiv: Optional[str] = None) -> List[str]: ...
+
+class Location:
+    key: str
+    encoding: str
+    id: str
+    words: List[str]
+    def __getattr__(self, attr: str) -> Any: ...
+    def __repr__(self) -> str: ...
+    def __str__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __ne__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+    def get_score(self) -> int: ...
+    def get_offset(self) -> Tuple[Any, ...]: ...
+    def get_names(self) -> Any: ...
+    def get_alt_names(self) -> Dict[str, List[str]]: ...
+    @property
+    def also(self) -> List[Location]:
+        """Other results for the same place, if the search was deduplicated."""
+    def to_text(self, template: Optional[str] = None) -> str:
+        """Render the location as text, such as "Stonehaven, Aberdeenshire,
+        United Kingdom — UN-LOCODE GB SVN", for display or for embedding
+        models. See `LocationsDb.to_texts` for templates.
+        """
+    def to_dict(self, full: bool = False) -> Any:
+        """The location as a dict of its key, encoding, id, names, codes and
+        state and subdivision codes, as the `berlin` command line outputs
+        it. With `full`, "data" holds the fields of its type too.
+        """
+    @property
+    def loc_type(self) -> str:
+        """The kind of location: "state", "subdivision", "locode", "airport",
+        "nuts", "fips", "postal" or "generic".
+        """
+    @property
+    def nuts_level(self) -> Optional[int]:
+        """The level of a NUTS region, from 1 to 3."""
+    @property
+    def continent(self) -> Any: ...
+    @property
+    def alpha3(self) -> Any: ...
+    @property
+    def level(self) -> Any: ...
+    @property
+    def function_code(self) -> Any: ...
+    @property
+    def status(self) -> Optional[str]:
+        """The LOCODE's status in the code list, such as "AI" or "RL"."""
+    @property
+    def date(self) -> Optional[str]:
+        """When the LOCODE was last changed in the code list, as "YYMM"."""
+    @property
+    def city(self) -> Any: ...
+    @property
+    def region(self) -> Any: ...
+    @property
+    def elevation(self) -> Any: ...
+    @property
+    def airport_type(self) -> Any: ...
+    @property
+    def coordinates(self) -> Optional[Tuple[float, float]]:
+        """`(lat, lon)` in degrees, north and east positive, if known."""
+    def distance_to(self, other: Location) -> Optional[float]:
+        """The great-circle distance to another location in kilometres, or None
+        if either lacks coordinates.
+        """
+    @property
+    def tags(self) -> List[str]: ...
+    @property
+    def external_ids(self) -> Dict[str, str]:
+        """Identifiers of the location in other gazetteers, as a dict with any
+        of "geonames" and "wikidata".
+        """
+    @property
+    def importance(self) -> Optional[float]: ...
+    @property
+    def matched_language(self) -> Optional[str]: ...
+    @property
+    def query_language(self) -> Optional[str]:
+        """The language detected in the query, if the search asked for it."""
+    @property
+    def coverage(self) -> Optional[float]:
+        """The share of the words of the query, other than stop words, within
+        the span this location matched, from 0 to 1. None if the location
+        did not come from `query`.
+        """
+    @property
+    def parent_boost(self) -> Optional[Tuple[str, int]]:
+        """The key of the parent, also matched by the query, that boosted this
+        location's score, and the boost, as a tuple. None if no parent did,
+        or if `query` was not asked for `parent_boosts`.
+        """
+    def get_codes(self) -> Any: ...
+    def get_state_code(self) -> str: ...
+    def get_subdiv_code(self) -> Optional[str]: ...
+    @property
+    def int_id(self) -> int: ...
+    @property
+    def children(self) -> List[Location]: ...
+    @property
+    def parent(self) -> Optional[Location]:
+        """The location this one sits directly under: its subdivision or state."""
+    @property
+    def ancestors(self) -> List[Location]:
+        """The locations this one sits under, nearest first, ending with its
+        state.
+        """
+    @property
+    def siblings(self) -> List[Location]:
+        """The other locations under the same parent."""
+    @property
+    def state(self) -> Location: ...
+    @property
+    def subdiv(self) -> Optional[Location]: ...
+
+def load(data_dir: str, on_progress: Optional[OnProgress] = None, files: Optional[List[Any]] = None, low_memory: bool = False, index_dir: Optional[Any] = None, normalization: Optional[Dict[str, Any]] = None) -> LocationsDbProxy:
+    """Load a database from the data files, stop words, aliases,
+    transliterations, tags, query rewrite rules and version in `data_dir`.
+    The data files are those listed in `files`, or else in the directory's
+    data-files.json, as paths relative to it or (kind, path) pairs, with
+    kinds "json", "code-list" or a table of `load_from_csv`. Otherwise they
+    are the default files and any regional files such as ISO-3166-2:GB.json.
+    `on_progress(stage, done, total)` is called after each data file is
+    read, and around the final build. With `low_memory`, the word index is
+    moved into memory-mapped files in `index_dir`, or else in a temporary
+    directory. `normalization` is a dict of "folding", from text to what it
+    is replaced by wherever it appears, such as {"ø": "oe"}, and
+    "expansions", from words to what they stand for, such as {"st":
+    "saint"}. Both names and queries are normalized with them. A warning is
+    logged if the directories of the data files record different versions.
+    Raises FileNotFoundError listing any missing files.
+    """
+
+def load_from_json(blocks: Iterable[Union[Block, Iterable[Block]]], rows: List[Dict[str, str]], on_progress: Optional[OnProgress] = None, normalization: Optional[Dict[str, Any]] = None) -> LocationsDbProxy:
+    """Load a database from JSON blocks and LOCODE rows. Each block maps keys
+    to locations, and is given either as a JSON string or as the parsed
+    dict. The blocks may be any iterable, such as a generator, of blocks or
+    of iterables of blocks; each is decoded and released before the next is
+    read. `on_progress(stage, done, total)` is called after each item of
+    `blocks`, with a total of None if it has no length, and around the final
+    build. `normalization` customizes how names and queries are normalized,
+    as for `load`.
+    """
+
+def load_from_csv(files: Dict[str, str], on_progress: Optional[OnProgress] = None, normalization: Optional[Dict[str, Any]] = None) -> LocationsDbProxy:
+    """Load a database from CSV files, given as a dict from table to path. The
+    tables are "state" (with columns alpha2, alpha3, name, short and
+    continent), "subdivision" (supercode, subcode, name and level), "iata"
+    (iata, name, type, city, country, region, x, y and elevation), "nuts"
+    (code, name and subdivision, such as "GB-SCT"), "fips" (code, name and
+    subdivision, such as "US-CA"), "postal" (country, code, name,
+    subdivision, lat and lon) and "locode", the UN/LOCODE code list as
+    published. Other columns, such as "name:fr", are kept as fields.
+    `on_progress(stage, done, total)` is called after each file is read, and
+    around the final build. `normalization` customizes how names and queries
+    are normalized, as for `load`.
+    """
+
+def score_breakdown_schema() -> Any:
+    """The JSON schema of the explanations returned by `explain`."""
+
+def location_schema() -> Any:
+    """The JSON schema of the dicts returned by `Location.to_dict`."""
+
+def parse_query(query: str, state: Optional[str] = None) -> Any:
+    """How `query` is parsed before searching, with the default stop words and
+    normalization, as a dict of the normalized query, the recognized state
+    and codes, the terms matched exactly and fuzzily, and the stop words.
+    Terms are only exact if some loaded name or code is spelled that way.
+    Offsets refer to the normalized query.
+    """
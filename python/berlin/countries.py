@@ -0,0 +1,58 @@
+"""Countries of a loaded database as a Python enum.
+
+`country_enum(db)` builds the enum at runtime. For code that should be
+checked statically, `scripts/generate_countries.py` writes the same enum
+out as a module at build time, using `render_country_module`.
+"""
+
+from enum import Enum
+
+
+class CountryBase(Enum):
+    """Members have the value `(alpha2, alpha3, name, key)`."""
+
+    @property
+    def alpha2(self):
+        return self.value[0]
+
+    @property
+    def alpha3(self):
+        return self.value[1]
+
+    @property
+    def country_name(self):
+        return self.value[2]
+
+    @property
+    def key(self):
+        return self.value[3]
+
+    @classmethod
+    def from_key(cls, key):
+        for country in cls:
+            if country.key == key:
+                return country
+        raise KeyError(f"{key} is not a country key")
+
+    @classmethod
+    def from_alpha3(cls, alpha3):
+        for country in cls:
+            if country.alpha3 == alpha3.upper():
+                return country
+        raise KeyError(f"{alpha3} is not a country code")
+
+
+def country_enum(db, name="Country"):
+    return CountryBase(name, {c[0]: c for c in db.countries()})
+
+
+def render_country_module(db, name="Country"):
+    lines = [
+        "# Generated by scripts/generate_countries.py. Do not edit.",
+        "from berlin.countries import CountryBase",
+        "",
+        "",
+        f"class {name}(CountryBase):",
+    ]
+    lines += [f"    {c[0]} = {c!r}" for c in db.countries()]
+    return "\n".join(lines) + "\n"
@@ -0,0 +1,98 @@
+"""Download the data files that `load` reads.
+
+A data source is a base URL with a directory per version, each holding the
+data files and a `manifest.json` of the form
+
+    {"version": "2024-1", "files": {"state.json": "<sha256>", ...}}
+
+The `latest` directory holds the newest version, with its real name in the
+manifest. Files are checked against their checksums and cached by version,
+so fetching the same version again downloads nothing.
+"""
+
+import hashlib
+import json
+import os
+import shutil
+import sys
+import tempfile
+from pathlib import Path
+from urllib.parse import quote
+from urllib.request import urlopen
+
+DATA_URL_VARIABLE = "BERLIN_DATA_URL"
+CACHE_DIR_VARIABLE = "BERLIN_CACHE_DIR"
+
+CHUNK_SIZE = 1 << 20
+
+
+def cache_dir():
+    """The directory downloads are cached in: `$BERLIN_CACHE_DIR` if set, or
+    else the platform's user cache directory."""
+    if os.environ.get(CACHE_DIR_VARIABLE):
+        return Path(os.environ[CACHE_DIR_VARIABLE])
+    if sys.platform == "win32":
+        base = Path(os.environ.get("LOCALAPPDATA", Path.home() / "AppData" / "Local"))
+    elif sys.platform == "darwin":
+        base = Path.home() / "Library" / "Caches"
+    else:
+        base = Path(os.environ.get("XDG_CACHE_HOME") or Path.home() / ".cache")
+    return base / "berlin"
+
+
+def sha256(path):
+    digest = hashlib.sha256()
+    with open(path, "rb") as f:
+        for chunk in iter(lambda: f.read(CHUNK_SIZE), b""):
+            digest.update(chunk)
+    return digest.hexdigest()
+
+
+def download(url, path, checksum):
+    # written alongside, checked and renamed, so that an interrupted or
+    # corrupt download never leaves a file in the cache
+    path.parent.mkdir(parents=True, exist_ok=True)
+    f = tempfile.NamedTemporaryFile(dir=path.parent, delete=False)
+    try:
+        with f, urlopen(url) as response:
+            shutil.copyfileobj(response, f, CHUNK_SIZE)
+        if sha256(f.name) != checksum:
+            raise ValueError(f"{path.name} does not match its checksum in version {path.parent.name}")
+        os.replace(f.name, path)
+    except BaseException:
+        os.unlink(f.name)
+        raise
+
+
+def check_name(name):
+    # names come from the manifest, and must not reach outside the cache or
+    # the destination directory
+    if Path(name).name != name or name in ("", ".", ".."):
+        raise ValueError(f"{name!r} is not a plain file name")
+    return name
+
+
+def fetch_data(dest_dir, version=None, url=None):
+    """Download a version of the data files (the latest if None) from `url`,
+    or else from `$BERLIN_DATA_URL`, into `dest_dir`, ready for `load`.
+    Raises ValueError if a file does not match its checksum."""
+    url = url or os.environ.get(DATA_URL_VARIABLE)
+    if not url:
+        raise ValueError(f"No data source given; pass url or set {DATA_URL_VARIABLE}")
+    version_url = f"{url.rstrip('/')}/{quote(version or 'latest')}"
+    with urlopen(f"{version_url}/manifest.json") as response:
+        manifest = json.load(response)
+
+    check_name(manifest["version"])
+    for name in manifest["files"]:
+        check_name(name)
+
+    cached = cache_dir() / manifest["version"]
+    dest_dir = Path(dest_dir)
+    dest_dir.mkdir(parents=True, exist_ok=True)
+    for name, checksum in manifest["files"].items():
+        path = cached / name
+        if not path.exists() or sha256(path) != checksum:
+            download(f"{version_url}/{quote(name)}", path, checksum)
+        shutil.copyfile(path, dest_dir / name)
+    return dest_dir
@@ -0,0 +1,60 @@
+"""Time the graph analysis with and without truncating its candidates.
+
+Broad queries match thousands of candidates, of which only a multiple of the
+limit per encoding are put into the graph. This compares that against
+putting every candidate in, and checks that both give the same scores in
+the same order. Results of equal score may come in either order.
+
+    python scripts/bench_graph.py [--locations 17576] [--repeat 20]
+"""
+import argparse
+import json
+import statistics
+import time
+
+from berlin import load_from_json
+
+from bench_results import TEST_DATA_DIR, synthetic_locodes
+
+QUERIES = [
+    ("single word", "port", 10),
+    ("two words", "saint bridge", 10),
+    ("with a subdivision", "port aberdeenshire", 10),
+    ("high limit", "port", 100),
+]
+# more candidates than any query here matches, so none are truncated
+UNTRUNCATED = 1_000_000
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--locations", type=int, default=17576)
+    parser.add_argument("--repeat", type=int, default=20)
+    args = parser.parse_args()
+
+    codes = json.loads((TEST_DATA_DIR / "test-codes.json").read_text())
+    codes.update(synthetic_locodes(args.locations))
+    started = time.perf_counter()
+    db = load_from_json([[codes]], [])
+    print(f"built {args.locations} locations in {time.perf_counter() - started:.2f}s")
+
+    for label, query, limit in QUERIES:
+        ranked = {}
+        for mode, candidate_limit in (("truncated", None), ("untruncated", UNTRUNCATED)):
+            totals, graph = [], []
+            for _ in range(args.repeat):
+                started = time.perf_counter()
+                results, timings = db.query(query, limit, 2, candidate_limit=candidate_limit, timing=True)
+                totals.append((time.perf_counter() - started) * 1000)
+                graph.append(timings["graph"])
+            ranked[mode] = [(loc.get_score(), loc.get_offset()) for loc in results]
+            print(
+                f"{label:20} {mode:12}"
+                f"  median {statistics.median(totals):8.2f}ms"
+                f"  graph {statistics.median(graph):8.2f}ms"
+            )
+        print(f"{'':20} same scores: {ranked['truncated'] == ranked['untruncated']}")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,90 @@
+"""Time queries returning many results, against a synthetic database.
+
+Building a result costs more than finding it once limits run into the
+hundreds, so this measures the whole of `query`, from search to the list of
+`Location` objects.
+
+    python scripts/bench_results.py [--locations 17576] [--repeat 20]
+
+There are at most 17,576 synthetic locations, one per three-letter code.
+"""
+import argparse
+import itertools
+import json
+import statistics
+import string
+import time
+from pathlib import Path
+
+from berlin import load_from_json
+
+TEST_DATA_DIR = Path(__file__).parent.parent / "tests" / "data"
+WORDS = ["port", "saint", "new", "upper", "lower", "north", "south", "bridge", "mill", "green"]
+QUERIES = [
+    ("single word, high limit", "port", 1000),
+    ("two words, high limit", "saint bridge", 1000),
+    ("single word, low limit", "port", 10),
+]
+
+
+def synthetic_locodes(count):
+    codes = ("".join(code) for code in itertools.product(string.ascii_uppercase, repeat=3))
+    locodes = {}
+    for n, code in zip(range(count), codes):
+        words = [WORDS[n % len(WORDS)], WORDS[n // len(WORDS) % len(WORDS)], f"place{n}"]
+        state, subdiv = ("GB", "ABD") if n % 2 else ("BG", "02")
+        locodes[f"{state}:{code}"] = {
+            "<c>": "UN-LOCODE",
+            "i": f"{state}:{code}",
+            "d": {
+                "name": " ".join(words).title(),
+                "supercode": state,
+                "subcode": code,
+                "subdivision_code": subdiv,
+                "function_code": "1-------",
+            },
+        }
+    return locodes
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--locations", type=int, default=17576)
+    parser.add_argument("--repeat", type=int, default=20)
+    args = parser.parse_args()
+
+    codes = json.loads((TEST_DATA_DIR / "test-codes.json").read_text())
+    codes.update(synthetic_locodes(args.locations))
+    started = time.perf_counter()
+    db = load_from_json([[codes]], [])
+    print(f"built {args.locations} locations in {time.perf_counter() - started:.2f}s")
+
+    for label, query, limit in QUERIES:
+        totals, building = [], []
+        for _ in range(args.repeat):
+            started = time.perf_counter()
+            results, timings = db.query(query, limit, 2, timing=True)
+            totals.append((time.perf_counter() - started) * 1000)
+            building.append(timings["results"])
+        print(
+            f"{label:26} {len(results):5} results"
+            f"  median {statistics.median(totals):8.2f}ms"
+            f"  min {min(totals):8.2f}ms"
+            f"  building results {statistics.median(building):8.2f}ms"
+        )
+
+    # listing descendants builds a result for each, with no search at all
+    totals = []
+    for _ in range(args.repeat):
+        started = time.perf_counter()
+        results = db.get_descendants("ISO-3166-1-gb")
+        totals.append((time.perf_counter() - started) * 1000)
+    print(
+        f"{'listing descendants':26} {len(results):5} results"
+        f"  median {statistics.median(totals):8.2f}ms"
+        f"  min {min(totals):8.2f}ms"
+    )
+
+
+if __name__ == "__main__":
+    main()
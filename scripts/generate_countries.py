@@ -0,0 +1,13 @@
+"""Write a Country enum for the states in a data directory.
+
+Usage: python generate_countries.py DATA_DIR OUTPUT
+"""
+import sys
+from pathlib import Path
+
+import berlin
+from berlin.countries import render_country_module
+
+data_dir, output = sys.argv[1:]
+db = berlin.load(data_dir)
+Path(output).write_text(render_country_module(db))
@@ -0,0 +1,279 @@
+"""Write type stubs for the _berlin extension module, from the signatures of
+the classes and functions it exports in src/lib.rs.
+
+Usage: python generate_stubs.py [LIB_RS [OUTPUT]]
+"""
+import re
+import sys
+from pathlib import Path
+
+ROOT = Path(__file__).parent.parent
+
+HEADER = """\
+# Generated from src/lib.rs by scripts/generate_stubs.py; do not edit.
+from typing import Any, Callable, Dict, Iterable, List, Optional, Tuple, Union
+
+# A block of locations, as a JSON string or the parsed dict
+Block = Union[str, Dict[str, Any]]
+# Called as on_progress(stage, done, total) while loading
+OnProgress = Callable[[str, int, Optional[int]], Any]
+"""
+
+SIMPLE_TYPES = {
+    "String": "str",
+    "str": "str",
+    "Ustr": "str",
+    "bool": "bool",
+    "f32": "float",
+    "f64": "float",
+    "PyObject": "Any",
+    "PyAny": "Any",
+    "PyDict": "Dict[str, Any]",
+    "PyList": "List[Any]",
+    "PyTuple": "Tuple[Any, ...]",
+}
+
+INTEGER_TYPES = {"u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize"}
+
+GENERIC_TYPES = {
+    "Option": "Optional",
+    "Vec": "List",
+    "HashMap": "Dict",
+}
+
+# Parameters that pyo3 fills in, rather than the caller
+IMPLICIT_PARAMS = {"self", "&self", "&mut self", "self: &Arc<Self>"}
+IMPLICIT_TYPES = {"Python"}
+
+# Attributes that classes serve from __getattr__
+DYNAMIC_ATTRIBUTES = {
+    "Location": [("key", "str"), ("encoding", "str"), ("id", "str"), ("words", "List[str]")],
+}
+
+# Attributes that exceptions are given when raised
+EXCEPTION_ATTRIBUTES = {
+    "LoadError": [("errors", "List[Dict[str, Any]]")],
+}
+
+# Arguments whose Rust type accepts more than it says
+ARGUMENT_TYPES = {
+    ("load_from_json", "blocks"): "Iterable[Union[Block, Iterable[Block]]]",
+    ("load_from_json", "on_progress"): "Optional[OnProgress]",
+    ("load", "on_progress"): "Optional[OnProgress]",
+    ("load_from_csv", "files"): "Dict[str, str]",
+    ("load_from_csv", "on_progress"): "Optional[OnProgress]",
+}
+
+
+def split_top_level(text, sep=","):
+    """Split on `sep` outside of brackets."""
+    parts, depth, start = [], 0, 0
+    for i, c in enumerate(text):
+        if c in "<([":
+            depth += 1
+        elif c in ">)]":
+            depth -= 1
+        elif c == sep and depth == 0:
+            parts.append(text[start:i])
+            start = i + 1
+    parts.append(text[start:])
+    return [part.strip() for part in parts if part.strip()]
+
+
+def python_type(rust, classes):
+    rust = re.sub(r"'\w+\s*", "", rust).strip()
+    rust = re.sub(r"^&\s*(mut\s+)?", "", rust).strip()
+    if rust.startswith("(") and rust.endswith(")"):
+        items = split_top_level(rust[1:-1])
+        if not items:
+            return "None"
+        return f"Tuple[{', '.join(python_type(item, classes) for item in items)}]"
+    match = re.fullmatch(r"(\w+)<(.*)>", rust, re.S)
+    if match:
+        name, args = match.group(1), split_top_level(match.group(2))
+        if name in ("PyResult", "Result"):
+            return python_type(args[0], classes)
+        if name in ("Py", "PyRef", "PyRefMut"):
+            return python_type(args[0], classes)
+        if name in GENERIC_TYPES:
+            mapped = ", ".join(python_type(arg, classes) for arg in args)
+            return f"{GENERIC_TYPES[name]}[{mapped}]"
+        return "Any"
+    if rust in classes:
+        return classes[rust]
+    if rust in INTEGER_TYPES:
+        return "int"
+    return SIMPLE_TYPES.get(rust, "Any")
+
+
+def python_default(rust):
+    return {"true": "True", "false": "False"}.get(rust, rust)
+
+
+class Item:
+    """A Rust function, with the attributes and doc comments before it."""
+
+    def __init__(self, attrs, docs, signature):
+        self.attrs = attrs
+        self.docs = docs
+        match = re.match(r"(?:pub\s+)?fn\s+(\w+)(?:<[^>]*>)?\s*\((.*)\)\s*(?:->\s*(.*?))?\s*\{?$", signature, re.S)
+        self.name, params, self.returns = match.group(1), match.group(2), match.group(3)
+        self.params = []
+        for param in split_top_level(params):
+            if param in IMPLICIT_PARAMS:
+                continue
+            name, rust = (part.strip() for part in param.split(":", 1))
+            if re.sub(r"<.*>", "", rust) in IMPLICIT_TYPES:
+                continue
+            self.params.append((name, rust))
+        # without a signature, pyo3 makes trailing Option arguments optional
+        self.defaults = {}
+        for name, rust in reversed(self.params):
+            if not rust.startswith("Option<"):
+                break
+            self.defaults[name] = "None"
+        for attr in attrs:
+            match = re.fullmatch(r"#\[pyo3\(signature\s*=\s*\((.*)\)\)\]", attr)
+            if match:
+                for param in split_top_level(match.group(1)):
+                    if "=" in param:
+                        name, default = (part.strip() for part in param.split("=", 1))
+                        self.defaults[name] = python_default(default)
+
+    def has_attr(self, name):
+        return any(attr == f"#[{name}]" for attr in self.attrs)
+
+
+def parse_items(lines):
+    """The functions among `lines`, skipping their bodies."""
+    items, attrs, docs = [], [], []
+    i = 0
+    while i < len(lines):
+        line = lines[i].strip()
+        if line.startswith("///"):
+            docs.append(line[3:].strip())
+        elif line.startswith("#["):
+            attrs.append(line)
+        elif re.match(r"(pub\s+)?fn\s", line):
+            signature = line
+            while not signature.rstrip().endswith("{"):
+                i += 1
+                signature += " " + lines[i].strip()
+            items.append(Item(attrs, docs, signature.rstrip()))
+            i = skip_block(lines, i)
+            attrs, docs = [], []
+            continue
+        elif line.endswith("{"):
+            i = skip_block(lines, i)
+            attrs, docs = [], []
+            continue
+        elif line and not line.startswith("//"):
+            attrs, docs = [], []
+        i += 1
+    return items
+
+
+def skip_block(lines, i):
+    """The index of the line after the block opened on line `i`."""
+    depth = 0
+    while True:
+        depth += lines[i].count("{") - lines[i].count("}")
+        i += 1
+        if depth <= 0:
+            return i
+
+
+def parse_module(source):
+    lines = source.splitlines()
+    classes, fields, methods = {}, {}, {}
+    for match in re.finditer(r"#\[pyclass(?:\(name\s*=\s*\"(\w+)\"\))?\]\s*(?:pub\s+)?struct\s+(\w+)\s*\{(.*?)\n\}", source, re.S):
+        name, rust_name, body = match.group(1), match.group(2), match.group(3)
+        classes[rust_name] = name or rust_name
+        fields[rust_name] = re.findall(r"#\[pyo3\(get(?:,\s*set)?\)\]\s*(?:pub\s+)?(\w+):\s*([^,\n]+),", body)
+    for i, line in enumerate(lines):
+        if line.strip() != "#[pymethods]":
+            continue
+        rust_name = re.match(r"impl\s+(\w+)", lines[i + 1].strip()).group(1)
+        end = skip_block(lines, i + 1)
+        methods.setdefault(rust_name, []).extend(parse_items(lines[i + 2 : end - 1]))
+    registered = re.findall(r"wrap_pyfunction!\((\w+)", source)
+    functions = [
+        item for item in parse_items(lines)
+        if item.has_attr("pyfunction") and item.name in registered
+    ]
+    functions.sort(key=lambda item: registered.index(item.name))
+    # the doc is a Rust string, with escaped newlines and continued lines
+    exceptions = [
+        (name, base.removeprefix("Py"), re.sub(r"\\\n\s*", "", doc).split("\\n"))
+        for name, base, doc in re.findall(
+            r"create_exception!\(\s*\w+,\s*(\w+),\s*(\w+),\s*\"(.*?)\"\s*\)", source, re.S
+        )
+    ]
+    return classes, fields, methods, functions, exceptions
+
+
+def render_docs(docs, indent):
+    if not docs:
+        return []
+    text = "\n".join(docs).replace('"""', '\\"\\"\\"')
+    lines = text.splitlines()
+    if len(lines) == 1:
+        return [f'{indent}"""{lines[0]}"""']
+    return [f'{indent}"""{lines[0]}'] + [f"{indent}{line}" if line else "" for line in lines[1:]] + [f'{indent}"""']
+
+
+def render_function(item, classes, indent=""):
+    if item.name == "__richcmp__":
+        return [
+            f"{indent}def __eq__(self, other: object) -> bool: ...",
+            f"{indent}def __ne__(self, other: object) -> bool: ...",
+        ]
+    params = ["self"] if indent else []
+    for name, rust in item.params:
+        annotation = ARGUMENT_TYPES.get((item.name, name)) or python_type(rust, classes)
+        default = item.defaults.get(name)
+        params.append(f"{name}: {annotation}" + (f" = {default}" if default else ""))
+    returns = python_type(item.returns, classes) if item.returns else "None"
+    name = item.name
+    lines = [f"{indent}@property"] if item.has_attr("getter") else []
+    if item.has_attr("setter"):
+        # pyo3 names the property after the setter, without its prefix
+        name = name.removeprefix("set_")
+        lines = [f"{indent}@{name}.setter"]
+    signature = f"{indent}def {name}({', '.join(params)}) -> {returns}:"
+    docs = render_docs(item.docs, indent + "    ")
+    if docs:
+        return lines + [signature] + docs
+    return lines + [signature + " ..."]
+
+
+def render_stubs(source):
+    classes, fields, methods, functions, exceptions = parse_module(source)
+    out = [HEADER]
+    for name, base, docs in exceptions:
+        out.append(f"class {name}({base}):")
+        out.extend(render_docs(docs, "    "))
+        out.extend(f"    {attr}: {annotation}" for attr, annotation in EXCEPTION_ATTRIBUTES.get(name, []))
+        out.append("")
+    for rust_name, name in classes.items():
+        out.append(f"class {name}:")
+        body = [
+            f"    {field}: {python_type(rust, classes)}"
+            for field, rust in fields[rust_name]
+        ]
+        body.extend(f"    {field}: {annotation}" for field, annotation in DYNAMIC_ATTRIBUTES.get(name, []))
+        for item in methods.get(rust_name, []):
+            body.extend(render_function(item, classes, "    "))
+        out.extend(body or ["    ..."])
+        out.append("")
+    for item in functions:
+        out.extend(render_function(item, classes))
+        out.append("")
+    return "\n".join(out)
+
+
+if __name__ == "__main__":
+    args = sys.argv[1:]
+    lib_rs = Path(args[0]) if args else ROOT / "src" / "lib.rs"
+    output = Path(args[1]) if len(args) > 1 else ROOT / "python" / "berlin" / "_berlin.pyi"
+    output.write_text(render_stubs(lib_rs.read_text()))
@@ -1,46 +1,445 @@
+// pyo3 0.18's #[pymethods] expansion trips this lint on newer compilers
+#![allow(non_local_definitions)]
+
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::iter::Iterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::MutexGuard;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use berlin_core::ustr::Ustr;
-use pyo3::exceptions::{PyAttributeError, PyKeyError, PyTypeError};
-use pyo3::prelude::*;
-use pyo3::types::{PyList, PyTuple};
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+use indextree::NodeId;
+use pyo3::exceptions::{
+    PyAttributeError, PyFileNotFoundError, PyKeyError, PyTypeError, PyValueError,
 };
+use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{PyDict, PyList, PyString, PyTuple};
+
+use berlin_core::location::{subdiv_key, AnyLocation, CsvLocode, LocData, Location};
+use berlin_core::search::Score;
+use berlin_explain::{word_coverage, LocationRepr, ScoreBreakdown};
 
-use berlin_core::location::{subdiv_key, CsvLocode, Location};
-use berlin_core::locations_db::{
-    parse_data_blocks, parse_data_files, parse_data_list, LocationsDb,
+mod pyjson;
+
+use berlin_db::builder::{
+    apply_external_ids, apply_importance, apply_int_ids, apply_tags, check_data_files,
+    dataset_versions, read_external_ids, read_json_file, BuildError, CsvTable, DataFile,
+    LocationsDbBuilder, RecordError, SourceNames,
+};
+use berlin_db::db::{
+    mentions, BerlinDb, DatasetVersion, GroupBy, FIPS_ENCODING, NUTS_ENCODING, POSTAL_ENCODING,
+};
+use berlin_db::query::{
+    inspect_query, lowercase_with_offsets, raw_score, Normalizer, Rewrite, RewriteRules,
+    DEFAULT_STOP_WORDS,
+};
+use berlin_db::render::{TextTemplate, DEFAULT_TEMPLATE};
+use berlin_db::search::{
+    GraphConfig, SearchOptions, SearchTimings, MAX_LEV_DISTANCE, STRICT_THRESHOLD,
 };
-use berlin_core::search::{Score, SearchTerm};
+use berlin_db::updater::{Mutation, Updater};
+use berlin_db::{db, language};
 
 // We will cap scores to this number
 pub const MAXIMUM_SCORE: i32 = 10000;
 
+// Where `load` puts the word index with `low_memory`, in the temporary
+// directory, unless told otherwise
+const INDEX_DIR: &str = "berlin-index";
+
+pyo3::create_exception!(
+    _berlin,
+    LoadError,
+    PyTypeError,
+    "Raised when records of location data cannot be parsed, with `errors`, a\n\
+     list of dicts of the block (or file) and the index (for rows) or key\n\
+     (for JSON) of each bad record, the field at fault if known, and the\n\
+     message."
+);
+
 #[pyclass]
 struct LocationsDbProxy {
-    _db: Arc<Mutex<LocationsDb>>,
+    _db: Arc<Mutex<BerlinDb>>,
+    _updater: Arc<Updater>,
+    /// Queries taking longer than this many milliseconds are logged as
+    /// warnings on the "berlin" logger. None, the default, logs none.
+    #[pyo3(get, set)]
+    slow_query_ms: Option<f64>,
+    /// The score below which `query(..., strict=True)` drops results
+    /// resting on a single fuzzily matched word.
+    #[pyo3(get, set)]
+    strict_threshold: i64,
+    // called on each query before it is searched for, if set
+    rewriter: Option<PyObject>,
+    // how results are boosted by parents that also matched
+    graph: GraphConfig,
+}
+
+/// Where the time of a query went, for reporting slow queries.
+#[derive(Default)]
+struct QueryTimings {
+    // waiting for the database lock
+    lock: Duration,
+    search: Duration,
+    // deduplicating and truncating
+    grouping: Duration,
+    // building the result objects
+    results: Duration,
+}
+
+impl QueryTimings {
+    fn stages(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("lock", self.lock),
+            ("search", self.search),
+            ("grouping", self.grouping),
+            ("results", self.results),
+        ]
+    }
+
+    fn total(&self) -> Duration {
+        self.stages().iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Milliseconds taken by each stage of the query and each phase of its
+    /// search, as a dict.
+    fn breakdown(&self, py: Python, parse: Duration, phases: SearchTimings) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        let phases = [
+            ("parse", parse),
+            ("fst_scan", phases.fst_scan),
+            ("scoring", phases.scoring),
+            ("graph", phases.graph),
+            ("sorting", phases.sorting),
+        ];
+        for (stage, duration) in phases.into_iter().chain(self.stages()) {
+            dict.set_item(stage, duration.as_secs_f64() * 1000.0)?;
+        }
+        dict.set_item("total", self.total().as_secs_f64() * 1000.0)?;
+        Ok(dict.into())
+    }
+}
+
+/// The options of `query` besides the query, limit and distance, so that
+/// the methods searching as `query` does share its defaults.
+struct QueryOptions {
+    state: Option<String>,
+    exact: bool,
+    candidate_limit: Option<usize>,
+    tags: Option<Vec<String>>,
+    phonetic: bool,
+    infix: bool,
+    dedupe: bool,
+    group_by: Option<GroupBy>,
+    detect_language: bool,
+    extract: bool,
+    strict: bool,
+    context: Option<Vec<String>>,
+    continent: Option<String>,
+    timing: bool,
+    parent_boosts: bool,
+    graph: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            state: None,
+            exact: false,
+            candidate_limit: None,
+            tags: None,
+            phonetic: false,
+            infix: false,
+            dedupe: false,
+            group_by: None,
+            detect_language: false,
+            extract: false,
+            strict: false,
+            context: None,
+            continent: None,
+            timing: false,
+            parent_boosts: false,
+            graph: true,
+        }
+    }
 }
 
 #[pyclass(name = "Location")]
 struct LocationProxy {
-    _loc: Location,
+    // the location is borrowed from the database on each access, rather
+    // than copied into every result
+    _key: Ustr,
     _score: Option<Score>,
-    _db: Arc<Mutex<LocationsDb>>,
+    // language of the alternate name the query matched, if any
+    _lang: Option<Ustr>,
+    // language detected in the query, if asked for
+    _query_lang: Option<&'static str>,
+    // share of the words of the query within the matched span
+    _coverage: Option<f64>,
+    // the parent that boosted the result, and by how much, if asked for
+    _parent_boost: Option<(Ustr, i64)>,
+    // other results for the same place, when deduplicated
+    _also: Vec<(Ustr, Score)>,
+    _db: Arc<Mutex<BerlinDb>>,
 }
 
 impl LocationsDbProxy {
+    // The results of `query`, searched for with `options`
+    fn run_query(
+        &self,
+        query: String,
+        limit: usize,
+        lev_distance: u32,
+        options: QueryOptions,
+    ) -> PyResult<PyObject> {
+        check_lev_distance(lev_distance)?;
+        let QueryOptions {
+            state,
+            exact,
+            candidate_limit,
+            tags,
+            phonetic,
+            infix,
+            dedupe,
+            group_by,
+            detect_language,
+            extract,
+            strict,
+            context,
+            continent,
+            timing,
+            parent_boosts,
+            graph,
+        } = options;
+        let profile = detect_language.then(|| language::detect(&query)).flatten();
+        let mut opts = SearchOptions {
+            candidate_limit,
+            tags: tags.unwrap_or_default(),
+            phonetic,
+            infix,
+            strict: strict.then_some(self.strict_threshold),
+            // normalized once the database is locked
+            context: vec![],
+            continent: continent.map(|continent| continent.to_lowercase()),
+            timings: timing.then(Arc::default),
+            parent_boosts: parent_boosts.then(Arc::default),
+            graph: GraphConfig {
+                enabled: graph && self.graph.enabled,
+                ..self.graph
+            },
+        };
+        let hooked = self.hook_rewrite(&query, state.as_deref())?;
+        let started = Instant::now();
+        let mut timings = QueryTimings::default();
+        let mut parse = Duration::ZERO;
+        let results = Python::with_gil(|_py| {
+            let db = self._db.lock().unwrap();
+            timings.lock = started.elapsed();
+            opts.context = context
+                .iter()
+                .flatten()
+                .map(|cue| db.normalizer().text(cue).trim().to_string())
+                .filter(|cue| !cue.is_empty())
+                .collect();
+            // search offsets refer to the text the search actually ran
+            // against, and are reported as characters of the raw query
+            // deduplicating and extracting need the candidates that would
+            // be cut
+            let search_limit = match dedupe || extract {
+                true => opts.candidate_limit(limit),
+                false => limit,
+            };
+            let mut rewrite = db.rewrite(&query);
+            rewrite.extend(hooked);
+            let state = rewrite.state.clone().or(state);
+            let (results, text, char_indices) = match exact {
+                true => {
+                    let (_, char_indices) = lowercase_with_offsets(&query);
+                    let results = db.exact_search(&query, state.as_deref(), search_limit, &opts);
+                    (results, query.to_lowercase(), char_indices)
+                }
+                false => {
+                    let parsing = Instant::now();
+                    let normalizer = rewrite.normalizer(db.normalizer());
+                    let (_, char_indices) = normalizer.with_offsets(&query);
+                    let st = db.rewritten_search_term(
+                        query.clone(),
+                        state,
+                        search_limit,
+                        lev_distance,
+                        profile,
+                        &rewrite,
+                    );
+                    parse = parsing.elapsed();
+                    (db.search(&st, &opts), st.normalized, char_indices)
+                }
+            };
+            timings.search = started.elapsed() - timings.lock;
+            let is_stop_word = |word: &str| {
+                db.stop_words().iter().any(|stop_word| stop_word == word)
+                    || profile.is_some_and(|profile| {
+                        profile.stop_words().any(|stop_word| stop_word == word)
+                    })
+            };
+            let results = match extract {
+                true => mentions(results, limit),
+                false => results,
+            };
+            let mut groups = match dedupe {
+                true => db.group_results(results),
+                false => results.into_iter().map(|result| vec![result]).collect(),
+            };
+            groups.truncate(limit);
+            timings.grouping = started.elapsed() - timings.lock - timings.search;
+            let parent_boosts = opts
+                .parent_boosts
+                .as_ref()
+                .map(|boosts| boosts.lock().unwrap().clone())
+                .unwrap_or_default();
+            groups
+                .into_iter()
+                .map(|group| {
+                    let mut group = group.into_iter();
+                    let (key, score) = group.next().expect("groups are not empty");
+                    let coverage =
+                        word_coverage(&text, score.offset.start, score.offset.end, is_stop_word);
+                    LocationProxy::new(key, self._db.clone())
+                        .with_score(raw_score(&char_indices, score))
+                        .with_lang(db.matched_language(&key, &text, score.offset))
+                        .with_query_lang(profile.map(|profile| profile.language))
+                        .with_coverage(coverage)
+                        .with_parent_boost(parent_boosts.get(&key).copied())
+                        .with_also(
+                            group
+                                .map(|(key, score)| (key, raw_score(&char_indices, score)))
+                                .collect(),
+                        )
+                })
+                .collect()
+        });
+        timings.results = started.elapsed() - timings.lock - timings.search - timings.grouping;
+        Python::with_gil(|py| {
+            self.warn_if_slow(py, &query, &timings)?;
+            let results = match group_by {
+                Some(by) => self.group_results(py, results, by)?,
+                None => results.into_py(py),
+            };
+            match &opts.timings {
+                Some(phases) => {
+                    let phases = *phases.lock().unwrap();
+                    Ok((results, timings.breakdown(py, parse, phases)?).into_py(py))
+                }
+                None => Ok(results),
+            }
+        })
+    }
+
+    // The rewrite the rewriting hook asks for, if one is set, given the
+    // query as parsed
+    fn hook_rewrite(&self, query: &str, state: Option<&str>) -> PyResult<Rewrite> {
+        let Some(rewriter) = &self.rewriter else {
+            return Ok(Rewrite::default());
+        };
+        let normalized = self._db.lock().unwrap().normalizer().text(query);
+        Python::with_gil(|py| {
+            let parsed = PyDict::new(py);
+            parsed.set_item("query", query)?;
+            parsed.set_item("normalized", normalized.trim())?;
+            parsed.set_item("words", normalized.split_whitespace().collect::<Vec<_>>())?;
+            parsed.set_item("state", state)?;
+            parse_rewrite(rewriter.call1(py, (parsed,))?.as_ref(py))
+        })
+    }
+
+    fn new(db: BerlinDb) -> Self {
+        let db = Arc::new(Mutex::new(db));
+        LocationsDbProxy {
+            _updater: Arc::new(Updater::new(db.clone())),
+            _db: db,
+            slow_query_ms: None,
+            strict_threshold: STRICT_THRESHOLD,
+            rewriter: None,
+            graph: GraphConfig::default(),
+        }
+    }
+
+    // Dicts keep insertion order, so groups come in order of their best
+    // result
+    fn group_results(
+        &self,
+        py: Python,
+        results: Vec<LocationProxy>,
+        by: GroupBy,
+    ) -> PyResult<PyObject> {
+        let db = self._db.lock().unwrap();
+        let groups = PyDict::new(py);
+        for result in results {
+            let key = db
+                .all
+                .get(&result._key)
+                .and_then(|loc| db.group_key(loc, by))
+                .map(|key| key.to_string());
+            match groups.get_item(&key) {
+                Some(group) => group.downcast::<PyList>()?.append(result.into_py(py))?,
+                None => groups.set_item(&key, vec![result.into_py(py)])?,
+            }
+        }
+        Ok(groups.to_object(py))
+    }
+
+    fn warn_if_slow(&self, py: Python, query: &str, timings: &QueryTimings) -> PyResult<()> {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let total = ms(timings.total());
+        if !self
+            .slow_query_ms
+            .is_some_and(|threshold| total > threshold)
+        {
+            return Ok(());
+        }
+        let stages = PyDict::new(py);
+        for (stage, duration) in timings.stages() {
+            stages.set_item(stage, ms(duration))?;
+        }
+        let extra = PyDict::new(py);
+        extra.set_item("query", query)?;
+        extra.set_item("timings", stages)?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("extra", extra)?;
+        let args = (
+            "Slow query %r took %.1fms (lock %.1fms, search %.1fms, grouping %.1fms, results %.1fms)",
+            query,
+            total,
+            ms(timings.lock),
+            ms(timings.search),
+            ms(timings.grouping),
+            ms(timings.results),
+        );
+        py.import("logging")?
+            .call_method1("getLogger", ("berlin",))?
+            .call_method("warning", args, Some(kwargs))?;
+        Ok(())
+    }
+
+    fn proxies(&self, db: &BerlinDb, keys: Vec<Ustr>) -> Vec<LocationProxy> {
+        keys.into_iter()
+            .filter(|key| db.all.contains_key(key))
+            .map(|key| LocationProxy::new(key, self._db.clone()))
+            .collect()
+    }
+
     fn _list<'a>(
         &'a self,
-        db: &'a MutexGuard<LocationsDb>,
+        db: &'a MutexGuard<BerlinDb>,
         encoding: &'a Option<String>,
         state: &'a Option<String>,
         subdiv: &'a Option<String>,
-    ) -> Box<dyn Iterator<Item = (&Ustr, &berlin_core::location::Location)> + 'a> {
+    ) -> Box<dyn Iterator<Item = (&'a Ustr, &'a Location)> + 'a> {
         let mut db_iter: Box<dyn Iterator<Item = (&Ustr, &Location)>> = Box::new(db.all.iter());
         if encoding.is_none() {
             db_iter =
@@ -67,13 +466,7 @@ impl LocationsDbProxy {
 impl LocationsDbProxy {
     fn retrieve(&self, term: String) -> PyResult<LocationProxy> {
         match self._db.lock().unwrap().retrieve(term.as_str()) {
-            Some(loc) => Python::with_gil(|_py| {
-                Ok(LocationProxy {
-                    _loc: loc,
-                    _score: None,
-                    _db: self._db.clone(),
-                })
-            }),
+            Some(loc) => Python::with_gil(|_py| Ok(LocationProxy::new(loc.key, self._db.clone()))),
             None => {
                 let err = PyKeyError::new_err(format!["{} not found", term.as_str()]);
                 Err(err)
@@ -81,244 +474,1250 @@ impl LocationsDbProxy {
         }
     }
 
-    fn get_state_key(&self, state: &str) -> PyResult<String> {
-        let code = match Ustr::from_existing(state) {
-            None => {
-                let err = PyKeyError::new_err(format!["{} not found as state key", state]);
-                return Err(err);
-            }
-            Some(code) => code,
-        };
-        let result = {
-            match self._db.lock().unwrap().state_by_code.get(&code) {
-                None => {
-                    let err = PyKeyError::new_err(format!["{} not found as state key", state]);
-                    Err(err)
-                }
-                Some(name) => Ok(name.to_string()),
-            }
-        };
-        result
+    /// Look up many keys at once, as `retrieve` does, returning a dict from
+    /// each key to its location, or to None if it is not found.
+    fn retrieve_many(&self, py: Python, keys: Vec<String>) -> PyResult<PyObject> {
+        // locked without the GIL, as in `autocomplete`
+        let found = py.allow_threads(|| {
+            let db = self._db.lock().unwrap();
+            keys.into_iter()
+                .map(|key| {
+                    let loc = db.retrieve(&key).map(|loc| loc.key);
+                    (key, loc)
+                })
+                .collect::<Vec<_>>()
+        });
+        let result = PyDict::new(py);
+        for (key, loc) in found {
+            let proxy = loc.map(|loc| LocationProxy::new(loc, self._db.clone()));
+            result.set_item(key, proxy.map(|proxy| proxy.into_py(py)))?;
+        }
+        Ok(result.to_object(py))
     }
 
-    fn get_subdiv_key(&self, state: &str, subdiv: &str) -> PyResult<String> {
-        let code_str = format!["{}:{}", state, subdiv];
-        let code = match Ustr::from_existing(code_str.as_str()) {
+    fn retrieve_by_int_id(&self, int_id: u32) -> PyResult<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        match db
+            .key_by_int_id(int_id)
+            .filter(|key| db.all.contains_key(key))
+        {
+            Some(key) => Ok(LocationProxy::new(key, self._db.clone())),
             None => {
-                let err = PyKeyError::new_err(format!["{} not found as subdiv key", code_str]);
-                return Err(err);
+                let err = PyKeyError::new_err(format!["{} not found as integer id", int_id]);
+                Err(err)
             }
-            Some(code) => code,
+        }
+    }
+
+    fn get_children(&self, key: &str) -> PyResult<Vec<LocationProxy>> {
+        self.get_descendants(key, Some(1))
+    }
+
+    /// Every location under `key`, level by level, down to `depth` levels
+    /// below it (or to the bottom).
+    #[pyo3(signature = (key, depth=None))]
+    fn get_descendants(&self, key: &str, depth: Option<usize>) -> PyResult<Vec<LocationProxy>> {
+        let db = self._db.lock().unwrap();
+        let Some(key) = Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) else {
+            let err = PyKeyError::new_err(format!["{} not found", key]);
+            return Err(err);
         };
-        let result = {
-            match self._db.lock().unwrap().subdiv_by_code.get(&code) {
-                None => {
-                    let err = PyKeyError::new_err(format!["{} not found as subdiv key", subdiv]);
-                    Err(err)
-                }
-                Some(name) => Ok(name.to_string()),
-            }
+        let result = db
+            .descendant_keys(&key, depth)
+            .into_iter()
+            .map(|key| LocationProxy::new(key, self._db.clone()))
+            .collect();
+        Ok(result)
+    }
+
+    /// The states on a continent, such as "eu", in key order.
+    fn states_by_continent(&self, continent: &str) -> Vec<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        self.proxies(&db, db.states_by_continent(continent))
+    }
+
+    /// The subdivisions of a state, such as "gb", in key order.
+    fn subdivisions_of(&self, state: &str) -> PyResult<Vec<LocationProxy>> {
+        let db = self._db.lock().unwrap();
+        let state = state_code(&db, state)?;
+        Ok(self.proxies(&db, db.subdivisions_of(&state)))
+    }
+
+    /// The LOCODEs of a state, such as "gb", in key order, only those in the
+    /// subdivision with code `subdiv`, such as "abd", if given.
+    #[pyo3(signature = (state, subdiv=None))]
+    fn locodes_of(&self, state: &str, subdiv: Option<&str>) -> PyResult<Vec<LocationProxy>> {
+        let db = self._db.lock().unwrap();
+        let state = state_code(&db, state)?;
+        let subdiv = subdiv.map(|subdiv| subdiv.to_lowercase());
+        let keys = match subdiv {
+            Some(subdiv) => match Ustr::from_existing(&subdiv) {
+                Some(subdiv) => db.locodes_of(&state, Some(&subdiv)),
+                None => vec![],
+            },
+            None => db.locodes_of(&state, None),
         };
-        result
+        Ok(self.proxies(&db, keys))
     }
 
-    fn query(
-        &self,
-        query: String,
-        limit: usize,
-        lev_distance: u32,
-        state: Option<String>,
-    ) -> PyResult<Vec<LocationProxy>> {
-        let results = Python::with_gil(|_py| {
-            let st = SearchTerm::from_raw_query(query, state, limit, lev_distance);
-            let db = self._db.lock().unwrap();
-            db.search(&st)
-                .into_iter()
-                .map(|(key, score)| {
-                    let loc = db.all.get(&key).cloned().expect("loc should be in db");
-                    LocationProxy {
-                        _loc: loc,
-                        _score: Some(score),
-                        _db: self._db.clone(),
-                    }
-                })
-                .collect()
-        });
-        Ok(results)
+    /// Whether the location `key` lies inside the state or subdivision
+    /// `ancestor`, by the hierarchy or, for a location placed in no
+    /// subdivision, by the subdivision of the nearest location, as when
+    /// checking a (LOCODE, country) pair.
+    fn is_within(&self, key: &str, ancestor: &str) -> PyResult<bool> {
+        let db = self._db.lock().unwrap();
+        let resolve = |key: &str| {
+            db.resolve_key(key)
+                .ok_or_else(|| PyKeyError::new_err(format!["{} not found", key]))
+        };
+        Ok(db.is_within(&resolve(key)?, &resolve(ancestor)?))
     }
 
-    fn list(
-        &self,
-        encoding: Option<String>,
-        state: Option<String>,
-        subdiv: Option<String>,
-    ) -> PyResult<Vec<LocationProxy>> {
-        let results = Python::with_gil(|_py| {
-            let db = self._db.lock().unwrap();
-            self._list(&db, &encoding, &state, &subdiv)
-                .map(|(_, loc)| LocationProxy {
-                    _loc: loc.clone(),
-                    _score: None,
-                    _db: self._db.clone(),
-                })
-                .collect()
-        });
-        Ok(results)
+    /// The hierarchy under `key` as nested dicts, each with the key,
+    /// encoding, id and name of a location and a list of its children.
+    fn get_subtree(&self, py: Python, key: &str) -> PyResult<PyObject> {
+        let db = self._db.lock().unwrap();
+        let Some(key) = Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) else {
+            let err = PyKeyError::new_err(format!["{} not found", key]);
+            return Err(err);
+        };
+        subtree(py, &db, &key)
     }
 
-    fn list_by_key(
-        &self,
-        encoding: Option<String>,
-        state: Option<String>,
-        subdiv: Option<String>,
-    ) -> PyResult<Vec<String>> {
-        let results = Python::with_gil(|_py| {
+    /// Write the integer id of every location, and of every location
+    /// removed since loading, as a JSON object from key to id. Placed in the
+    /// data directory as `int-ids.json`, it makes `load` give locations the
+    /// same ids again.
+    fn save_int_ids(&self, path: PathBuf) -> PyResult<()> {
+        let recorded = {
             let db = self._db.lock().unwrap();
-            self._list(&db, &encoding, &state, &subdiv)
-                .map(|(key, _)| key.to_string())
-                .collect()
-        });
-        Ok(results)
+            db.keys_by_int_id()
+                .iter()
+                .enumerate()
+                .map(|(n, key)| (key.to_string(), serde_json::Value::from(n)))
+                .collect::<serde_json::Map<_, _>>()
+        };
+        let contents = serde_json::to_string(&recorded).expect("ids serialize");
+        fs::write(&path, contents).map_err(|err| build_error(BuildError::Io(path, err)))
     }
-}
 
-#[pymethods]
-impl LocationProxy {
-    fn __getattr__(&self, attr: String) -> PyResult<PyObject> {
-        let val = Python::with_gil(|py| {
-            let val = match attr.as_str() {
-                "key" => self._loc.key.to_string().to_object(py),
-                "encoding" => self._loc.encoding.to_string().to_object(py),
-                "id" => self._loc.id.to_string().to_object(py),
-                "words" => self
-                    ._loc
-                    .words
-                    .iter()
-                    .map(|word| word.to_string())
-                    .collect::<Vec<_>>()
-                    .to_object(py),
-                _ => {
-                    let err = PyAttributeError::new_err(format!["{} not found", attr.as_str()]);
-                    return Err(err);
-                }
-            };
-            Ok(val)
-        });
-        Ok(val.unwrap())
+    /// Assign integer ids as written by `save_int_ids`, as `load` does with
+    /// `int-ids.json` in the data directory.
+    fn load_int_ids(&self, path: PathBuf) -> PyResult<()> {
+        let recorded = read_json_file(&path).map_err(build_error)?;
+        let mut db = self._db.lock().unwrap();
+        apply_int_ids(&mut db, &path, recorded).map_err(build_error)
     }
 
-    fn get_score(&self) -> Result<i32, PyErr> {
-        match self._score {
-            Some(score) => Ok(match i32::try_from(score.score) {
-                Ok(_score) => i32::min(MAXIMUM_SCORE, _score),
-                _ => MAXIMUM_SCORE,
-            }),
-            None => Err(PyAttributeError::new_err(format![
-                "No string offset attached to this location object"
-            ])),
+    fn get_int_id(&self, key: &str) -> PyResult<u32> {
+        let db = self._db.lock().unwrap();
+        match Ustr::from_existing(key).and_then(|key| db.int_id(&key)) {
+            Some(int_id) => Ok(int_id),
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found", key]);
+                Err(err)
+            }
         }
     }
 
-    fn get_offset(&self) -> PyResult<Py<PyTuple>> {
-        match self._score {
-            Some(score) => {
-                let offset_tuple = Python::with_gil(|_py| {
-                    PyTuple::new(_py, [score.offset.start, score.offset.end]).into()
-                });
-                Ok(offset_tuple)
-            }
-            None => Err(PyAttributeError::new_err(format![
-                "No string offset attached to this location object"
-            ])),
+    fn add_location(&self, encoding: &str, id: &str, fields: &PyDict) -> PyResult<LocationProxy> {
+        let mut db = self._db.lock().unwrap();
+        let (loc, data) = decode_location(db.normalizer(), encoding, id, fields)?;
+        if let Some(parent) = db.missing_parent(&loc) {
+            let err = PyKeyError::new_err(format!["{} not found", parent]);
+            return Err(err);
         }
+        db.insert(loc.clone());
+        SourceNames::from_data(loc.key, &data).apply(&mut db);
+        Ok(LocationProxy::new(loc.key, self._db.clone()))
     }
 
-    fn get_names(&self) -> PyResult<Py<PyAny>> {
-        let val: Result<_, PyAttributeError> = Python::with_gil(|py| {
-            let names: &PyList =
-                PyList::new(py, self._loc.get_names().iter().map(|name| name.as_str()));
-            Ok(names.into())
-        });
-        Ok(val.unwrap())
+    /// Queue a location to be added, or to replace the location with the
+    /// same key, in the background. Queued changes are applied in batches,
+    /// without blocking queries while the indexes are rebuilt.
+    fn queue_location(&self, encoding: &str, id: &str, fields: &PyDict) -> PyResult<()> {
+        let normalizer = self._db.lock().unwrap().normalizer().clone();
+        let (loc, data) = decode_location(&normalizer, encoding, id, fields)?;
+        let names = SourceNames::from_data(loc.key, &data);
+        self._updater.enqueue(Mutation::Location { loc, names });
+        Ok(())
     }
 
-    fn get_codes(&self) -> PyResult<Py<PyAny>> {
-        let val: Result<_, PyAttributeError> = Python::with_gil(|py| {
-            let codes: &PyList =
-                PyList::new(py, self._loc.get_codes().iter().map(|code| code.as_str()));
-            Ok(codes.into())
-        });
-        Ok(val.unwrap())
+    /// Queue an alias to be added in the background. It is dropped if the
+    /// location does not exist by the time it is applied.
+    fn queue_alias(&self, key: &str, name: &str) {
+        let mutation = Mutation::Alias {
+            key: Ustr::from(key),
+            name: name.to_string(),
+        };
+        self._updater.enqueue(mutation);
+    }
+
+    /// Queue a location, and every location under it, to be removed in the
+    /// background.
+    fn queue_removal(&self, key: &str) {
+        self._updater.enqueue(Mutation::Tombstone(Ustr::from(key)));
+    }
+
+    /// Wait until every queued change has been applied.
+    fn flush_updates(&self, py: Python) {
+        py.allow_threads(|| self._updater.flush());
+    }
+
+    /// The number of queued changes, the number applied (or failed) so far,
+    /// and when a batch was last applied, as a Unix timestamp.
+    fn update_stats(&self, py: Python) -> PyResult<PyObject> {
+        let stats = self._updater.stats();
+        let last_applied = stats
+            .last_applied
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs_f64());
+        let dict = PyDict::new(py);
+        dict.set_item("queue_depth", stats.queue_depth)?;
+        dict.set_item("applied", stats.applied)?;
+        dict.set_item("failed", stats.failed)?;
+        dict.set_item("last_applied", last_applied)?;
+        Ok(dict.to_object(py))
+    }
+
+    /// Expand the word `short`, such as "St" or "N.", to `long` in queries,
+    /// as with the built-in abbreviations. Names already in the database
+    /// keep their form; to expand those too, pass the abbreviation to
+    /// `load` in `normalization`.
+    fn add_abbreviation(&self, short: &str, long: &str) {
+        self._db.lock().unwrap().add_abbreviation(short, long)
     }
 
-    fn get_state_code(&self) -> &str {
-        self._loc.get_state().as_str()
+    /// Register `native`, a name in another script such as "Москва", as a
+    /// name of each location named `name` in the data. Returns the number
+    /// of locations named.
+    fn add_transliteration(&self, native: &str, name: &str) -> usize {
+        self._db.lock().unwrap().add_transliteration(native, name)
     }
 
-    fn get_subdiv_code(&self) -> Option<&str> {
-        match self._loc.get_subdiv() {
-            None => None,
-            Some(ustr) => Some(ustr.as_str()),
+    /// Register another name for a location. Aliases are only matched
+    /// exactly, after normalization, never fuzzily.
+    fn add_alias(&self, key: &str, name: &str) -> PyResult<()> {
+        let mut db = self._db.lock().unwrap();
+        match Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) {
+            Some(key) => {
+                db.add_alias(key, name);
+                Ok(())
+            }
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found", key]);
+                Err(err)
+            }
         }
     }
 
-    #[getter]
-    fn children(&self) -> PyResult<Vec<LocationProxy>> {
+    /// Every state as an `(alpha2, alpha3, name, key)` tuple, in alpha-2 order.
+    fn countries(&self) -> Vec<(String, String, String, String)> {
+        self._db
+            .lock()
+            .unwrap()
+            .countries()
+            .into_iter()
+            .map(|c| (c.alpha2, c.alpha3, c.name, c.key.to_string()))
+            .collect()
+    }
+
+    /// Coordinates that were corrected, dropped or flagged on load, each as a
+    /// dict with the key, the original coordinates, any correction, whether
+    /// they were dropped and a reason.
+    fn load_report(&self) -> PyResult<Vec<PyObject>> {
         let db = self._db.lock().unwrap();
-        let child_nodes = db.indices.get(&self._loc.key).unwrap().children(&db.arena);
-        let result = Python::with_gil(|_py| {
-            child_nodes
-                .map(|node_id| {
-                    let node = db.arena.get(node_id).unwrap();
-                    let key = node.get();
-                    let loc = db.retrieve(key).unwrap();
-                    LocationProxy {
-                        _loc: loc,
-                        _score: None,
-                        _db: self._db.clone(),
-                    }
+        Python::with_gil(|py| {
+            db.coordinate_issues()
+                .iter()
+                .map(|issue| {
+                    let dict = PyDict::new(py);
+                    dict.set_item("key", issue.key.as_str())?;
+                    dict.set_item("coordinates", (issue.original.lat, issue.original.lon))?;
+                    dict.set_item("corrected", issue.corrected.map(|c| (c.lat, c.lon)))?;
+                    dict.set_item("dropped", issue.dropped)?;
+                    dict.set_item("reason", issue.reason)?;
+                    Ok(dict.to_object(py))
                 })
                 .collect()
-        });
-        Ok(result)
+        })
     }
 
-    #[getter]
-    fn state(&self) -> PyResult<LocationProxy> {
-        let db = self._db.lock().unwrap();
-        match db.state_by_code.get(&self._loc.get_state()) {
-            Some(key) => Python::with_gil(|_py| {
-                let loc = db.retrieve(key).unwrap();
-                Ok(LocationProxy {
-                    _loc: loc,
-                    _score: None,
-                    _db: self._db.clone(),
-                })
-            }),
+    fn add_tag(&self, key: &str, tag: &str) -> PyResult<()> {
+        let mut db = self._db.lock().unwrap();
+        match Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) {
+            Some(key) => {
+                db.add_tag(key, tag);
+                Ok(())
+            }
             None => {
-                let err = PyKeyError::new_err(format!["{} not found", self._loc.get_state()]);
+                let err = PyKeyError::new_err(format!["{} not found", key]);
                 Err(err)
             }
         }
     }
 
+    /// Set the importance of locations, such as their population or port
+    /// size, from a dict of key to importance. Each tenfold increase adds
+    /// `importance_weight` to the score of a location in search results.
+    fn set_importance(&self, importance: HashMap<String, f64>) -> PyResult<()> {
+        let mut db = self._db.lock().unwrap();
+        apply_importance(&mut db, importance).map_err(build_error)
+    }
+
     #[getter]
-    fn subdiv(&self) -> PyResult<Option<LocationProxy>> {
-        let db = self._db.lock().unwrap();
-        match self._loc.get_subdiv() {
-            Some(key) => Python::with_gil(|_py| {
-                let state = self._loc.get_state();
-                match subdiv_key(state, key) {
-                    Some(key) => {
-                        let loc = db.retrieve(&key).unwrap();
-                        Ok(Some(LocationProxy {
-                            _loc: loc,
-                            _score: None,
-                            _db: self._db.clone(),
-                        }))
-                    }
-                    None => {
-                        let err = PyKeyError::new_err(format!["{} not found", key]);
+    fn importance_weight(&self) -> f64 {
+        self._db.lock().unwrap().importance_weight()
+    }
+
+    #[setter]
+    fn set_importance_weight(&self, weight: f64) {
+        self._db.lock().unwrap().set_importance_weight(weight);
+    }
+
+    fn remove_tag(&self, key: &str, tag: &str) -> PyResult<()> {
+        let mut db = self._db.lock().unwrap();
+        match Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) {
+            Some(key) => {
+                db.remove_tag(&key, tag);
+                Ok(())
+            }
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found", key]);
+                Err(err)
+            }
+        }
+    }
+
+    /// Tag locations from a JSON file mapping location keys to lists of tags.
+    fn load_tags(&self, path: String) -> PyResult<()> {
+        let tags = read_json_file(&PathBuf::from(path)).map_err(build_error)?;
+        let mut db = self._db.lock().unwrap();
+        apply_tags(&mut db, tags).map_err(build_error)
+    }
+
+    /// Load GeoNames IDs and Wikidata QIDs of locations from a TSV file with
+    /// columns key, geonames and wikidata.
+    fn load_external_ids(&self, path: String) -> PyResult<()> {
+        let rows = read_external_ids(&PathBuf::from(path)).map_err(build_error)?;
+        let mut db = self._db.lock().unwrap();
+        apply_external_ids(&mut db, rows).map_err(build_error)
+    }
+
+    /// Change how results whose state or subdivision also matched are
+    /// boosted: whether at all, the score above which both must be, the
+    /// share of the parent's score added, by the parent's kind, and whether
+    /// the boosts of a location's state and subdivision add up when both
+    /// matched, rather than the larger alone counting.
+    /// Settings left as None are kept.
+    #[pyo3(signature = (enabled=None, edge_threshold=None, state_share=None, subdiv_share=None, locode_share=None, generic_share=None, chains=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn configure_graph(
+        &mut self,
+        enabled: Option<bool>,
+        edge_threshold: Option<i64>,
+        state_share: Option<f64>,
+        subdiv_share: Option<f64>,
+        locode_share: Option<f64>,
+        generic_share: Option<f64>,
+        chains: Option<bool>,
+    ) -> PyResult<()> {
+        let shares = [state_share, subdiv_share, locode_share, generic_share];
+        if let Some(share) = shares
+            .into_iter()
+            .flatten()
+            .find(|share| !(0.0..=1.0).contains(share))
+        {
+            let err = PyValueError::new_err(format!["share {} is not between 0 and 1", share]);
+            return Err(err);
+        }
+        let graph = &mut self.graph;
+        graph.enabled = enabled.unwrap_or(graph.enabled);
+        graph.edge_threshold = edge_threshold.unwrap_or(graph.edge_threshold);
+        graph.state_share = state_share.unwrap_or(graph.state_share);
+        graph.subdiv_share = subdiv_share.unwrap_or(graph.subdiv_share);
+        graph.locode_share = locode_share.unwrap_or(graph.locode_share);
+        graph.generic_share = generic_share.unwrap_or(graph.generic_share);
+        graph.chains = chains.unwrap_or(graph.chains);
+        Ok(())
+    }
+
+    /// How results are boosted by parents that also matched, as a dict of
+    /// the settings of `configure_graph`.
+    #[getter]
+    fn graph_config(&self, py: Python) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("enabled", self.graph.enabled)?;
+        dict.set_item("edge_threshold", self.graph.edge_threshold)?;
+        dict.set_item("state_share", self.graph.state_share)?;
+        dict.set_item("subdiv_share", self.graph.subdiv_share)?;
+        dict.set_item("locode_share", self.graph.locode_share)?;
+        dict.set_item("generic_share", self.graph.generic_share)?;
+        dict.set_item("chains", self.graph.chains)?;
+        Ok(dict.into())
+    }
+
+    /// The release of the data, from the version.json in the data directory,
+    /// as a dict of "version" and "date", or None if it records none.
+    #[getter]
+    fn version(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let db = self._db.lock().unwrap();
+        let Some(version) = db.version() else {
+            return Ok(None);
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("version", &version.version)?;
+        dict.set_item("date", &version.date)?;
+        Ok(Some(dict.into()))
+    }
+
+    /// Rewrite every query by the rules in a JSON file: "drop", a list of
+    /// words dropped as stop words, "synonyms", from words to what they are
+    /// searched for as, such as {"nyc": "new york"}, and "states", from
+    /// words to the state searched within when they appear, such as
+    /// {"scotland": "gb"}. These replace any rules already loaded.
+    fn load_rewrites(&self, path: String) -> PyResult<()> {
+        let rules: RewriteRules = read_json_file(&PathBuf::from(path)).map_err(build_error)?;
+        self._db.lock().unwrap().set_rewrite_rules(rules);
+        Ok(())
+    }
+
+    /// Call `rewriter` on each query before `query` searches for it, with a
+    /// dict of the "query", its "normalized" text and "words", and the
+    /// "state" asked for. It returns None, or a dict of words to "drop",
+    /// "synonyms" from words to what they are searched for as, and a
+    /// "state" to search within in place of the one asked for. These apply
+    /// on top of any rewrite rules. None removes the hook.
+    fn set_rewriter(&mut self, rewriter: Option<PyObject>) {
+        self.rewriter = rewriter;
+    }
+
+    fn get_stop_words(&self) -> Vec<String> {
+        self._db.lock().unwrap().stop_words().to_vec()
+    }
+
+    fn set_stop_words(&self, stop_words: Vec<String>) {
+        self._db
+            .lock()
+            .unwrap()
+            .set_stop_words(stop_words.into_iter());
+    }
+
+    /// The key of a state by its ISO 3166-1 alpha-2, alpha-3 or numeric code.
+    fn get_state_key(&self, state: &str) -> PyResult<String> {
+        match self._db.lock().unwrap().resolve_country(state) {
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found as state key", state]);
+                Err(err)
+            }
+            Some(key) => Ok(key.to_string()),
+        }
+    }
+
+    /// The state with an ISO 3166-1 alpha-2, alpha-3 or numeric code, such
+    /// as "GB", "GBR" or "826".
+    fn resolve_country(&self, code: &str) -> PyResult<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        match db.resolve_country(code) {
+            Some(key) => Ok(self.proxies(&db, vec![key]).remove(0)),
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found as country", code]);
+                Err(err)
+            }
+        }
+    }
+
+    fn get_subdiv_key(&self, state: &str, subdiv: &str) -> PyResult<String> {
+        let code_str = format!["{}:{}", state, subdiv];
+        let code = match Ustr::from_existing(code_str.as_str()) {
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found as subdiv key", code_str]);
+                return Err(err);
+            }
+            Some(code) => code,
+        };
+        let result = {
+            match self._db.lock().unwrap().subdiv_by_code.get(&code) {
+                None => {
+                    let err = PyKeyError::new_err(format!["{} not found as subdiv key", subdiv]);
+                    Err(err)
+                }
+                Some(name) => Ok(name.to_string()),
+            }
+        };
+        result
+    }
+
+    /// Search for up to `limit` distinct locations in the query. Internally,
+    /// `candidate_limit` ranked candidates are considered before truncating.
+    /// If `tags` are given, only locations carrying all of them are returned.
+    /// With `phonetic`, names that sound like words in the query also match,
+    /// and with `infix`, names containing words of the query. With `dedupe`,
+    /// results for the same place, such as an airport and the LOCODE it
+    /// serves, are returned once, with the others in `also`. Result offsets
+    /// are characters of `query` as given. With `group_by` of "state" or
+    /// "subdiv", results come back as a dict from the key of each state or
+    /// subdivision to its results, best first, with None for results
+    /// outside any subdivision. With `detect_language`, the stop words of
+    /// the language the query appears to be in are dropped too, and each
+    /// result reports that language as `query_language`. With `extract`,
+    /// the results are up to `limit` separate locations mentioned in the
+    /// query, with spans that do not overlap, in order of mention, as for
+    /// "flights from Dublin to Berlin". With `strict`, results whose only
+    /// evidence is one fuzzily matched word, scoring below
+    /// `strict_threshold`, are dropped, so that "poverty" returns nothing.
+    /// Results right after one of the `context` words, such as "near",
+    /// "based in" or "travelling to", are boosted. With `continent`, such as
+    /// "eu", only locations in states on that continent are returned.
+    /// With `timing`, a tuple of the results and a dict of the milliseconds
+    /// taken by each phase of the query is returned instead. With
+    /// `parent_boosts`, each result's `parent_boost` tells which matching
+    /// parent boosted it, so that "London, GB" reads as one interpretation.
+    /// Without `graph`, no result is boosted by its parents, which saves
+    /// time on latency-critical queries.
+    #[pyo3(signature = (query, limit, lev_distance, state=None, exact=false, candidate_limit=None, tags=None, phonetic=false, infix=false, dedupe=false, group_by=None, detect_language=false, extract=false, strict=false, context=None, continent=None, timing=false, parent_boosts=false, graph=true))]
+    #[allow(clippy::too_many_arguments)]
+    fn query(
+        &self,
+        query: String,
+        limit: usize,
+        lev_distance: u32,
+        state: Option<String>,
+        exact: bool,
+        candidate_limit: Option<usize>,
+        tags: Option<Vec<String>>,
+        phonetic: bool,
+        infix: bool,
+        dedupe: bool,
+        group_by: Option<&str>,
+        detect_language: bool,
+        extract: bool,
+        strict: bool,
+        context: Option<Vec<String>>,
+        continent: Option<String>,
+        timing: bool,
+        parent_boosts: bool,
+        graph: bool,
+    ) -> PyResult<PyObject> {
+        self.run_query(
+            query,
+            limit,
+            lev_distance,
+            QueryOptions {
+                state,
+                exact,
+                candidate_limit,
+                tags,
+                phonetic,
+                infix,
+                dedupe,
+                group_by: group_by.map(parse_group_by).transpose()?,
+                detect_language,
+                extract,
+                strict,
+                context,
+                continent,
+                timing,
+                parent_boosts,
+                graph,
+            },
+        )
+    }
+
+    /// Render locations as text, parsing the template once. Fields are
+    /// written in braces ({name}, {subdiv}, {state}, {encoding}, {id}, {key},
+    /// {code}, {state_code}, {subdiv_code}), and a group in square brackets
+    /// is left out if any field in it is empty.
+    #[pyo3(signature = (keys, template=None))]
+    fn to_texts(&self, keys: Vec<String>, template: Option<&str>) -> PyResult<Vec<String>> {
+        let template = parse_template(template)?;
+        let db = self._db.lock().unwrap();
+        keys.iter()
+            .map(
+                |key| match Ustr::from_existing(key).and_then(|key| db.text_fields(&key)) {
+                    Some(fields) => Ok(template.render(&fields)),
+                    None => Err(PyKeyError::new_err(format!["{} not found", key])),
+                },
+            )
+            .collect()
+    }
+
+    /// Search as `query` does, returning the results as a GeoJSON
+    /// FeatureCollection, as a dict. Each feature has a point geometry where
+    /// the location has coordinates, or none, and the location's fields,
+    /// score and span as properties. For other options of `query`, pass its
+    /// results to `to_geojson`.
+    #[pyo3(signature = (query, limit, lev_distance, state=None, exact=false, tags=None, strict=false, continent=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_geojson(
+        &self,
+        py: Python,
+        query: String,
+        limit: usize,
+        lev_distance: u32,
+        state: Option<String>,
+        exact: bool,
+        tags: Option<Vec<String>>,
+        strict: bool,
+        continent: Option<String>,
+    ) -> PyResult<PyObject> {
+        let options = QueryOptions {
+            state,
+            exact,
+            tags,
+            strict,
+            continent,
+            ..QueryOptions::default()
+        };
+        let results = self.run_query(query, limit, lev_distance, options)?;
+        let results = results.extract::<Vec<PyRef<LocationProxy>>>(py)?;
+        self.to_geojson(py, results)
+    }
+
+    /// Locations, such as the results of `query`, as a GeoJSON
+    /// FeatureCollection, as a dict.
+    fn to_geojson(&self, py: Python, locations: Vec<PyRef<LocationProxy>>) -> PyResult<PyObject> {
+        let db = self._db.lock().unwrap();
+        let features = locations
+            .iter()
+            .map(|loc| loc.geojson_feature(&db))
+            .collect::<PyResult<Vec<_>>>()?;
+        let collection = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+        pyjson::to_py(py, &collection)
+    }
+
+    /// How the index is made up: the number of indexed words, the mean
+    /// number of locations per word, and the `top` most ambiguous words
+    /// with their location counts. Very ambiguous words make for large
+    /// candidate sets, and may be better as stop words.
+    #[pyo3(signature = (top=10))]
+    fn index_stats(&self, py: Python, top: usize) -> PyResult<PyObject> {
+        let stats = self._db.lock().unwrap().index_stats(top);
+        let dict = PyDict::new(py);
+        dict.set_item("words", stats.words)?;
+        dict.set_item("average_postings", stats.average_postings)?;
+        dict.set_item("most_ambiguous", stats.most_ambiguous)?;
+        Ok(dict.to_object(py))
+    }
+
+    /// The probable language of a query, as an ISO 639-1 code, from its
+    /// script or, for Latin script, its stop words and letters. None if
+    /// nothing suggests one.
+    fn detect_language(&self, query: &str) -> Option<&'static str> {
+        language::detect(query).map(|profile| profile.language)
+    }
+
+    /// The text as queries are normalized before searching, including the
+    /// database's folds and expansions.
+    fn normalize(&self, text: &str) -> String {
+        self._db.lock().unwrap().normalizer().text(text)
+    }
+
+    /// The indexes searches use, as a dict from name ("fst", "exact",
+    /// "phonetic" and "infix") to whether it is present, its number of
+    /// entries, its approximate size in bytes, how long it took to build and
+    /// whether it is on disk. The infix index is built by the first search
+    /// with `infix`.
+    fn capabilities(&self, py: Python) -> PyResult<PyObject> {
+        let indexes = self._db.lock().unwrap().capabilities();
+        let dict = PyDict::new(py);
+        for index in indexes {
+            let info = PyDict::new(py);
+            info.set_item("present", index.present)?;
+            info.set_item("entries", index.entries)?;
+            info.set_item("bytes", index.bytes)?;
+            let build_ms = index.build_time.map(|t| t.as_secs_f64() * 1000.0);
+            info.set_item("build_ms", build_ms)?;
+            info.set_item("on_disk", index.on_disk)?;
+            dict.set_item(index.name, info)?;
+        }
+        Ok(dict.to_object(py))
+    }
+
+    /// Move the word index into memory-mapped files in `dir`, as `load` does
+    /// with `low_memory`, so that it need not all be held in memory.
+    fn move_index_to_disk(&self, dir: PathBuf) -> PyResult<()> {
+        self._db.lock().unwrap().move_index_to_disk(&dir)?;
+        Ok(())
+    }
+
+    /// Keep the results of up to `capacity` recent fuzzy searches, each for
+    /// at most `ttl` seconds if given, so that repeated queries are not
+    /// searched again. A `capacity` of 0 stops caching. Any change to the
+    /// database invalidates the cache.
+    #[pyo3(signature = (capacity, ttl=None))]
+    fn set_cache(&self, capacity: usize, ttl: Option<f64>) -> PyResult<()> {
+        let ttl = ttl
+            .map(|ttl| {
+                Duration::try_from_secs_f64(ttl)
+                    .ok()
+                    .filter(|ttl| !ttl.is_zero())
+                    .ok_or_else(|| {
+                        PyValueError::new_err(format!["ttl must be positive, not {}", ttl])
+                    })
+            })
+            .transpose()?;
+        self._db.lock().unwrap().set_cache(capacity, ttl);
+        Ok(())
+    }
+
+    /// How the result cache is doing, as a dict of its hits, misses,
+    /// current size, capacity and ttl in seconds, or None if not caching.
+    fn cache_info(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let db = self._db.lock().unwrap();
+        db.with_cache(|cache| {
+            let dict = PyDict::new(py);
+            dict.set_item("hits", cache.hits)?;
+            dict.set_item("misses", cache.misses)?;
+            dict.set_item("size", cache.len())?;
+            dict.set_item("capacity", cache.capacity())?;
+            dict.set_item("ttl", cache.ttl().map(|ttl| ttl.as_secs_f64()))?;
+            Ok(dict.to_object(py))
+        })
+        .transpose()
+    }
+
+    /// Complete a prefix typed into a search box, returning (key, name)
+    /// pairs of the locations with a name, word or code starting with it.
+    #[pyo3(signature = (prefix, limit=10, state=None))]
+    fn autocomplete(
+        &self,
+        py: Python,
+        prefix: &str,
+        limit: usize,
+        state: Option<String>,
+    ) -> Vec<(String, String)> {
+        let state = state.map(|s| s.to_lowercase());
+        // the lock is taken and released without the GIL, so that a thread
+        // holding the GIL while it waits for the lock cannot block this one
+        py.allow_threads(|| {
+            let db = self._db.lock().unwrap();
+            db.autocomplete(prefix, state.as_deref(), limit)
+                .into_iter()
+                .map(|(key, name)| (key.to_string(), name))
+                .collect()
+        })
+    }
+
+    /// Explain the score the location `key` gets for the query, as a dict
+    /// following `score_breakdown_schema()`, or None if it is not found.
+    #[pyo3(signature = (query, key, lev_distance, state=None, phonetic=false, infix=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn explain(
+        &self,
+        py: Python,
+        query: String,
+        key: &str,
+        lev_distance: u32,
+        state: Option<String>,
+        phonetic: bool,
+        infix: bool,
+    ) -> PyResult<PyObject> {
+        check_lev_distance(lev_distance)?;
+        let db = self._db.lock().unwrap();
+        let Some(key) = Ustr::from_existing(key).filter(|key| db.all.contains_key(key)) else {
+            let err = PyKeyError::new_err(format!["{} not found", key]);
+            return Err(err);
+        };
+        let opts = SearchOptions {
+            phonetic,
+            infix,
+            graph: self.graph,
+            ..Default::default()
+        };
+        let st = db.search_term(query, state, 1, lev_distance, None);
+        match db.explain(&st, &opts, &key) {
+            Some(explanation) => {
+                let value = serde_json::to_value(explanation).expect("explanations serialize");
+                pyjson::to_py(py, &value)
+            }
+            None => Ok(py.None()),
+        }
+    }
+
+    /// Find the locations mentioned anywhere in a long document, returning
+    /// each once, in order of mention. Up to `window_limit` locations are
+    /// taken from each window of the document, and offsets are characters
+    /// of the document.
+    #[pyo3(signature = (document, lev_distance=2, state=None, window_limit=3, threshold=0))]
+    fn search_text(
+        &self,
+        document: String,
+        lev_distance: u32,
+        state: Option<String>,
+        window_limit: usize,
+        threshold: i64,
+    ) -> PyResult<Vec<LocationProxy>> {
+        check_lev_distance(lev_distance)?;
+        let db = self._db.lock().unwrap();
+        let results = db.search_text(&document, state, window_limit, lev_distance, threshold);
+        Ok(results
+            .into_iter()
+            .map(|(key, score)| LocationProxy::new(key, self._db.clone()).with_score(score))
+            .collect())
+    }
+
+    #[pyo3(signature = (corpus, lev_distance, threshold=0, state=None))]
+    fn false_positives(
+        &self,
+        corpus: Vec<String>,
+        lev_distance: u32,
+        threshold: i64,
+        state: Option<String>,
+    ) -> PyResult<Vec<PyObject>> {
+        check_lev_distance(lev_distance)?;
+        let matches =
+            self._db
+                .lock()
+                .unwrap()
+                .false_positives(&corpus, state, lev_distance, threshold);
+        Python::with_gil(|py| {
+            matches
+                .into_iter()
+                .map(|(query, key, score)| {
+                    let dict = PyDict::new(py);
+                    dict.set_item("query", query)?;
+                    dict.set_item("key", key.as_str())?;
+                    dict.set_item("score", score.score)?;
+                    dict.set_item("offset", (score.offset.start, score.offset.end))?;
+                    Ok(dict.to_object(py))
+                })
+                .collect()
+        })
+    }
+
+    fn list(
+        &self,
+        encoding: Option<String>,
+        state: Option<String>,
+        subdiv: Option<String>,
+    ) -> PyResult<Vec<LocationProxy>> {
+        let results = Python::with_gil(|_py| {
+            let db = self._db.lock().unwrap();
+            self._list(&db, &encoding, &state, &subdiv)
+                .map(|(key, _)| LocationProxy::new(*key, self._db.clone()))
+                .collect()
+        });
+        Ok(results)
+    }
+
+    fn list_by_key(
+        &self,
+        encoding: Option<String>,
+        state: Option<String>,
+        subdiv: Option<String>,
+    ) -> PyResult<Vec<String>> {
+        let results = Python::with_gil(|_py| {
+            let db = self._db.lock().unwrap();
+            self._list(&db, &encoding, &state, &subdiv)
+                .map(|(key, _)| key.to_string())
+                .collect()
+        });
+        Ok(results)
+    }
+}
+
+#[pymethods]
+impl LocationProxy {
+    fn __getattr__(&self, attr: String) -> PyResult<PyObject> {
+        if attr == "key" {
+            return Python::with_gil(|py| Ok(self._key.to_string().to_object(py)));
+        }
+        let db = self._db.lock().unwrap();
+        let loc = self.loc(&db)?;
+        Python::with_gil(|py| {
+            let val = match attr.as_str() {
+                "encoding" => loc.encoding.to_string().to_object(py),
+                "id" => loc.id.to_string().to_object(py),
+                "words" => loc
+                    .words
+                    .iter()
+                    .map(|word| word.to_string())
+                    .collect::<Vec<_>>()
+                    .to_object(py),
+                _ => {
+                    let err = PyAttributeError::new_err(format!["{} not found", attr.as_str()]);
+                    return Err(err);
+                }
+            };
+            Ok(val)
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        let name = self._db.lock().unwrap().display_name(&self._key);
+        let (key, name) = (self._key, name.unwrap_or_default());
+        match self._score {
+            Some(score) => format!["<Location {} {:?} score={}>", key, name, score.score],
+            None => format!["<Location {} {:?}>", key, name],
+        }
+    }
+
+    fn __str__(&self) -> String {
+        let db = self._db.lock().unwrap();
+        let fields = db.text_fields(&self._key).unwrap_or_default();
+        TextTemplate::parse(DEFAULT_TEMPLATE)
+            .expect("default template should parse")
+            .render(&fields)
+    }
+
+    /// Locations are equal if they have the same key, whatever the query
+    /// that found them.
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp, py: Python) -> PyObject {
+        let Ok(other) = other.extract::<PyRef<LocationProxy>>() else {
+            return py.NotImplemented();
+        };
+        match op {
+            CompareOp::Eq => (self._key == other._key).into_py(py),
+            CompareOp::Ne => (self._key != other._key).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self._key.as_str().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get_score(&self) -> Result<i32, PyErr> {
+        match self._score {
+            Some(score) => Ok(match i32::try_from(score.score) {
+                Ok(_score) => i32::min(MAXIMUM_SCORE, _score),
+                _ => MAXIMUM_SCORE,
+            }),
+            None => Err(PyAttributeError::new_err(
+                "No string offset attached to this location object",
+            )),
+        }
+    }
+
+    fn get_offset(&self) -> PyResult<Py<PyTuple>> {
+        match self._score {
+            Some(score) => {
+                let offset_tuple = Python::with_gil(|_py| {
+                    PyTuple::new(_py, [score.offset.start, score.offset.end]).into()
+                });
+                Ok(offset_tuple)
+            }
+            None => Err(PyAttributeError::new_err(
+                "No string offset attached to this location object",
+            )),
+        }
+    }
+
+    fn get_names(&self) -> PyResult<Py<PyAny>> {
+        let db = self._db.lock().unwrap();
+        let loc = self.loc(&db)?;
+        let val: Result<_, PyAttributeError> = Python::with_gil(|py| {
+            let names: &PyList = PyList::new(py, loc.get_names().iter().map(|name| name.as_str()));
+            Ok(names.into())
+        });
+        Ok(val.unwrap())
+    }
+
+    fn get_alt_names(&self) -> HashMap<String, Vec<String>> {
+        let db = self._db.lock().unwrap();
+        let mut alt_names: HashMap<String, Vec<String>> = HashMap::new();
+        for (lang, name) in db.alt_names(&self._key) {
+            alt_names
+                .entry(lang.to_string())
+                .or_default()
+                .push(name.clone());
+        }
+        alt_names
+    }
+
+    /// Other results for the same place, if the search was deduplicated.
+    #[getter]
+    fn also(&self) -> Vec<LocationProxy> {
+        self._also
+            .iter()
+            .map(|(key, score)| LocationProxy::new(*key, self._db.clone()).with_score(*score))
+            .collect()
+    }
+
+    /// Render the location as text, such as "Stonehaven, Aberdeenshire,
+    /// United Kingdom — UN-LOCODE GB SVN", for display or for embedding
+    /// models. See `LocationsDb.to_texts` for templates.
+    #[pyo3(signature = (template=None))]
+    fn to_text(&self, template: Option<&str>) -> PyResult<String> {
+        let template = parse_template(template)?;
+        let db = self._db.lock().unwrap();
+        let fields = db.text_fields(&self._key).unwrap_or_default();
+        Ok(template.render(&fields))
+    }
+
+    /// The location as a dict of its key, encoding, id, names, codes and
+    /// state and subdivision codes, as the `berlin` command line outputs
+    /// it. With `full`, "data" holds the fields of its type too.
+    #[pyo3(signature = (full=false))]
+    fn to_dict(&self, py: Python, full: bool) -> PyResult<PyObject> {
+        let db = self._db.lock().unwrap();
+        let loc = self.loc(&db)?;
+        let repr = match full {
+            true => LocationRepr::full(loc)
+                .with_coordinates(db.coordinates(&self._key).map(|c| (c.lat, c.lon))),
+            false => LocationRepr::brief(loc),
+        };
+        pyjson::to_py(
+            py,
+            &serde_json::to_value(repr).expect("locations serialize"),
+        )
+    }
+
+    /// The kind of location: "state", "subdivision", "locode", "airport",
+    /// "nuts", "fips", "postal" or "generic".
+    #[getter]
+    fn loc_type(&self) -> PyResult<&'static str> {
+        let db = self._db.lock().unwrap();
+        Ok(loc_type(self.loc(&db)?))
+    }
+
+    /// The level of a NUTS region, from 1 to 3.
+    #[getter]
+    fn nuts_level(&self) -> PyResult<Option<usize>> {
+        let db = self._db.lock().unwrap();
+        Ok(db::nuts_level(self.loc(&db)?))
+    }
+
+    #[getter]
+    fn continent(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "St", "continent")
+    }
+
+    #[getter]
+    fn alpha3(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "St", "alpha3")
+    }
+
+    #[getter]
+    fn level(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Subdv", "level")
+    }
+
+    #[getter]
+    fn function_code(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Locd", "function_code")
+    }
+
+    /// The LOCODE's status in the code list, such as "AI" or "RL".
+    #[getter]
+    fn status(&self) -> Option<String> {
+        let db = self._db.lock().unwrap();
+        let status = db.locode_status(&self._key)?;
+        Some(status.status.clone()).filter(|s| !s.is_empty())
+    }
+
+    /// When the LOCODE was last changed in the code list, as "YYMM".
+    #[getter]
+    fn date(&self) -> Option<String> {
+        let db = self._db.lock().unwrap();
+        let status = db.locode_status(&self._key)?;
+        Some(status.date.clone()).filter(|d| !d.is_empty())
+    }
+
+    #[getter]
+    fn city(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Airp", "city")
+    }
+
+    #[getter]
+    fn region(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Airp", "region")
+    }
+
+    #[getter]
+    fn elevation(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Airp", "elevation")
+    }
+
+    #[getter]
+    fn airport_type(&self, py: Python) -> PyResult<PyObject> {
+        self.data_field(py, "Airp", "airport_type")
+    }
+
+    /// `(lat, lon)` in degrees, north and east positive, if known.
+    #[getter]
+    fn coordinates(&self) -> Option<(f64, f64)> {
+        let db = self._db.lock().unwrap();
+        db.coordinates(&self._key).map(|c| (c.lat, c.lon))
+    }
+
+    /// The great-circle distance to another location in kilometres, or None
+    /// if either lacks coordinates.
+    fn distance_to(&self, other: PyRef<LocationProxy>) -> Option<f64> {
+        let db = self._db.lock().unwrap();
+        db.distance(&self._key, &other._key)
+    }
+
+    #[getter]
+    fn tags(&self) -> Vec<String> {
+        self._db.lock().unwrap().tags(&self._key).to_vec()
+    }
+
+    /// Identifiers of the location in other gazetteers, as a dict with any
+    /// of "geonames" and "wikidata".
+    #[getter]
+    fn external_ids(&self) -> HashMap<&'static str, String> {
+        let db = self._db.lock().unwrap();
+        let Some(ids) = db.external_ids(&self._key) else {
+            return HashMap::new();
+        };
+        [("geonames", &ids.geonames), ("wikidata", &ids.wikidata)]
+            .into_iter()
+            .filter_map(|(name, id)| Some((name, id.clone()?)))
+            .collect()
+    }
+
+    #[getter]
+    fn importance(&self) -> Option<f64> {
+        self._db.lock().unwrap().importance(&self._key)
+    }
+
+    #[getter]
+    fn matched_language(&self) -> Option<&str> {
+        self._lang.as_ref().map(|lang| lang.as_str())
+    }
+
+    /// The language detected in the query, if the search asked for it.
+    #[getter]
+    fn query_language(&self) -> Option<&str> {
+        self._query_lang
+    }
+
+    /// The share of the words of the query, other than stop words, within
+    /// the span this location matched, from 0 to 1. None if the location
+    /// did not come from `query`.
+    #[getter]
+    fn coverage(&self) -> Option<f64> {
+        self._coverage
+    }
+
+    /// The key of the parent, also matched by the query, that boosted this
+    /// location's score, and the boost, as a tuple. None if no parent did,
+    /// or if `query` was not asked for `parent_boosts`.
+    #[getter]
+    fn parent_boost(&self) -> Option<(&str, i64)> {
+        self._parent_boost
+            .as_ref()
+            .map(|(parent, boost)| (parent.as_str(), *boost))
+    }
+
+    fn get_codes(&self) -> PyResult<Py<PyAny>> {
+        let db = self._db.lock().unwrap();
+        let loc = self.loc(&db)?;
+        let val: Result<_, PyAttributeError> = Python::with_gil(|py| {
+            let codes: &PyList = PyList::new(py, loc.get_codes().iter().map(|code| code.as_str()));
+            Ok(codes.into())
+        });
+        Ok(val.unwrap())
+    }
+
+    fn get_state_code(&self) -> PyResult<String> {
+        let db = self._db.lock().unwrap();
+        Ok(self.loc(&db)?.get_state().to_string())
+    }
+
+    fn get_subdiv_code(&self) -> PyResult<Option<String>> {
+        let db = self._db.lock().unwrap();
+        Ok(self.loc(&db)?.get_subdiv().map(|subdiv| subdiv.to_string()))
+    }
+
+    #[getter]
+    fn int_id(&self) -> PyResult<u32> {
+        match self._db.lock().unwrap().int_id(&self._key) {
+            Some(int_id) => Ok(int_id),
+            None => {
+                let err = PyKeyError::new_err(format!["{} has no integer id", self._key]);
+                Err(err)
+            }
+        }
+    }
+
+    #[getter]
+    fn children(&self) -> PyResult<Vec<LocationProxy>> {
+        let db = self._db.lock().unwrap();
+        self.loc(&db)?;
+        let child_nodes = db.indices.get(&self._key).unwrap().children(&db.arena);
+        let result = Python::with_gil(|_py| {
+            child_nodes
+                .map(|node_id| {
+                    let node = db.arena.get(node_id).unwrap();
+                    LocationProxy::new(*node.get(), self._db.clone())
+                })
+                .collect()
+        });
+        Ok(result)
+    }
+
+    /// The location this one sits directly under: its subdivision or state.
+    #[getter]
+    fn parent(&self) -> Option<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        let node_id = db.indices.get(&self._key)?;
+        let parent = db.arena.get(*node_id)?.parent()?;
+        self.related(&db, std::iter::once(parent)).pop()
+    }
+
+    /// The locations this one sits under, nearest first, ending with its
+    /// state.
+    #[getter]
+    fn ancestors(&self) -> Vec<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        match db.indices.get(&self._key) {
+            Some(node_id) => self.related(&db, node_id.ancestors(&db.arena).skip(1)),
+            None => vec![],
+        }
+    }
+
+    /// The other locations under the same parent.
+    #[getter]
+    fn siblings(&self) -> Vec<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        let Some(node_id) = db.indices.get(&self._key) else {
+            return vec![];
+        };
+        if db
+            .arena
+            .get(*node_id)
+            .and_then(|node| node.parent())
+            .is_none()
+        {
+            return vec![];
+        }
+        let preceding = node_id.preceding_siblings(&db.arena).skip(1);
+        let following = node_id.following_siblings(&db.arena).skip(1);
+        self.related(&db, preceding.chain(following))
+    }
+
+    #[getter]
+    fn state(&self) -> PyResult<LocationProxy> {
+        let db = self._db.lock().unwrap();
+        let state = self.loc(&db)?.get_state();
+        match db.state_by_code.get(&state) {
+            Some(key) => Python::with_gil(|_py| Ok(LocationProxy::new(*key, self._db.clone()))),
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found", state]);
+                Err(err)
+            }
+        }
+    }
+
+    #[getter]
+    fn subdiv(&self) -> PyResult<Option<LocationProxy>> {
+        let db = self._db.lock().unwrap();
+        let loc = self.loc(&db)?;
+        match loc.get_subdiv() {
+            Some(key) => Python::with_gil(|_py| {
+                let state = loc.get_state();
+                match subdiv_key(state, key) {
+                    Some(key) => Ok(Some(LocationProxy::new(key, self._db.clone()))),
+                    None => {
+                        let err = PyKeyError::new_err(format!["{} not found", key]);
                         Err(err)
                     }
                 }
@@ -328,166 +1727,654 @@ impl LocationProxy {
     }
 }
 
-/// Formats the sum of two numbers as string.
+impl LocationProxy {
+    fn new(key: Ustr, db: Arc<Mutex<BerlinDb>>) -> Self {
+        LocationProxy {
+            _key: key,
+            _score: None,
+            _lang: None,
+            _query_lang: None,
+            _coverage: None,
+            _parent_boost: None,
+            _also: vec![],
+            _db: db,
+        }
+    }
+
+    fn with_score(mut self, score: Score) -> Self {
+        self._score = Some(score);
+        self
+    }
+
+    fn with_lang(mut self, lang: Option<Ustr>) -> Self {
+        self._lang = lang;
+        self
+    }
+
+    fn with_query_lang(mut self, query_lang: Option<&'static str>) -> Self {
+        self._query_lang = query_lang;
+        self
+    }
+
+    fn with_coverage(mut self, coverage: f64) -> Self {
+        self._coverage = Some(coverage);
+        self
+    }
+
+    fn with_parent_boost(mut self, parent_boost: Option<(Ustr, i64)>) -> Self {
+        self._parent_boost = parent_boost;
+        self
+    }
+
+    fn with_also(mut self, also: Vec<(Ustr, Score)>) -> Self {
+        self._also = also;
+        self
+    }
+
+    // The location, which may have been removed since this was made
+    fn loc<'a>(&self, db: &'a BerlinDb) -> PyResult<&'a Location> {
+        match db.all.get(&self._key) {
+            Some(loc) => Ok(loc),
+            None => {
+                let err = PyKeyError::new_err(format!["{} not found", self._key]);
+                Err(err)
+            }
+        }
+    }
+
+    // None where the field does not apply to this kind of location
+    fn data_field(&self, py: Python, variant: &str, field: &str) -> PyResult<PyObject> {
+        let db = self._db.lock().unwrap();
+        match db::data_field(self.loc(&db)?, variant, field) {
+            Some(value) => Ok(pyjson::to_py(py, &value).unwrap_or_else(|_| py.None())),
+            None => Ok(py.None()),
+        }
+    }
+
+    fn geojson_feature(&self, db: &BerlinDb) -> PyResult<serde_json::Value> {
+        let key = &self._key;
+        let loc = self.loc(db)?;
+        let (state, subdiv) = loc.get_parents();
+        // GeoJSON positions are longitude first
+        let geometry = match db.coordinates(key) {
+            Some(c) => serde_json::json!({ "type": "Point", "coordinates": [c.lon, c.lat] }),
+            None => serde_json::Value::Null,
+        };
+        let names = loc
+            .get_names()
+            .iter()
+            .map(|name| name.to_string())
+            .chain(db.alt_names(key).iter().map(|(_, name)| name.clone()))
+            .collect::<Vec<_>>();
+        let external_ids = db
+            .external_ids(key)
+            .map(|ids| serde_json::json!({ "geonames": ids.geonames, "wikidata": ids.wikidata }));
+        let data = LocationRepr::full(loc)
+            .with_coordinates(db.coordinates(key).map(|c| (c.lat, c.lon)))
+            .data;
+        Ok(serde_json::json!({
+            "type": "Feature",
+            "id": key.as_str(),
+            "geometry": geometry,
+            "properties": {
+                "key": key.as_str(),
+                "encoding": loc.encoding.as_str(),
+                "id": loc.id.as_str(),
+                "name": db.display_name(key),
+                "names": names,
+                "codes": loc.get_codes().iter().map(|code| code.as_str()).collect::<Vec<_>>(),
+                "loc_type": loc_type(loc),
+                "state": state.map(|key| key.to_string()),
+                "subdiv": subdiv.map(|key| key.to_string()),
+                "score": self._score.map(|score| score.score.min(MAXIMUM_SCORE as i64)),
+                "offset": self._score.map(|score| [score.offset.start, score.offset.end]),
+                "coverage": self._coverage,
+                "tags": db.tags(key),
+                "importance": db.importance(key),
+                "external_ids": external_ids,
+                "data": data,
+            },
+        }))
+    }
+
+    fn related(&self, db: &BerlinDb, node_ids: impl Iterator<Item = NodeId>) -> Vec<LocationProxy> {
+        node_ids
+            .filter_map(|node_id| db.arena.get(node_id))
+            .map(|node| LocationProxy::new(*node.get(), self._db.clone()))
+            .collect()
+    }
+}
+
+// The kind of location, as the `loc_type` of a result
+fn loc_type(loc: &Location) -> &'static str {
+    match loc.data {
+        LocData::St(_) => "state",
+        LocData::Subdv(_) => "subdivision",
+        LocData::Locd(_) => "locode",
+        LocData::Airp(_) => "airport",
+        LocData::Gen(_) if loc.encoding == NUTS_ENCODING => "nuts",
+        LocData::Gen(_) if loc.encoding == FIPS_ENCODING => "fips",
+        LocData::Gen(_) if loc.encoding == POSTAL_ENCODING => "postal",
+        LocData::Gen(_) => "generic",
+    }
+}
+
+fn subtree(py: Python, db: &BerlinDb, key: &Ustr) -> PyResult<PyObject> {
+    let loc = &db.all[key];
+    let node = PyDict::new(py);
+    node.set_item("key", key.as_str())?;
+    node.set_item("encoding", loc.encoding.as_str())?;
+    node.set_item("id", loc.id.as_str())?;
+    node.set_item("name", db.display_name(key))?;
+    let children = db
+        .child_keys(key)
+        .iter()
+        .map(|child| subtree(py, db, child))
+        .collect::<PyResult<Vec<_>>>()?;
+    node.set_item("children", children)?;
+    Ok(node.to_object(py))
+}
+
+/// Load a database from JSON blocks and LOCODE rows. Each block maps keys
+/// to locations, and is given either as a JSON string or as the parsed
+/// dict. The blocks may be any iterable, such as a generator, of blocks or
+/// of iterables of blocks; each is decoded and released before the next is
+/// read. `on_progress(stage, done, total)` is called after each item of
+/// `blocks`, with a total of None if it has no length, and around the final
+/// build. `normalization` customizes how names and queries are normalized,
+/// as for `load`.
 #[pyfunction]
+#[pyo3(signature = (blocks, rows, on_progress=None, normalization=None))]
 fn load_from_json(
-    blocks: Vec<Vec<String>>,
+    py: Python,
+    blocks: &PyAny,
     rows: Vec<HashMap<String, String>>,
+    on_progress: Option<&PyAny>,
+    normalization: Option<&PyDict>,
 ) -> PyResult<LocationsDbProxy> {
-    let db = {
-        let mut errors: Vec<String> = vec![];
-        let blocks: Vec<(String, Result<serde_json::Value, _>)> = blocks
-            .par_iter()
-            .enumerate()
-            .map(|(m, strings)| {
-                strings
-                    .par_iter()
-                    .enumerate()
-                    .map_with(m, |m, (n, string)| {
-                        (
-                            format!("{m}, {n}"),
-                            serde_json::from_str::<serde_json::Value>(string),
-                        )
-                    })
-            })
-            .flatten()
-            .collect::<_>();
+    let progress = Progress::new(py, on_progress);
+    let total = blocks.len().ok();
+    let mut builder = LocationsDbBuilder::new().normalizer(parse_normalization(normalization)?);
+    for (m, item) in blocks.iter()?.enumerate() {
+        let item = item?;
+        if item.is_instance_of::<PyString>()? || item.is_instance_of::<PyDict>()? {
+            builder = add_json_block(py, builder, format!("{m}"), item)?;
+        } else {
+            for (n, block) in item.iter()?.enumerate() {
+                builder = add_json_block(py, builder, format!("{m}, {n}"), block?)?;
+            }
+        }
+        progress.report("blocks", m + 1, total)?;
+    }
 
-        let blocks: Vec<(String, serde_json::Value)> = blocks
-            .into_iter()
-            .filter_map(|(loc, value)| match value {
-                Ok(value) => Some((loc, value)),
+    let mut errors: Vec<RecordError> = vec![];
+    let rows = rows
+        .iter()
+        .enumerate()
+        .filter_map(|(n, row)| {
+            match (|row: &HashMap<String, String>| {
+                let locode = CsvLocode {
+                    country: match row.get("country") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => {
+                            return Err(RecordError::in_row(
+                                "rows",
+                                n,
+                                Some("country"),
+                                "No country".to_string(),
+                            ))
+                        }
+                    },
+                    subcode: match row.get("subcode") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => {
+                            return Err(RecordError::in_row(
+                                "rows",
+                                n,
+                                Some("subcode"),
+                                "No subcode".to_string(),
+                            ))
+                        }
+                    },
+                    name: match row.get("name") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    name_wo_diacritics: match row.get("name_wo_diacritics") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    subdivision_code: match row.get("subdivision_code") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    status: match row.get("status") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    date: match row.get("date") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    iata_code: match row.get("iata_code") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    function: match row.get("function") {
+                        Some(str_ref) => (*str_ref).clone(),
+                        None => "".to_string(),
+                    },
+                    coordinates: row.get("coordinates").cloned(),
+                };
+                Ok(locode)
+            })(row)
+            {
+                Ok(locode) => Some(locode),
                 Err(err) => {
-                    errors.push(format!("Block {loc}: {}", err.to_string()));
+                    errors.push(err);
                     None
                 }
-            })
-            .collect::<_>();
-
-        if errors.len() > 0 {
-            return Err(PyTypeError::new_err(format!(
-                "JSON parsing errors:\n{}",
-                errors.join("\n")
-            )));
-        }
-
-        let db = match parse_data_blocks(blocks.into_par_iter(), None) {
-            Ok(db) => db,
-            Err(err) => {
-                return Err(PyTypeError::new_err(format!(
-                    "JSON parsing errors:\n{}",
-                    err.to_string()
-                )));
             }
-        };
+        })
+        .collect::<Vec<CsvLocode>>();
+    let builder = builder.add_csv_locodes(rows).add_errors(errors);
+    progress.report("build", 0, Some(1))?;
+    let db = py.allow_threads(|| builder.build()).map_err(build_error)?;
+    progress.report("build", 1, Some(1))?;
+    Ok(LocationsDbProxy::new(db))
+}
 
-        let mut errors: Vec<String> = vec![];
-        let rows = rows
-            .iter()
-            .enumerate()
-            .filter_map(|(n, row)| {
-                match (|row: &HashMap<String, String>| {
-                    let locode = CsvLocode {
-                        country: match row.get("country") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => return Err(format!("Line {n}: No country")),
-                        },
-                        subcode: match row.get("subcode") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => return Err(format!("Line {n}: No subcode")),
-                        },
-                        name: match row.get("name") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        name_wo_diacritics: match row.get("name_wo_diacritics") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        subdivision_code: match row.get("subdivision_code") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        status: match row.get("status") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        date: match row.get("date") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        iata_code: match row.get("iata_code") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        function: match row.get("function") {
-                            Some(str_ref) => (*str_ref).clone(),
-                            None => "".to_string(),
-                        },
-                        coordinates: match row.get("coordinates") {
-                            Some(coordinates) => Some((*coordinates).clone()),
-                            None => None,
-                        },
-                    };
-                    Ok(locode)
-                })(row)
-                {
-                    Ok(locode) => Some(locode),
-                    Err(err) => {
-                        errors.push(err);
-                        None
-                    }
+/// Reports the progress of loading to an optional Python callback. Each
+/// report also checks for signals, so that Ctrl-C cancels loading with a
+/// KeyboardInterrupt between steps.
+struct Progress<'py> {
+    py: Python<'py>,
+    callback: Option<&'py PyAny>,
+}
+
+impl<'py> Progress<'py> {
+    fn new(py: Python<'py>, callback: Option<&'py PyAny>) -> Self {
+        Progress { py, callback }
+    }
+
+    fn report(&self, stage: &str, done: usize, total: Option<usize>) -> PyResult<()> {
+        self.py.check_signals()?;
+        if let Some(callback) = self.callback {
+            callback.call1((stage, done, total))?;
+        }
+        Ok(())
+    }
+}
+
+// The GIL is released while the locations of the block are decoded
+fn add_json_block(
+    py: Python,
+    builder: LocationsDbBuilder,
+    name: String,
+    block: &PyAny,
+) -> PyResult<LocationsDbBuilder> {
+    match block.extract::<&str>() {
+        Ok(string) => Ok(py.allow_threads(|| builder.add_json_block(name, string))),
+        Err(_) => {
+            let value = pyjson::to_json(block)?;
+            Ok(py.allow_threads(|| builder.add_json_value(name, value)))
+        }
+    }
+}
+
+fn parse_normalization(normalization: Option<&PyDict>) -> PyResult<Normalizer> {
+    let mut normalizer = Normalizer::default();
+    for (step, table) in normalization.into_iter().flatten() {
+        let table = table.extract::<HashMap<String, String>>()?;
+        match step.extract::<&str>()? {
+            "folding" => {
+                for (text, replacement) in &table {
+                    normalizer.add_fold(text, replacement);
                 }
-            })
-            .collect::<Vec<CsvLocode>>();
-        if errors.len() > 0 {
-            return Err(PyTypeError::new_err(format!(
-                "LOCODE parsing errors:\n{}",
-                errors.join("\n")
-            )));
-        }
-        let db = match parse_data_list(db, rows.into_iter()) {
-            Ok(db) => db,
-            Err(err) => {
-                return Err(PyTypeError::new_err(format!(
-                    "JSON parsing errors:\n{}",
-                    err.to_string()
-                )));
             }
-        };
-        db.mk_fst()
-    };
-    let db_proxy = LocationsDbProxy {
-        _db: Arc::new(Mutex::new(db)),
+            "expansions" => {
+                for (word, expansion) in &table {
+                    normalizer.add_expansion(word, expansion);
+                }
+            }
+            step => {
+                let err = PyValueError::new_err(format![
+                    "{} is not a normalization step; use folding or expansions",
+                    step
+                ]);
+                return Err(err);
+            }
+        }
+    }
+    Ok(normalizer)
+}
+
+fn parse_rewrite(rewrite: &PyAny) -> PyResult<Rewrite> {
+    let mut parsed = Rewrite::default();
+    if rewrite.is_none() {
+        return Ok(parsed);
+    }
+    let rewrite = rewrite
+        .downcast::<PyDict>()
+        .map_err(|_| PyTypeError::new_err(format!["{} is not a dict or None", rewrite]))?;
+    for (change, value) in rewrite {
+        match change.extract::<&str>()? {
+            "drop" => parsed.drop = value.extract()?,
+            "synonyms" => {
+                parsed.synonyms = value
+                    .extract::<HashMap<String, String>>()?
+                    .into_iter()
+                    .collect()
+            }
+            "state" => {
+                parsed.state = value
+                    .extract::<Option<String>>()?
+                    .map(|state| state.to_lowercase())
+            }
+            change => {
+                let err = PyValueError::new_err(format![
+                    "{} is not a rewrite; use drop, synonyms or state",
+                    change
+                ]);
+                return Err(err);
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// Warn on the "berlin" logger if the data files come from different
+// releases of the data
+fn warn_if_mixed_versions(py: Python, versions: &[(PathBuf, DatasetVersion)]) -> PyResult<()> {
+    if versions
+        .iter()
+        .all(|(_, version)| *version == versions[0].1)
+    {
+        return Ok(());
+    }
+    let found = versions
+        .iter()
+        .map(|(dir, version)| format!("{} in {}", version.version, dir.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    py.import("logging")?
+        .call_method1("getLogger", ("berlin",))?
+        .call_method1(
+            "warning",
+            ("Data files come from different dataset versions: %s", found),
+        )?;
+    Ok(())
+}
+
+fn parse_template(template: Option<&str>) -> PyResult<TextTemplate> {
+    TextTemplate::parse(template.unwrap_or(DEFAULT_TEMPLATE)).map_err(PyValueError::new_err)
+}
+
+fn decode_location(
+    normalizer: &Normalizer,
+    encoding: &str,
+    id: &str,
+    fields: &PyDict,
+) -> PyResult<(Location, serde_json::Value)> {
+    let data = pyjson::to_json(fields)?;
+    let mut raw = serde_json::json!({ "<c>": encoding, "i": id, "d": data.clone() });
+    normalizer.apply_to_entry(&mut raw);
+    match serde_json::from_value::<AnyLocation>(raw).and_then(Location::from_raw) {
+        Ok(loc) => Ok((loc, data)),
+        Err(err) => Err(PyTypeError::new_err(format!(
+            "Cannot decode location {encoding}-{id}: {err}"
+        ))),
+    }
+}
+
+// The Python exception for a `BuildError`
+fn build_error(err: BuildError) -> PyErr {
+    match err {
+        BuildError::UnknownKey(_) => PyKeyError::new_err(err.to_string()),
+        BuildError::IntId(..) => PyValueError::new_err(err.to_string()),
+        BuildError::Missing(_) => PyFileNotFoundError::new_err(err.to_string()),
+        BuildError::Json(ref errors) => Python::with_gil(|py| {
+            let pyerr = LoadError::new_err(err.to_string());
+            match record_errors(py, errors)
+                .and_then(|errors| pyerr.value(py).setattr("errors", errors))
+            {
+                Ok(()) => pyerr,
+                Err(setattr_err) => setattr_err,
+            }
+        }),
+        _ => PyTypeError::new_err(err.to_string()),
+    }
+}
+
+fn record_errors(py: Python, errors: &[RecordError]) -> PyResult<PyObject> {
+    let records = errors
+        .iter()
+        .map(|error| {
+            let record = PyDict::new(py);
+            record.set_item("block", &error.block)?;
+            record.set_item("index", error.index)?;
+            record.set_item("record", &error.record)?;
+            record.set_item("field", &error.field)?;
+            record.set_item("message", &error.message)?;
+            Ok(record)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(records.to_object(py))
+}
+
+fn parse_group_by(group_by: &str) -> PyResult<GroupBy> {
+    match group_by {
+        "state" => Ok(GroupBy::State),
+        "subdiv" => Ok(GroupBy::Subdiv),
+        _ => {
+            let err = PyValueError::new_err(format![
+                "group_by should be \"state\" or \"subdiv\", not {:?}",
+                group_by
+            ]);
+            Err(err)
+        }
+    }
+}
+
+// Distances above the maximum have no precompiled automaton, and would
+// match most of the index anyway
+fn check_lev_distance(lev_distance: u32) -> PyResult<()> {
+    if lev_distance > MAX_LEV_DISTANCE {
+        let err = PyValueError::new_err(format![
+            "lev_distance may be at most {}, not {}",
+            MAX_LEV_DISTANCE, lev_distance
+        ]);
+        return Err(err);
+    }
+    Ok(())
+}
+
+// The code of a known state, as given in any case
+fn state_code(db: &BerlinDb, state: &str) -> PyResult<Ustr> {
+    Ustr::from_existing(&state.to_lowercase())
+        .filter(|code| db.state_by_code.contains_key(code))
+        .ok_or_else(|| PyKeyError::new_err(format!["{} not found as state", state]))
+}
+
+// A data file given to `load`, as a path or a (kind, path) pair
+fn parse_data_file(data_path: &Path, file: &PyAny) -> PyResult<DataFile> {
+    let (kind, path) = match file.extract::<String>() {
+        Ok(path) => (None, path),
+        Err(_) => {
+            let (kind, path) = file.extract::<(String, String)>()?;
+            (Some(kind), path)
+        }
     };
-    Ok(db_proxy)
+    DataFile::parse(kind.as_deref(), data_path.join(path)).map_err(PyValueError::new_err)
 }
 
-/// Formats the sum of two numbers as string.
+/// Load a database from the data files, stop words, aliases,
+/// transliterations, tags, query rewrite rules and version in `data_dir`.
+/// The data files are those listed in `files`, or else in the directory's
+/// data-files.json, as paths relative to it or (kind, path) pairs, with
+/// kinds "json", "code-list" or a table of `load_from_csv`. Otherwise they
+/// are the default files and any regional files such as ISO-3166-2:GB.json.
+/// `on_progress(stage, done, total)` is called after each data file is
+/// read, and around the final build. With `low_memory`, the word index is
+/// moved into memory-mapped files in `index_dir`, or else in a temporary
+/// directory. `normalization` is a dict of "folding", from text to what it
+/// is replaced by wherever it appears, such as {"ø": "oe"}, and
+/// "expansions", from words to what they stand for, such as {"st":
+/// "saint"}. Both names and queries are normalized with them. A warning is
+/// logged if the directories of the data files record different versions.
+/// Raises FileNotFoundError listing any missing files.
 #[pyfunction]
-fn load(data_dir: String) -> PyResult<LocationsDbProxy> {
+#[pyo3(signature = (data_dir, on_progress=None, files=None, low_memory=false, index_dir=None, normalization=None))]
+fn load(
+    py: Python,
+    data_dir: String,
+    on_progress: Option<&PyAny>,
+    files: Option<Vec<&PyAny>>,
+    low_memory: bool,
+    index_dir: Option<PathBuf>,
+    normalization: Option<&PyDict>,
+) -> PyResult<LocationsDbProxy> {
+    let progress = Progress::new(py, on_progress);
     let data_path = PathBuf::from(data_dir);
-    let db = match parse_data_files(data_path) {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(PyTypeError::new_err(format!(
-                "JSON parsing errors:\n{}",
-                err.to_string()
-            )));
-        }
+    let files = match files {
+        Some(files) => files
+            .into_iter()
+            .map(|file| parse_data_file(&data_path, file))
+            .collect::<PyResult<Vec<_>>>()?,
+        None => DataFile::in_dir(&data_path).map_err(build_error)?,
     };
-    let db_proxy = LocationsDbProxy {
-        _db: Arc::new(Mutex::new(db)),
+    check_data_files(&files).map_err(build_error)?;
+    let versions = dataset_versions(&data_path, &files).map_err(build_error)?;
+    warn_if_mixed_versions(py, &versions)?;
+    let mut builder = LocationsDbBuilder::new().normalizer(parse_normalization(normalization)?);
+    if let Some((_, version)) = versions.into_iter().next() {
+        builder = builder.version(version);
+    }
+    for (n, file) in files.iter().enumerate() {
+        builder = py
+            .allow_threads(|| builder.add_data_file(file))
+            .map_err(build_error)?;
+        progress.report("files", n + 1, Some(files.len()))?;
+    }
+    builder = builder
+        .add_optional_files(&data_path)
+        .map_err(build_error)?;
+    progress.report("build", 0, Some(1))?;
+    let mut db = py.allow_threads(|| builder.build()).map_err(build_error)?;
+    progress.report("build", 1, Some(1))?;
+    if low_memory {
+        let index_dir = index_dir.unwrap_or_else(|| env::temp_dir().join(INDEX_DIR));
+        py.allow_threads(|| db.move_index_to_disk(&index_dir))?;
+    }
+    Ok(LocationsDbProxy::new(db))
+}
+
+/// Load a database from CSV files, given as a dict from table to path. The
+/// tables are "state" (with columns alpha2, alpha3, name, short and
+/// continent), "subdivision" (supercode, subcode, name and level), "iata"
+/// (iata, name, type, city, country, region, x, y and elevation), "nuts"
+/// (code, name and subdivision, such as "GB-SCT"), "fips" (code, name and
+/// subdivision, such as "US-CA"), "postal" (country, code, name,
+/// subdivision, lat and lon) and "locode", the UN/LOCODE code list as
+/// published. Other columns, such as "name:fr", are kept as fields.
+/// `on_progress(stage, done, total)` is called after each file is read, and
+/// around the final build. `normalization` customizes how names and queries
+/// are normalized, as for `load`.
+#[pyfunction]
+#[pyo3(signature = (files, on_progress=None, normalization=None))]
+fn load_from_csv(
+    py: Python,
+    files: &PyDict,
+    on_progress: Option<&PyAny>,
+    normalization: Option<&PyDict>,
+) -> PyResult<LocationsDbProxy> {
+    let progress = Progress::new(py, on_progress);
+    let mut tables = vec![];
+    for (table, path) in files.iter() {
+        let table = table.extract::<&str>()?;
+        match CsvTable::parse(table) {
+            Some(csv_table) => tables.push((csv_table, PathBuf::from(path.extract::<&str>()?))),
+            None => {
+                let err = PyValueError::new_err(format![
+                    "{} is not a table; use state, subdivision, locode, iata, nuts, fips or postal",
+                    table
+                ]);
+                return Err(err);
+            }
+        }
+    }
+    let total = tables.len();
+    let mut builder = LocationsDbBuilder::new().normalizer(parse_normalization(normalization)?);
+    for (n, (table, path)) in tables.into_iter().enumerate() {
+        builder = py
+            .allow_threads(|| builder.add_csv_table(table, &path))
+            .map_err(build_error)?;
+        progress.report("files", n + 1, Some(total))?;
+    }
+    progress.report("build", 0, Some(1))?;
+    let db = py.allow_threads(|| builder.build()).map_err(build_error)?;
+    progress.report("build", 1, Some(1))?;
+    Ok(LocationsDbProxy::new(db))
+}
+
+/// The JSON schema of the explanations returned by `explain`.
+#[pyfunction]
+fn score_breakdown_schema(py: Python) -> PyResult<PyObject> {
+    let schema = serde_json::to_value(ScoreBreakdown::schema()).expect("schemas serialize");
+    pyjson::to_py(py, &schema)
+}
+
+/// How `query` is parsed before searching, with the default stop words and
+/// normalization, as a dict of the normalized query, the recognized state
+/// and codes, the terms matched exactly and fuzzily, and the stop words.
+/// Terms are only exact if some loaded name or code is spelled that way.
+/// Offsets refer to the normalized query.
+#[pyfunction]
+#[pyo3(signature = (query, state=None))]
+fn parse_query(py: Python, query: String, state: Option<String>) -> PyResult<PyObject> {
+    let stop_words = DEFAULT_STOP_WORDS.map(String::from);
+    let (st, terms) = inspect_query(query, state, 1, 0, &stop_words, &Normalizer::default());
+    let spans = |terms: Vec<(String, berlin_core::search::Offset)>| {
+        terms
+            .into_iter()
+            .map(|(term, offset)| serde_json::json!({"term": term, "offset": [offset.start, offset.end]}))
+            .collect::<Vec<_>>()
     };
-    Ok(db_proxy)
+    let codes = st
+        .codes
+        .iter()
+        .map(|code| (code.term.to_string(), code.offset))
+        .collect();
+    let parsed = serde_json::json!({
+        "raw": st.raw,
+        "normalized": st.normalized,
+        "state": st.state_filter.map(|state| state.to_string()),
+        "codes": spans(codes),
+        "exact": spans(terms.exact),
+        "fuzzy": spans(terms.fuzzy),
+        "stop_words": terms.stop_words,
+    });
+    pyjson::to_py(py, &parsed)
+}
+
+/// The JSON schema of the dicts returned by `Location.to_dict`.
+#[pyfunction]
+fn location_schema(py: Python) -> PyResult<PyObject> {
+    let schema = serde_json::to_value(LocationRepr::schema()).expect("schemas serialize");
+    pyjson::to_py(py, &schema)
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 #[pyo3(name = "_berlin")]
-fn berlin(_py: Python, m: &PyModule) -> PyResult<()> {
+fn berlin(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<LocationProxy>()?;
+    m.add("LoadError", py.get_type::<LoadError>())?;
     m.add_function(wrap_pyfunction!(load, m)?)?;
     m.add_function(wrap_pyfunction!(load_from_json, m)?)?;
+    m.add_function(wrap_pyfunction!(load_from_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(score_breakdown_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(location_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_query, m)?)?;
     Ok(())
 }
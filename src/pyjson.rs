@@ -0,0 +1,73 @@
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use serde_json::{Map, Number, Value};
+
+/// Convert a plain Python object (dicts, lists, strings, numbers, bools and
+/// None) into a JSON value, without a round-trip through a JSON string.
+pub fn to_json(obj: &PyAny) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if let Ok(i) = obj.downcast::<PyLong>() {
+        Ok(Value::Number(Number::from(i.extract::<i64>()?)))
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        match Number::from_f64(f.value()) {
+            Some(n) => Ok(Value::Number(n)),
+            None => Err(PyTypeError::new_err(format!["{} is not a JSON number", f])),
+        }
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Ok(Value::String(s.to_str()?.to_string()))
+    } else if let Ok(d) = obj.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (k, v) in d.iter() {
+            let k = match k.downcast::<PyString>() {
+                Ok(k) => k.to_str()?.to_string(),
+                Err(_) => return Err(PyTypeError::new_err(format!["{} is not a string key", k])),
+            };
+            map.insert(k, to_json(v)?);
+        }
+        Ok(Value::Object(map))
+    } else if let Ok(l) = obj.downcast::<PyList>() {
+        Ok(Value::Array(
+            l.iter().map(to_json).collect::<PyResult<_>>()?,
+        ))
+    } else if let Ok(t) = obj.downcast::<PyTuple>() {
+        Ok(Value::Array(
+            t.iter().map(to_json).collect::<PyResult<_>>()?,
+        ))
+    } else {
+        Err(PyTypeError::new_err(format![
+            "Cannot convert {} to JSON",
+            obj.get_type().name()?
+        ]))
+    }
+}
+
+/// Convert a JSON value into the equivalent plain Python object.
+pub fn to_py(py: Python, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.to_object(py),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.to_object(py),
+            None => n.as_f64().to_object(py),
+        },
+        Value::String(s) => s.to_object(py),
+        Value::Array(values) => {
+            let items = values
+                .iter()
+                .map(|v| to_py(py, v))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items).to_object(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (k, v) in map {
+                dict.set_item(k, to_py(py, v)?)?;
+            }
+            dict.to_object(py)
+        }
+    })
+}
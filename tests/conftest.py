@@ -26,6 +26,26 @@ def load_test_code_list():
 def test_codes():
     return json.loads(load_test_codes())
 
+@pytest.fixture()
+def test_code_list():
+    return load_test_code_list()
+
 @pytest.fixture()
 def db():
     return load_from_json([[load_test_codes()]], load_test_code_list())
+
+@pytest.fixture()
+def region_files(tmp_path):
+    (tmp_path / "states.csv").write_text(
+        "alpha2,alpha3,name,continent\nGB,GBR,United Kingdom,EU\nUS,USA,United States,NA\n"
+    )
+    (tmp_path / "subdivisions.csv").write_text(
+        "supercode,subcode,name,level\n"
+        "GB,ABD,Aberdeenshire,council area\n"
+        "GB,LND,London,city\n"
+        "US,CA,California,state\n"
+    )
+    return {
+        "state": str(tmp_path / "states.csv"),
+        "subdivision": str(tmp_path / "subdivisions.csv"),
+    }
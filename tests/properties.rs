@@ -0,0 +1,132 @@
+//! Properties of the berlin-core parsing that every query and data file
+//! goes through: normalization, search terms and LOCODE coordinates. These
+//! look for panics on input such as non-ASCII or empty text, and for spans
+//! that do not point back into the normalized query.
+use berlin_core::coordinates::coordinate_parser;
+use berlin_core::normalize;
+use berlin_core::search::SearchTerm;
+use berlin_core::ustr::Ustr;
+use proptest::prelude::*;
+
+// Words that are interned, as the names of locations are once loaded, so
+// that queries containing them are matched exactly rather than fuzzily
+const VOCABULARY: [&str; 8] = [
+    "abercarn",
+    "bognor",
+    "regis",
+    "bognor regis",
+    "sofia",
+    "gb",
+    "lon",
+    "ile de france",
+];
+
+fn intern_vocabulary() {
+    for word in VOCABULARY {
+        Ustr::from(word);
+    }
+}
+
+// Queries mixing known words, stop words, punctuation and non-ASCII text
+fn query() -> impl Strategy<Value = String> {
+    let piece = prop_oneof![
+        prop::sample::select(VOCABULARY.to_vec()).prop_map(String::from),
+        prop::sample::select(vec!["in", "the", "of", " ", ", ", "-", "'", "  "])
+            .prop_map(String::from),
+        prop::sample::select(vec![
+            "Île",
+            "BOGNOR",
+            "北京市",
+            "مصر",
+            "ß",
+            "İ",
+            "ﬁ",
+            "🙂",
+            "e\u{301}"
+        ])
+        .prop_map(String::from),
+        any::<String>(),
+    ];
+    prop::collection::vec(piece, 0..8).prop_map(|pieces| pieces.join(" "))
+}
+
+// The terms a search term matches exactly and those it searches for
+fn terms(st: &SearchTerm) -> (Vec<String>, Vec<String>) {
+    let mut exact = vec![];
+    let mut not_exact = vec![];
+    st.build_search(
+        fst::map::OpBuilder::new(),
+        |op, term| {
+            not_exact.push(term.to_string());
+            op
+        },
+        |term| {
+            exact.push(term.to_string());
+            None
+        },
+    );
+    (exact, not_exact)
+}
+
+proptest! {
+    #[test]
+    fn normalize_is_ascii_and_idempotent(raw in any::<String>()) {
+        let normalized = normalize(&raw);
+        prop_assert!(normalized.is_ascii());
+        prop_assert_eq!(normalize(&normalized), normalized);
+    }
+
+    #[test]
+    fn search_terms_come_from_the_normalized_query(
+        raw in query(),
+        limit in 1usize..100,
+        lev_dist in 0u32..3,
+    ) {
+        intern_vocabulary();
+        let st = SearchTerm::from_raw_query(raw.clone(), None, limit, lev_dist);
+        prop_assert_eq!(&st.normalized, &normalize(&raw));
+        let (exact, not_exact) = terms(&st);
+        for term in exact.iter().chain(not_exact.iter()) {
+            prop_assert!(st.normalized.contains(term.as_str()), "{:?} not in {:?}", term, st.normalized);
+        }
+    }
+
+    #[test]
+    fn search_terms_match_known_words(word in prop::sample::select(VOCABULARY.to_vec())) {
+        intern_vocabulary();
+        let st = SearchTerm::from_raw_query(format!["Hotels in {}", word.to_uppercase()], None, 5, 2);
+        let score = st.match_str(word).expect("a word of the query should match");
+        prop_assert_eq!(&st.normalized[score.offset.start..score.offset.end], word);
+    }
+
+    #[test]
+    fn coordinate_parser_does_not_panic(raw in any::<String>()) {
+        let _ = coordinate_parser(&raw);
+    }
+
+    #[test]
+    fn coordinate_parser_reads_locode_coordinates(
+        lat_deg in 0u32..90,
+        lat_min in 0u32..60,
+        north in any::<bool>(),
+        lon_deg in 0u32..180,
+        lon_min in 0u32..60,
+        east in any::<bool>(),
+    ) {
+        let raw = format![
+            "{:02}{:02}{} {:03}{:02}{}",
+            lat_deg,
+            lat_min,
+            if north { 'N' } else { 'S' },
+            lon_deg,
+            lon_min,
+            if east { 'E' } else { 'W' },
+        ];
+        let (rest, coordinates) = coordinate_parser(&raw).expect("coordinates should parse");
+        prop_assert!(rest.is_empty());
+        let lat = (lat_deg as f64 + lat_min as f64 / 60.0) * if north { 1.0 } else { -1.0 };
+        let lon = (lon_deg as f64 + lon_min as f64 / 60.0) * if east { 1.0 } else { -1.0 };
+        prop_assert!((coordinates.lat - lat).abs() < 1e-9);
+        prop_assert!((coordinates.lon - lon).abs() < 1e-9);
+    }
+}
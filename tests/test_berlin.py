@@ -1,5 +1,14 @@
 import pytest
-from berlin import Location
+import ast
+import hashlib
+import importlib.util
+import json
+import logging
+import random
+import threading
+from pathlib import Path
+from berlin import Location, LoadError, fetch_data, load, load_from_csv, load_from_json, location_schema, parse_query, score_breakdown_schema
+from berlin.countries import country_enum, render_country_module
 
 def test_search_long(db):
     for query, match in (
@@ -54,6 +63,45 @@ def test_retrieve(db):
     assert db.get_subdiv_key(loc.get_state_code(), loc.get_subdiv_code()) == "ISO-3166-2-gb:cay"
     assert loc.subdiv.id == "gb:cay"
 
+def test_retrieve_tolerant_keys(db):
+    for term in ("UN-LOCODE-GB:SVN", "un-locode gb svn", "GB:SVN", "gb svn", "GBSVN", " gb:svn "):
+        assert db.retrieve(term).key == "UN-LOCODE-gb:svn"
+    assert db.retrieve("gb:abc").key == "UN-LOCODE-gb:abc"
+    assert db.retrieve("GB-ABC").key == "ISO-3166-2-gb:abc"
+    assert db.retrieve("iso-3166-2 gb abc").key == "ISO-3166-2-gb:abc"
+    assert db.retrieve("GB").key == "ISO-3166-1-gb"
+    with pytest.raises(KeyError):
+        db.retrieve("GB:XXX")
+
+def test_retrieve_many(db):
+    result = db.retrieve_many(["UN-LOCODE-gb:svn", "GB:ABC", "UN-LOCODE-gb:xxx"])
+    assert list(result) == ["UN-LOCODE-gb:svn", "GB:ABC", "UN-LOCODE-gb:xxx"]
+    assert result["UN-LOCODE-gb:svn"].get_names() == ["stonehaven"]
+    assert result["GB:ABC"].key == "UN-LOCODE-gb:abc"
+    assert result["UN-LOCODE-gb:xxx"] is None
+    assert db.retrieve_many([]) == {}
+
+def test_retrieve_many_threads(db):
+    keys = ["UN-LOCODE-gb:svn", "GB:ABC"]
+    errors = []
+    def lookups(call):
+        try:
+            for _ in range(200):
+                call()
+        except Exception as err:
+            errors.append(err)
+    threads = [
+        threading.Thread(target=lookups, args=(lambda: db.retrieve_many(keys),), daemon=True),
+        threading.Thread(target=lookups, args=(lambda: db.query("stonehaven", 1, 2),), daemon=True),
+        threading.Thread(target=lookups, args=(lambda: db.retrieve_many(keys),), daemon=True),
+    ]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join(timeout=30)
+    assert not any(thread.is_alive() for thread in threads)
+    assert errors == []
+
 def test_retrieve_with_score(db):
     loc = db.retrieve("UN-LOCODE-gb:abc")
     with pytest.raises(AttributeError):
@@ -89,6 +137,39 @@ def test_retrieve_country_children(db):
 
     assert stonehaven.children == []
 
+def test_hierarchy_queries(db):
+    children = db.get_children("ISO-3166-1-gb")
+    assert [loc.key for loc in children] == ["ISO-3166-2-gb:abc", "ISO-3166-2-gb:abd", "ISO-3166-2-gb:cay", "ISO-3166-2-gb:wsx"]
+
+    descendants = db.get_descendants("ISO-3166-1-gb")
+    assert [loc.key for loc in descendants][:4] == [loc.key for loc in children]
+    assert "UN-LOCODE-gb:svn" in {loc.key for loc in descendants}
+    assert [loc.key for loc in db.get_descendants("ISO-3166-1-gb", depth=1)] == [loc.key for loc in children]
+    assert db.get_descendants("ISO-3166-1-gb", depth=0) == []
+
+    subtree = db.get_subtree("ISO-3166-2-gb:abd")
+    assert subtree["key"] == "ISO-3166-2-gb:abd"
+    assert subtree["name"] == "Aberdeenshire"
+    assert [child["key"] for child in subtree["children"]] == ["UN-LOCODE-gb:svn"]
+    assert subtree["children"][0]["children"] == []
+
+    with pytest.raises(KeyError):
+        db.get_children("UN-LOCODE-gb:xxx")
+
+def test_retrieve_ancestors_and_siblings(db):
+    stonehaven = db.retrieve("UN-LOCODE-gb:svn")
+    assert stonehaven.parent.key == "ISO-3166-2-gb:abd"
+    assert [loc.key for loc in stonehaven.ancestors] == ["ISO-3166-2-gb:abd", "ISO-3166-1-gb"]
+    assert stonehaven.siblings == []
+
+    aberdeen = stonehaven.parent
+    assert {loc.key for loc in aberdeen.siblings} == {"ISO-3166-2-gb:cay", "ISO-3166-2-gb:wsx", "ISO-3166-2-gb:abc"}
+
+    country = db.retrieve("ISO-3166-1-gb")
+    assert country.parent is None
+    assert country.ancestors == []
+    assert country.siblings == []
+
 def test_search_for_generic_with_state(db):
     for query in ("Dentists in Two2", "Dental Two2"):
         state = "GB"
@@ -145,3 +226,1353 @@ def test_list(db, test_codes):
             )
         )
     )
+
+def test_int_ids(db, test_codes):
+    loc = db.retrieve("UN-LOCODE-gb:abc")
+    assert isinstance(loc.int_id, int)
+    assert db.get_int_id("UN-LOCODE-gb:abc") == loc.int_id
+    assert db.retrieve_by_int_id(loc.int_id).key == loc.key
+
+    keys = {db.retrieve_by_int_id(int_id).key for int_id in range(len(test_codes))}
+    assert len(keys) == len(test_codes)
+    assert all(db.get_int_id(key) < len(test_codes) for key in keys)
+
+    with pytest.raises(KeyError):
+        db.retrieve_by_int_id(len(test_codes))
+
+def test_int_ids_persist(db, test_codes, test_code_list, tmp_path):
+    ids_file = tmp_path / "int-ids.json"
+    db.save_int_ids(ids_file)
+    ids = {key: db.get_int_id(key) for key in json.loads(ids_file.read_text())}
+
+    # Abercarn sorts before Stonehaven, so would shift the ids after it
+    del test_codes["GB:ABC"]
+    other = load_from_json([[json.dumps(test_codes)]], test_code_list)
+    other.load_int_ids(ids_file)
+    for key, int_id in ids.items():
+        if key != "UN-LOCODE-gb:abc":
+            assert other.get_int_id(key) == int_id
+    added = other.add_location("MY-STANDARD", "GB:WLV", {"name": "Wolverton", "supercode": "GB", "subcode": "WLV"})
+    assert added.int_id == len(ids)
+
+    ids_file.write_text(json.dumps({"ISO-3166-1-gb": 0, "ISO-3166-1-bg": 0}))
+    with pytest.raises(ValueError):
+        other.load_int_ids(ids_file)
+
+def test_search_exact(db):
+    result = db.query("Dentists in Bognor Regis", 2, 2, state="GB", exact=True)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:bsi"]
+    assert result[0].get_offset() == (12, 24)
+
+    assert db.query("Dentists in Bognor Regs", 2, 2, state="GB", exact=True) == []
+    assert len(db.query("Dentists in Bognor Regs", 2, 2, state="GB")) == 1
+
+    assert db.query("Lözarevo", 1, 2, exact=True) == []
+    assert db.query("Lözarevo", 1, 2)[0].key == "UN-LOCODE-bg:loz"
+
+def test_add_location(db):
+    assert db.query("Deliveries to Wolverton Works", 1, 2, state="GB") == []
+
+    loc = db.add_location("MY-STANDARD", "GB:WLV", {
+        "name": "Wolverton Works",
+        "supercode": "GB",
+        "subcode": "WLV",
+        "subdivision_code": "ABD",
+    })
+    assert loc.key == "MY-STANDARD-gb:wlv"
+    assert db.retrieve("MY-STANDARD-gb:wlv").get_names() == ["wolverton works"]
+    assert db.retrieve_by_int_id(loc.int_id).key == loc.key
+    assert loc.subdiv.key == "ISO-3166-2-gb:abd"
+    assert "MY-STANDARD-gb:wlv" in {child.key for child in loc.subdiv.children}
+
+    result = db.query("Deliveries to Wolverton Works", 1, 2, state="GB")
+    assert [loc.key for loc in result] == ["MY-STANDARD-gb:wlv"]
+
+    with pytest.raises(TypeError):
+        db.add_location("MY-STANDARD", "GB:BAD", {"name": "Missing Codes"})
+
+    db.queue_removal("ISO-3166-1-bg")
+    db.flush_updates()
+    with pytest.raises(KeyError):
+        db.add_location("MY-STANDARD", "BG:9", {"name": "Zedtown", "supercode": "BG", "subcode": "QQQ"})
+    assert [loc.key for loc in db.query("Deliveries to Wolverton Works", 1, 2)] == ["MY-STANDARD-gb:wlv"]
+
+def test_false_positives(db):
+    corpus = ["interest rate", "average income taxes", "dentists in bognor regis"]
+    matches = db.false_positives(corpus, 2, state="GB")
+    assert [m["query"] for m in matches] == ["dentists in bognor regis"]
+    assert matches[0]["key"] == "UN-LOCODE-gb:bsi"
+    assert matches[0]["offset"] == (12, 24)
+
+    assert db.false_positives(corpus, 2, threshold=matches[0]["score"], state="GB") == []
+
+def test_add_alias(db):
+    assert db.query("Ferries from Bognor", 1, 2, state="GB", exact=True) == []
+
+    db.add_alias("UN-LOCODE-gb:bsi", "Bognor")
+    db.add_alias("ISO-3166-1-bg", "Balgariya")
+
+    for exact in (False, True):
+        result = db.query("Ferries from Bognor", 1, 2, state="GB", exact=exact)
+        assert [loc.key for loc in result] == ["UN-LOCODE-gb:bsi"]
+        assert result[0].get_offset() == (13, 19)
+
+    result = db.query("Hotels in Balgariya", 1, 2)
+    assert [loc.key for loc in result] == ["ISO-3166-1-bg"]
+    # aliases are not matched fuzzily
+    db.add_alias("UN-LOCODE-gb:bsi", "Smokeyton")
+    assert [loc.key for loc in db.query("Hotels in Smokeyton", 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    assert db.query("Hotels in Smokeytan", 1, 2) == []
+
+    with pytest.raises(KeyError):
+        db.add_alias("UN-LOCODE-gb:xxx", "Nowhere")
+
+def test_search_limit_preserves_ranking(db):
+    for query in ("Burgas", "Dentists in Abercarn", "Blagoevgrad Lozarevo", "Stonehaven Aberdeenshire"):
+        ranked = [loc.key for loc in db.query(query, 10, 2)]
+        for limit in range(1, len(ranked) + 1):
+            assert [loc.key for loc in db.query(query, limit, 2)] == ranked[:limit]
+            # truncating candidates before the graph analysis
+            result = db.query(query, limit, 2, candidate_limit=limit)
+            assert [loc.key for loc in result] == ranked[:limit]
+
+def test_stop_words(db):
+    assert "in" in db.get_stop_words()
+    assert [loc.key for loc in db.query("Garmen", 1, 2)] == ["UN-LOCODE-bg:da3"]
+
+    db.set_stop_words(["Garmen", "in"])
+    assert db.get_stop_words() == ["garmen", "in"]
+    assert db.query("Garmen", 1, 2) == []
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+def test_normalization(test_codes, test_code_list):
+    for code, name in (("GB:MAS", "Mountain Ash"), ("GB:MTB", "Møntby")):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "UN-LOCODE", "i": code,
+            "d": {"name": name, "supercode": supercode, "subcode": subcode, "subdivision_code": "CAY", "function_code": "1-------"},
+        }
+    normalization = {"expansions": {"mtn": "mountain", "a.": "ash"}, "folding": {"ø": "oe"}}
+    db = load_from_json([json.dumps(test_codes)], test_code_list, normalization=normalization)
+    assert db.normalize("Mtn. A. Møntby, A Mtn") == "mountain ash moentby, a mountain"
+
+    for query, span in (("Mountain Ash", "Mountain Ash"), ("Hotels in Mtn. Ash", "Mtn. Ash")):
+        result = db.query(query, 1, 2)
+        assert result[0].key == "UN-LOCODE-gb:mas"
+        assert query[slice(*result[0].get_offset())] == span
+    result = db.query("MOENTBY", 1, 0)
+    assert result[0].key == "UN-LOCODE-gb:mtb"
+
+    # without it, "mtn" is not expanded
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+    assert db.normalize("Mtn. Ash") == "mtn. ash"
+
+    with pytest.raises(ValueError):
+        load_from_json([json.dumps(test_codes)], test_code_list, normalization={"stemming": {}})
+
+def test_abbreviations(test_codes, test_code_list):
+    for code, name in (("GB:SAL", "St Albans"), ("GB:MTG", "Mount Gambier")):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "UN-LOCODE", "i": code,
+            "d": {"name": name, "supercode": supercode, "subcode": subcode, "subdivision_code": "CAY", "function_code": "1-------"},
+        }
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+    assert db.normalize("St. Albans, N. Mt Gambier, N Ireland") == "saint albans, north mount gambier, n ireland"
+
+    for query, key in (
+        ("Saint Albans", "UN-LOCODE-gb:sal"),
+        ("cafes in St. Albans", "UN-LOCODE-gb:sal"),
+        ("Mt Gambier", "UN-LOCODE-gb:mtg"),
+        ("Mt. Gambier", "UN-LOCODE-gb:mtg"),
+    ):
+        result = db.query(query, 1, 2)
+        assert result[0].key == key
+    query = "Mt. Gambier"
+    assert query[slice(*db.query(query, 1, 2)[0].get_offset())] == "Mt. Gambier"
+    assert db.normalize("Hotels in the U.K.") == "hotels in the uk"
+
+    assert db.query("Bgr", 1, 0) == []
+    db.add_abbreviation("Bgr", "Bognor")
+    assert [loc.key for loc in db.query("Bgr", 1, 0)] == ["UN-LOCODE-gb:bsi"]
+
+def test_hyphenated_and_apostrophe_names(test_codes, test_code_list):
+    for code, name in (
+        ("GB:STK", "Stoke-on-Trent"),
+        ("GB:BSF", "Bishop's Stortford"),
+        ("GB:SRP", "Saint-Remy-de-Provence"),
+    ):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "UN-LOCODE", "i": code,
+            "d": {"name": name, "supercode": supercode, "subcode": subcode, "subdivision_code": "CAY", "function_code": "1-------"},
+        }
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+
+    for query, key, span in (
+        ("Stoke on Trent", "UN-LOCODE-gb:stk", "Stoke on Trent"),
+        ("hotels in stoke-on-trent", "UN-LOCODE-gb:stk", "stoke-on-trent"),
+        ("Trent", "UN-LOCODE-gb:stk", "Trent"),
+        ("Bishops Stortford", "UN-LOCODE-gb:bsf", "Bishops Stortford"),
+        ("Bishop's Stortford", "UN-LOCODE-gb:bsf", "Bishop's Stortford"),
+        ("Saint-Remy-de-Provence", "UN-LOCODE-gb:srp", "Saint-Remy-de-Provence"),
+        ("Saint Remy de Provence", "UN-LOCODE-gb:srp", "Saint Remy de Provence"),
+    ):
+        result = db.query(query, 1, 0)
+        assert result[0].key == key
+        assert query[slice(*result[0].get_offset())] == span
+
+    # a part alone is a weaker match than the whole name
+    assert db.query("Trent", 1, 0)[0].get_score() < db.query("Stoke on Trent", 1, 0)[0].get_score()
+    assert db.explain("Trent", "UN-LOCODE-gb:stk", 0)["tier"] == "infix"
+
+def test_short_words(test_codes, test_code_list):
+    for code, name in (
+        ("GB:RYH", "Rye Harbour"),
+        ("GB:ZQB", "Zennor of Qua Bay"),
+    ):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "UN-LOCODE", "i": code,
+            "d": {"name": name, "supercode": supercode, "subcode": subcode, "subdivision_code": "CAY", "function_code": "1-------"},
+        }
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+
+    result = db.query("walks near Rye Harbour", 1, 0)
+    assert result[0].key == "UN-LOCODE-gb:ryh"
+    keys = [r.key for r in db.query("Qua", 5, 0)]
+    assert "UN-LOCODE-gb:zqb" in keys
+    assert db.explain("Qua", "UN-LOCODE-gb:zqb", 0)["tier"] == "infix"
+
+    # short words match exactly, and stop words not at all
+    assert "UN-LOCODE-gb:zqb" not in [r.key for r in db.query("Quo", 5, 0)]
+    assert "UN-LOCODE-gb:zqb" not in [r.key for r in db.query("of", 5, 0)]
+
+def test_query_rewriting(db, tmp_path):
+    assert db.query("Lozza", 1, 0) == []
+    seen = []
+    def rewriter(parsed):
+        seen.append(parsed)
+        return {"synonyms": {"lozza": "lozarevo"}}
+    db.set_rewriter(rewriter)
+    result = db.query("Lozza", 1, 0)
+    assert result[0].key == "UN-LOCODE-bg:loz"
+    assert result[0].get_offset() == (0, 5)
+    assert seen == [{"query": "Lozza", "normalized": "lozza", "words": ["lozza"], "state": None}]
+
+    db.set_rewriter(lambda parsed: {"state": "BG"})
+    assert db.query("Abercarn", 1, 0) == []
+    db.set_rewriter(lambda parsed: {"stop_words": ["abercarn"]})
+    with pytest.raises(ValueError):
+        db.query("Abercarn", 1, 0)
+    db.set_rewriter(None)
+    assert db.query("Abercarn", 1, 0)[0].key == "UN-LOCODE-gb:abc"
+
+    rules = tmp_path / "rewrites.json"
+    rules.write_text(json.dumps({"drop": ["Abercarn"], "states": {"wales": "bg"}}))
+    db.load_rewrites(str(rules))
+    assert db.query("Abercarn", 1, 0) == []
+    rules.write_text(json.dumps({"states": {"wales": "bg"}}))
+    db.load_rewrites(str(rules))
+    assert db.query("Abercarn", 1, 0)[0].key == "UN-LOCODE-gb:abc"
+    assert db.query("Abercarn Wales", 1, 0) == []
+    rules.write_text(json.dumps({"replace": {}}))
+    with pytest.raises(TypeError):
+        db.load_rewrites(str(rules))
+
+def test_search_candidate_limit(db):
+    query = "abercarn aberdeenshire bognor stonehaven"
+    ranked = [loc.key for loc in db.query(query, 10, 2)]
+    assert len(ranked) > 2
+    for candidate_limit in (0, 1, 10, 100):
+        result = db.query(query, 2, 2, candidate_limit=candidate_limit)
+        assert [loc.key for loc in result] == ranked[:2]
+
+def test_alt_names(db):
+    loc = db.retrieve("ISO-3166-1-bg")
+    assert loc.get_names() == ["bulgaria"]
+    assert loc.get_alt_names() == {"fr": ["Bulgarie"], "de": ["Bulgarien", "Republik Bulgarien"]}
+    assert loc.matched_language is None
+
+    result = db.query("Ferien in Bulgarien", 1, 2)
+    assert [loc.key for loc in result] == ["ISO-3166-1-bg"]
+    assert result[0].matched_language == "de"
+    assert result[0].get_offset() == (10, 19)
+
+    result = db.query("Bulgaria holidays", 1, 2)
+    assert result[0].matched_language is None
+
+    db.add_location("MY-STANDARD", "BG:SOF", {
+        "name": "Sofia", "supercode": "BG", "subcode": "SOF", "name:bg": "София",
+    })
+    result = db.query("Хотели в София", 1, 2, exact=True)
+    assert [loc.key for loc in result] == ["MY-STANDARD-bg:sof"]
+    assert result[0].matched_language == "bg"
+
+def test_tags(db, tmp_path):
+    query = "abercarn aberdeenshire bognor stonehaven"
+    ranked = [loc.key for loc in db.query(query, 10, 2)]
+    assert db.retrieve(ranked[1]).tags == []
+
+    db.add_tag(ranked[1], "customer-port")
+    db.add_tag(ranked[1], "eu")
+    assert db.retrieve(ranked[1]).tags == ["customer-port", "eu"]
+    assert [loc.key for loc in db.query(query, 10, 2, tags=["eu"])] == [ranked[1]]
+    assert db.query(query, 10, 2, tags=["eu", "restricted"]) == []
+
+    tags_file = tmp_path / "tags.json"
+    tags_file.write_text(json.dumps({ranked[2]: ["eu"]}))
+    db.load_tags(str(tags_file))
+    result = db.query(query, 10, 2, tags=["eu"])
+    assert [loc.key for loc in result] == [ranked[1], ranked[2]]
+
+    db.remove_tag(ranked[1], "eu")
+    assert [loc.key for loc in db.query(query, 10, 2, tags=["eu"])] == [ranked[2]]
+    with pytest.raises(KeyError):
+        db.add_tag("UN-LOCODE-xx:xxx", "eu")
+
+    tags_file.write_text(json.dumps({ranked[3]: ["restricted"], "UN-LOCODE-xx:xxx": ["restricted"]}))
+    with pytest.raises(KeyError):
+        db.load_tags(str(tags_file))
+    assert db.retrieve(ranked[3]).tags == []
+
+def test_filters_keep_parent_boosts(db):
+    query = "Stonehaven Aberdeenshire"
+    [unfiltered] = [loc for loc in db.query(query, 10, 2) if loc.key == "UN-LOCODE-gb:svn"]
+    db.add_tag("UN-LOCODE-gb:svn", "eu")
+    result = db.query(query, 10, 2, tags=["eu"])
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn"]
+    assert result[0].get_score() == unfiltered.get_score()
+
+def test_search_phonetic(db):
+    assert db.query("Hotels in Stownhayvun", 1, 2) == []
+    result = db.query("Hotels in Stownhayvun", 1, 2, phonetic=True)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn"]
+    assert result[0].get_offset() == (10, 21)
+    result = db.query("Hotels in Stonehaven", 1, 2, phonetic=True)
+    assert result[0].get_score() > db.query("Hotels in Stownhayvun", 1, 2, phonetic=True)[0].get_score()
+
+def test_search_lev_distance(db):
+    query = "Hotels in Stonahevan"
+    assert db.query(query, 1, 2) == []
+    for lev_distance in (3, 4):
+        result = db.query(query, 1, lev_distance)
+        assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn"]
+    with pytest.raises(ValueError):
+        db.query(query, 1, 5)
+
+def test_search_infix(db):
+    assert db.query("Flights to gnor", 1, 0) == []
+    result = db.query("Flights to gnor", 1, 0, infix=True)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:bsi"]
+    assert result[0].get_offset() == (11, 15)
+    assert db.query("Flights to gno", 1, 0, infix=True) == []
+
+    db.add_location("MY-STANDARD", "GB:SUA", {
+        "name": "Stratford-upon-Avon", "supercode": "GB", "subcode": "SUA",
+    })
+    result = db.query("upon avon", 1, 0, infix=True)
+    assert [loc.key for loc in result] == ["MY-STANDARD-gb:sua"]
+
+def test_countries(db):
+    assert db.countries() == [
+        ("BG", "BGR", "Bulgaria", "ISO-3166-1-bg"),
+        ("GB", "GBR", "United Kingdom of Great Britain and Northern Ireland", "ISO-3166-1-gb"),
+    ]
+    Country = country_enum(db)
+    assert Country.BG.alpha3 == "BGR"
+    assert Country.from_key("ISO-3166-1-gb") is Country.GB
+    assert Country.from_alpha3("bgr") is Country.BG
+    assert db.retrieve(Country.BG.key).get_state_code() == "bg"
+
+    namespace = {}
+    exec(render_country_module(db), namespace)
+    assert namespace["Country"].GB.country_name == Country.GB.country_name
+
+def test_search_text(db):
+    document = (
+        "Le café near Abercarn was busy. " + "Nothing to see here at all. " * 10
+        + "Later, the team travelled on to Stonehaven, and then back to Abercarn."
+    )
+    result = db.search_text(document, threshold=900)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:abc", "UN-LOCODE-gb:svn"]
+    for loc in result:
+        start, end = loc.get_offset()
+        assert document[start:end].lower() in loc.get_names()
+    assert result[0].get_offset() == (13, 21)
+
+def test_coordinate_validation(test_codes):
+    gb_points = {
+        "AAA": "5130N 00007W", "AAB": "5229N 00154W", "AAC": "5357N 00105W",
+        "AAD": "5557N 00311W", "AAE": "5024N 00408W", "AAI": "5458N 00137W",
+        "AAJ": "5325N 00259W", "AAK": "5727N 00414W", "AAL": "5238N 00117E",
+        "AAF": "5130S 00010W",
+        "AAG": "1800N 00400E", "AAH": "9530N 00010W",
+    }
+    rows = []
+    for subcode, coordinates in gb_points.items():
+        test_codes[f"GB:{subcode}"] = {
+            "<c>": "UN-LOCODE", "i": f"GB:{subcode}",
+            "d": {"name": f"Place {subcode}", "supercode": "GB", "subcode": subcode,
+                  "function_code": "1-------"},
+        }
+        rows.append({"country": "GB", "subcode": subcode, "coordinates": coordinates})
+    db = load_from_json([[json.dumps(test_codes)]], rows)
+
+    lat, lon = db.retrieve("UN-LOCODE-gb:aaa").coordinates
+    assert lat == pytest.approx(51.5) and lon == pytest.approx(-7 / 60)
+    lat, lon = db.retrieve("UN-LOCODE-gb:aaf").coordinates
+    assert lat == pytest.approx(51.5) and lon == pytest.approx(-1 / 6)
+    assert db.retrieve("UN-LOCODE-gb:aah").coordinates is None
+
+    report = {issue["key"]: issue for issue in db.load_report()}
+    assert sorted(report) == ["UN-LOCODE-gb:aaf", "UN-LOCODE-gb:aag", "UN-LOCODE-gb:aah"]
+    assert report["UN-LOCODE-gb:aaf"]["reason"] == "latitude hemisphere flipped"
+    assert not report["UN-LOCODE-gb:aaf"]["dropped"]
+    assert report["UN-LOCODE-gb:aag"]["corrected"] is None
+    assert not report["UN-LOCODE-gb:aag"]["dropped"]
+    assert report["UN-LOCODE-gb:aah"]["reason"] == "out of range"
+    assert report["UN-LOCODE-gb:aah"]["dropped"]
+
+def test_offsets_refer_to_raw_query(db):
+    query = "Straße to Stonehaven"
+    result = db.query(query, 1, 2)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn"]
+    start, end = result[0].get_offset()
+    assert query[start:end] == "Stonehaven"
+
+    query = "İİ Хотели в Stonehaven"
+    result = db.query(query, 1, 2, exact=True)
+    start, end = result[0].get_offset()
+    assert query[start:end] == "Stonehaven"
+
+def test_offsets_of_words_within_words(db):
+    # "abercarn" first appears inside "abercarnage", but matched its own word
+    query = "Abercarnage, Abercarn"
+    result = db.query(query, 1, 2)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:abc"]
+    assert result[0].get_offset() == (13, 21)
+
+    query = "Abercarn or Abercarn"
+    result = db.query(query, 1, 2)
+    assert query[slice(*result[0].get_offset())] == "Abercarn"
+
+def test_load_from_json_generator(test_codes, test_code_list):
+    def blocks():
+        for key, entry in test_codes.items():
+            yield json.dumps({key: entry})
+
+    db = load_from_json(blocks(), test_code_list)
+    assert db.retrieve("UN-LOCODE-gb:abc").get_names() == ["abercarn"]
+    assert db.retrieve("ISO-3166-1-bg").get_alt_names()["fr"] == ["Bulgarie"]
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+    with pytest.raises(TypeError):
+        load_from_json(iter(["{", json.dumps({"BG": test_codes["BG"]})]), [])
+
+def test_load_from_json_objects(test_codes, test_code_list):
+    db = load_from_json([test_codes], test_code_list)
+    assert db.retrieve("UN-LOCODE-gb:abc").get_names() == ["abercarn"]
+
+    blocks = [{key: entry} for key, entry in test_codes.items()]
+    db = load_from_json([blocks[:2], json.dumps(blocks[2]), blocks[3:]], test_code_list)
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+    with pytest.raises(TypeError):
+        load_from_json([{"BG": {"<c>": "ISO-3166-1", "i": object()}}], [])
+
+def test_load_invalid_elevation(test_codes, test_code_list):
+    airport = {
+        "<c>": "IATA", "i": "SVN",
+        "d": {"name": "Stonehaven Airfield", "iata": "SVN", "type": "small_airport",
+              "country": "GB", "region": "GB-ABD", "y": 56.96, "x": -2.2, "elevation": "high"},
+    }
+    with pytest.raises(TypeError, match="IATA:SVN: not a whole number of feet"):
+        load_from_json([{**test_codes, "IATA:SVN": airport}], test_code_list)
+    airport["d"]["elevation"] = "112"
+    db = load_from_json([{**test_codes, "IATA:SVN": airport}], test_code_list)
+    assert db.retrieve("IATA-svn").elevation == 112
+    with pytest.raises(AttributeError):
+        db.retrieve("IATA-svn").altitude
+
+def test_load_error_report(test_codes, test_code_list):
+    bad_state = {"<c>": "ISO-3166-1", "i": "XX", "d": {"name": "Nowhere"}}
+    rows = test_code_list + [{"subcode": "XXX"}]
+    with pytest.raises(LoadError) as excinfo:
+        load_from_json([{**test_codes, "XX": bad_state}, "{"], rows)
+    errors = sorted(excinfo.value.errors, key=lambda error: error["block"])
+    assert errors[0] == {
+        "block": "0", "index": None, "record": "XX", "field": "short",
+        "message": errors[0]["message"],
+    }
+    assert "short" in errors[0]["message"]
+    assert errors[1]["block"] == "1" and errors[1]["record"] is None
+    assert errors[2] == {
+        "block": "rows", "index": len(test_code_list), "record": None, "field": "country",
+        "message": "No country",
+    }
+    # still a TypeError, as before the report
+    assert isinstance(excinfo.value, TypeError)
+
+def test_query_extract(db):
+    query = "Flights from Stonehaven to Bognor Regis"
+    result = db.query(query, 5, 2, extract=True)
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn", "UN-LOCODE-gb:bsi"]
+    assert [query[slice(*loc.get_offset())] for loc in result] == ["Stonehaven", "Bognor Regis"]
+
+    assert [loc.key for loc in db.query(query, 1, 2, extract=True)] == ["UN-LOCODE-gb:bsi"]
+
+def test_query_coverage(db):
+    [loc] = db.query("Dentists in Bognor Regis", 1, 2)
+    assert loc.key == "UN-LOCODE-gb:bsi"
+    assert loc.coverage == pytest.approx(2 / 3)
+    [loc] = db.query("Stonehaven", 1, 2)
+    assert loc.coverage == 1.0
+    assert db.retrieve("UN-LOCODE-gb:bsi").coverage is None
+
+def test_query_strict(db):
+    assert [loc.key for loc in db.query("garden", 1, 2)] == ["UN-LOCODE-bg:da3"]
+    assert db.query("garden", 1, 2, strict=True) == []
+    assert [loc.key for loc in db.query("Garmen", 1, 2, strict=True)] == ["UN-LOCODE-bg:da3"]
+    assert [loc.key for loc in db.query("Regis", 1, 2, strict=True)] == ["UN-LOCODE-gb:bsi"]
+
+    db.strict_threshold = 700
+    assert [loc.key for loc in db.query("garden", 1, 2, strict=True)] == ["UN-LOCODE-bg:da3"]
+
+def test_query_context(db):
+    query = "Bognor or near Stonehavn"
+    assert [loc.key for loc in db.query(query, 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    result = db.query(query, 1, 2, context=["Near", "travelling to"])
+    assert [loc.key for loc in result] == ["UN-LOCODE-gb:svn"]
+    # a cue must be a whole word before the name
+    assert [loc.key for loc in db.query(query, 1, 2, context=["ear"])] == ["UN-LOCODE-gb:bsi"]
+
+def test_importance(db):
+    query = "Bognor or Stonehavn"
+    assert [loc.key for loc in db.query(query, 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    db.set_importance({"UN-LOCODE-gb:svn": 1e6})
+    assert db.retrieve("UN-LOCODE-gb:svn").importance == 1e6
+    assert db.retrieve("UN-LOCODE-gb:bsi").importance is None
+    assert [loc.key for loc in db.query(query, 1, 2)] == ["UN-LOCODE-gb:svn"]
+    db.importance_weight = 0.0
+    assert db.importance_weight == 0.0
+    assert [loc.key for loc in db.query(query, 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    with pytest.raises(KeyError):
+        db.set_importance({"UN-LOCODE-gb:nowhere": 1.0})
+
+def test_continent(test_codes, test_code_list):
+    test_codes["JP"] = {
+        "<c>": "ISO-3166-1", "i": "JP",
+        "d": {"name": "Japan", "short": "Japan", "alpha2": "JP", "alpha3": "JPN", "continent": "AS"},
+    }
+    test_codes["JP:01"] = {
+        "<c>": "ISO-3166-2", "i": "JP:01",
+        "d": {"name": "Abercarn", "supercode": "JP", "subcode": "01", "level": "prefecture"},
+    }
+    db = load_from_json([[json.dumps(test_codes)]], test_code_list)
+    assert [loc.key for loc in db.states_by_continent("eu")] == ["ISO-3166-1-bg", "ISO-3166-1-gb"]
+    assert [loc.key for loc in db.states_by_continent("AS")] == ["ISO-3166-1-jp"]
+    assert db.states_by_continent("oc") == []
+
+    assert len(db.query("Abercarn", 5, 2)) == 2
+    assert [loc.key for loc in db.query("Abercarn", 5, 2, continent="EU")] == ["UN-LOCODE-gb:abc"]
+    assert [loc.key for loc in db.query("Abercarn", 5, 2, continent="as")] == ["ISO-3166-2-jp:01"]
+
+def test_query_timing(db):
+    results, timings = db.query("Abercarn", 5, 2, timing=True)
+    assert [loc.key for loc in results] == [loc.key for loc in db.query("Abercarn", 5, 2)]
+    assert set(timings) == {
+        "parse", "fst_scan", "scoring", "graph", "sorting",
+        "lock", "search", "grouping", "results", "total",
+    }
+    assert all(ms >= 0 for ms in timings.values())
+    assert timings["search"] >= timings["fst_scan"] + timings["scoring"]
+    assert timings["total"] >= timings["search"]
+
+    results, timings = db.query("abercarn", 5, 2, exact=True, group_by="state", timing=True)
+    assert list(results) == ["ISO-3166-1-gb"]
+    assert timings["parse"] == timings["fst_scan"] == 0
+
+def test_parent_boosts(db):
+    results = db.query("Stonehaven, Aberdeenshire", 2, 0, parent_boosts=True)
+    assert [loc.key for loc in results] == ["UN-LOCODE-gb:svn", "ISO-3166-2-gb:abd"]
+    parent, boost = results[0].parent_boost
+    assert parent == "ISO-3166-2-gb:abd" and boost > 0
+    assert results[1].parent_boost is None
+    assert [loc.get_score() for loc in results] == [loc.get_score() for loc in db.query("Stonehaven, Aberdeenshire", 2, 0)]
+    assert db.query("Stonehaven, Aberdeenshire", 1, 0)[0].parent_boost is None
+
+    # cached results carry no boosts, so they are not taken from the cache
+    db.set_cache(2)
+    db.query("Stonehaven, Aberdeenshire", 2, 0)
+    assert db.query("Stonehaven, Aberdeenshire", 2, 0, parent_boosts=True)[0].parent_boost == (parent, boost)
+
+def test_graph_config(db):
+    query = "Stonehaven, Aberdeenshire, United Kingdom"
+    assert db.graph_config["edge_threshold"] == 600 and db.graph_config["chains"] is False
+    assert db.query(query, 1, 0)[0].key == "ISO-3166-2-gb:abd"
+    unboosted = db.query(query, 3, 0, graph=False)
+    assert unboosted[0].key == "ISO-3166-1-gb"
+
+    # the state's and subdivision's boosts add up
+    db.configure_graph(chains=True)
+    result = db.query(query, 1, 0, parent_boosts=True)[0]
+    assert result.key == "UN-LOCODE-gb:svn"
+    assert result.parent_boost[0] == "ISO-3166-1-gb"
+    assert result.get_score() == [loc.get_score() for loc in unboosted if loc.key == result.key][0] + result.parent_boost[1]
+
+    db.configure_graph(state_share=0.0, subdiv_share=0.0)
+    assert [loc.key for loc in db.query(query, 3, 0)] == [loc.key for loc in unboosted]
+    db.configure_graph(enabled=False, state_share=0.5, subdiv_share=1 / 3)
+    assert db.query(query, 1, 0)[0].key == "ISO-3166-1-gb"
+    with pytest.raises(ValueError):
+        db.configure_graph(locode_share=2.0)
+
+def test_result_cache(db):
+    assert db.cache_info() is None
+    expected = [(loc.key, loc.get_score()) for loc in db.query("Abercarn", 5, 2)]
+    db.set_cache(2)
+    for _ in range(3):
+        assert [(loc.key, loc.get_score()) for loc in db.query("Abercarn", 5, 2)] == expected
+    assert db.cache_info() == {"hits": 2, "misses": 1, "size": 1, "capacity": 2, "ttl": None}
+
+    db.query("Abercarn", 1, 2)
+    db.query("Abercarn", 5, 2, state="gb")
+    assert db.cache_info()["size"] == 2
+    db.importance_weight = 0.0
+    db.query("Abercarn", 5, 2)
+    assert db.cache_info()["size"] == 1
+
+    db.set_cache(2, ttl=0.5)
+    assert db.cache_info()["ttl"] == 0.5
+    with pytest.raises(ValueError):
+        db.set_cache(2, ttl=0)
+    db.set_cache(0)
+    assert db.cache_info() is None
+
+def test_subdivisions_and_locodes_of(db):
+    assert [loc.key for loc in db.subdivisions_of("GB")] == [
+        "ISO-3166-2-gb:abc", "ISO-3166-2-gb:abd", "ISO-3166-2-gb:cay", "ISO-3166-2-gb:wsx",
+    ]
+    assert [loc.key for loc in db.locodes_of("gb")] == [
+        "UN-LOCODE-gb:abc", "UN-LOCODE-gb:bsi", "UN-LOCODE-gb:svn",
+    ]
+    assert [loc.key for loc in db.locodes_of("gb", "ABD")] == ["UN-LOCODE-gb:svn"]
+    assert len(db.locodes_of("bg")) == 4
+    with pytest.raises(KeyError):
+        db.subdivisions_of("zz")
+
+def test_resolve_country(test_codes, test_code_list):
+    test_codes["GB"]["d"]["numeric"] = "826"
+    test_codes["BG"]["d"]["numeric"] = "100"
+    db = load_from_json([[json.dumps(test_codes)]], test_code_list)
+    for code in ("gb", "GB", "GBR", "826", " gbr "):
+        assert db.resolve_country(code).key == "ISO-3166-1-gb"
+    assert db.get_state_key("BGR") == "ISO-3166-1-bg"
+    assert db.get_state_key("100") == "ISO-3166-1-bg"
+    assert db.retrieve("GBR").key == "ISO-3166-1-gb"
+    with pytest.raises(KeyError):
+        db.resolve_country("XYZ")
+    with pytest.raises(KeyError):
+        db.resolve_country("999")
+
+def test_external_ids(db, tmp_path):
+    assert db.retrieve("ISO-3166-1-gb").external_ids == {}
+    (tmp_path / "external-ids.tsv").write_text(
+        "key\tgeonames\twikidata\n"
+        "ISO-3166-1-gb\t2635167\tQ145\n"
+        "UN-LOCODE-gb:svn\t2636638\t\n"
+    )
+    db.load_external_ids(str(tmp_path / "external-ids.tsv"))
+    assert db.retrieve("ISO-3166-1-gb").external_ids == {"geonames": "2635167", "wikidata": "Q145"}
+    assert db.retrieve("UN-LOCODE-gb:svn").external_ids == {"geonames": "2636638"}
+
+    (tmp_path / "external-ids.tsv").write_text("key\tgeonames\nUN-LOCODE-gb:nowhere\t1\n")
+    with pytest.raises(KeyError):
+        db.load_external_ids(str(tmp_path / "external-ids.tsv"))
+    (tmp_path / "external-ids.tsv").write_text("geonames\n1\n")
+    with pytest.raises(LoadError):
+        db.load_external_ids(str(tmp_path / "external-ids.tsv"))
+
+def test_distance_to(db):
+    stonehaven = db.retrieve("UN-LOCODE-gb:svn")
+    bognor = db.retrieve("UN-LOCODE-gb:bsi")
+    assert stonehaven.distance_to(bognor) == pytest.approx(695, abs=1)
+    assert bognor.distance_to(stonehaven) == stonehaven.distance_to(bognor)
+    assert stonehaven.distance_to(stonehaven) == 0
+    assert stonehaven.distance_to(db.retrieve("ISO-3166-1-gb")) is None
+
+def test_is_within(test_codes, test_code_list):
+    test_codes["GB:NHL"] = {
+        "<c>": "UN-LOCODE", "i": "GB:NHL",
+        "d": {"name": "Newtonhill", "supercode": "GB", "subcode": "NHL", "function_code": "1-------"},
+    }
+    test_code_list.append({
+        "country": "GB", "subcode": "NHL", "name": "Newtonhill", "name_wo_diacritics": "Newtonhill",
+        "subdivision_code": "", "status": "RL", "function": "1-------", "date": "2301",
+        "iata_code": "", "coordinates": "5702N 00209W",
+    })
+    db = load_from_json([[json.dumps(test_codes)]], test_code_list)
+    assert db.is_within("UN-LOCODE-gb:svn", "ISO-3166-2-gb:abd")
+    assert db.is_within("UN-LOCODE-gb:svn", "ISO-3166-1-gb")
+    assert db.is_within("GBSVN", "GBR")
+    assert not db.is_within("UN-LOCODE-gb:svn", "ISO-3166-1-bg")
+    assert not db.is_within("UN-LOCODE-gb:svn", "ISO-3166-2-gb:wsx")
+    assert not db.is_within("ISO-3166-1-gb", "UN-LOCODE-gb:svn")
+    # placed in no subdivision, but nearest Stonehaven
+    assert db.is_within("UN-LOCODE-gb:nhl", "ISO-3166-2-gb:abd")
+    assert not db.is_within("UN-LOCODE-gb:nhl", "ISO-3166-2-gb:wsx")
+    with pytest.raises(KeyError):
+        db.is_within("UN-LOCODE-gb:nowhere", "ISO-3166-1-gb")
+
+def test_query_geojson(db):
+    collection = db.query_geojson("Stonehaven and Aberdeenshire", 3, 2)
+    assert collection["type"] == "FeatureCollection"
+    features = {feature["id"]: feature for feature in collection["features"]}
+    stonehaven = features["UN-LOCODE-gb:svn"]
+    assert stonehaven["type"] == "Feature"
+    lon, lat = stonehaven["geometry"]["coordinates"]
+    assert (lat, lon) == db.retrieve("UN-LOCODE-gb:svn").coordinates
+    properties = stonehaven["properties"]
+    assert properties["name"] == "Stonehaven" and properties["loc_type"] == "locode"
+    assert properties["subdiv"] == "ISO-3166-2-gb:abd"
+    assert properties["offset"] == [0, 10]
+    assert features["ISO-3166-2-gb:abd"]["geometry"] is None
+
+    results = db.query("Stonehaven and Aberdeenshire", 3, 2, dedupe=True)
+    assert len(db.to_geojson(results)["features"]) == len(results)
+    assert json.loads(json.dumps(collection)) == collection
+
+def test_to_dict(db):
+    stonehaven = db.retrieve("UN-LOCODE-gb:svn")
+    brief = stonehaven.to_dict()
+    assert brief["key"] == "UN-LOCODE-gb:svn"
+    assert brief["state"] == "gb" and brief["subdiv"] == "abd"
+    assert "Stonehaven" in [name.title() for name in brief["names"]]
+    assert "data" not in brief
+    full = stonehaven.to_dict(full=True)
+    assert full["data"]["subdivision_code"] == "abd"
+    assert full["data"]["coordinates"] == list(stonehaven.coordinates)
+    assert "coordinates" not in db.retrieve("ISO-3166-1-gb").to_dict(full=True)["data"]
+    assert {key: value for key, value in full.items() if key != "data"} == brief
+    assert set(brief) == set(location_schema()["properties"]) - {"data"}
+
+def test_query_random_input(db):
+    # non-ASCII, combining and CJK text, stop words and punctuation around
+    # known names, in random mixes
+    pieces = [
+        "abercarn", "Bognor Regis", "sofia", "gb", "lon", "in", "the", " ", ", ", "-", "'",
+        "Île", "BOGNOR", "北京市", "の地図", "مصر", "ß", "İ", "ı", "ﬁ", "🙂", "e\u0301", "\u200d", "\t",
+    ]
+    rng = random.Random(1086)
+    for _ in range(300):
+        query = "".join(rng.choice(pieces) for _ in range(rng.randint(0, 10)))
+        for options in ({}, {"exact": True}, {"extract": True}, {"dedupe": True}):
+            for loc in db.query(query, 5, 2, **options):
+                start, end = loc.get_offset()
+                assert 0 <= start <= end <= len(query)
+        db.autocomplete(query)
+
+def test_search_dedupe(test_codes, test_code_list):
+    test_codes["IATA:SVN"] = {
+        "<c>": "IATA", "i": "SVN",
+        "d": {"name": "Stonehaven Airfield", "iata": "SVN", "type": "small_airport",
+              "country": "GB", "region": "GB-ABD", "y": 56.96, "x": -2.2},
+    }
+    db = load_from_json([[json.dumps(test_codes)]], test_code_list)
+    result = db.query("Flights to Stonehaven", 2, 2)
+    assert sorted(loc.key for loc in result) == ["IATA-svn", "UN-LOCODE-gb:svn"]
+
+    result = db.query("Flights to Stonehaven", 2, 2, dedupe=True)
+    assert len(result) == 1
+    assert {result[0].key} | {loc.key for loc in result[0].also} == {"IATA-svn", "UN-LOCODE-gb:svn"}
+    assert db.query("Flights to Stonehaven", 2, 2)[0].also == []
+
+def test_explain(db):
+    explanation = db.explain("Dentists in Abercarn", "UN-LOCODE-gb:abc", 2)
+    result = db.query("Dentists in Abercarn", 1, 2)[0]
+    assert explanation["tier"] == "fuzzy"
+    assert explanation["total"] == result.get_score()
+    assert explanation["total"] == explanation["base"] + explanation["graph_boost"]
+    assert 0 < explanation["coverage"] <= 1
+
+    assert db.explain("Dentists in Abercarn", "UN-LOCODE-gb:svn", 2) is None
+    with pytest.raises(KeyError):
+        db.explain("Dentists in Abercarn", "UN-LOCODE-gb:nowhere", 2)
+
+    schema = score_breakdown_schema()
+    assert set(explanation) == set(schema["required"])
+
+def test_parse_query(db):
+    parsed = parse_query("Hotels in Abercarn and Lodnon", state="gb")
+    assert parsed["normalized"] == "hotels in abercarn and lodnon"
+    assert parsed["state"] == "gb"
+    assert parsed["stop_words"] == ["in", "and"]
+    assert {"term": "abercarn", "offset": [10, 18]} in parsed["exact"]
+    assert "lodnon" in [term["term"] for term in parsed["fuzzy"]]
+    assert "abercarn" not in [term["term"] for term in parsed["fuzzy"]]
+    assert parse_query("Abercarn")["state"] is None
+
+def test_search_cjk_and_arabic(test_codes, test_code_list):
+    for code, name in (("JP", "Japan"), ("CN", "China"), ("EG", "Egypt")):
+        test_codes[code] = {
+            "<c>": "ISO-3166-1", "i": code,
+            "d": {"name": name, "short": name, "alpha2": code, "alpha3": code + "X", "continent": "AS"},
+        }
+    for code, name, lang, native in (
+        ("JP:13", "Tokyo", "ja", "東京都"),
+        ("CN:BJ", "Beijing", "zh", "北京市"),
+        ("EG:C", "Cairo", "ar", "القاهرة"),
+    ):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "ISO-3166-2", "i": code,
+            "d": {
+                "name": name, "supercode": supercode, "subcode": subcode,
+                "level": "province", f"name:{lang}": native,
+            },
+        }
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+
+    for query, name, span in (
+        ("東京のホテル", "tokyo", "東京"),
+        ("北京", "beijing", "北京"),
+        ("hotels in 北京", "beijing", "北京"),
+        ("فنادق في القاهرة", "cairo", "القاهرة"),
+    ):
+        result = db.query(query, 1, 2)
+        assert result[0].get_names()[0] == name
+        start, end = result[0].get_offset()
+        assert query[start:end] == span
+
+    result = db.query("北京市の地図", 1, 2)
+    assert result[0].get_names()[0] == "beijing"
+    assert result[0].matched_language == "zh"
+
+def test_search_cyrillic_and_greek(test_codes, test_code_list):
+    for code, name in (("RU", "Russia"), ("GR", "Greece")):
+        test_codes[code] = {
+            "<c>": "ISO-3166-1", "i": code,
+            "d": {"name": name, "short": name, "alpha2": code, "alpha3": code + "X", "continent": "EU"},
+        }
+    for code, name, extra in (
+        ("RU:MOW", "Moscow", {}),
+        ("RU:SPE", "Saint Petersburg", {"name:ru": "Санкт-Петербург"}),
+        ("GR:I", "Athens", {}),
+    ):
+        supercode, subcode = code.split(":")
+        test_codes[code] = {
+            "<c>": "ISO-3166-2", "i": code,
+            "d": {"name": name, "supercode": supercode, "subcode": subcode, "level": "region", **extra},
+        }
+    db = load_from_json([json.dumps(test_codes)], test_code_list)
+
+    for query, key, span in (
+        ("Москва", "ISO-3166-2-ru:mow", "Москва"),
+        ("отели МОСКВА", "ISO-3166-2-ru:mow", "МОСКВА"),
+        ("Ξενοδοχεία Αθήνα", "ISO-3166-2-gr:i", "Αθήνα"),
+        ("Санкт-Петербург", "ISO-3166-2-ru:spe", "Санкт-Петербург"),
+    ):
+        result = db.query(query, 1, 2)
+        assert result[0].key == key
+        assert query[slice(*result[0].get_offset())] == span
+
+    # names in the data are found by their romanization too
+    result = db.query("hotels in Sankt-Peterburg", 1, 2)
+    assert result[0].key == "ISO-3166-2-ru:spe"
+    assert result[0].matched_language == "ru"
+
+    # only whole words are replaced
+    assert all(loc.key != "ISO-3166-2-gr:i" for loc in db.query("Καλαθήνα", 1, 0))
+
+    assert db.add_transliteration("Аберкарн", "Abercarn") == 1
+    result = db.query("Аберкарн", 1, 0)
+    assert result[0].key == "UN-LOCODE-gb:abc"
+    assert result[0].matched_language == "ru"
+    assert db.add_transliteration("Нигде", "Nowhere") == 0
+
+def test_load_data_dir(test_codes, tmp_path):
+    (tmp_path / "state.json").write_text(json.dumps(test_codes))
+    for file in ("subdivision.json", "locode.json", "iata.json", "ISO-3166-2:GB.json"):
+        (tmp_path / file).write_text("{}")
+    with pytest.raises(FileNotFoundError):
+        load(str(tmp_path))
+
+    code_list = (Path(__file__).parent / "data" / "test-code-list.csv").read_text()
+    (tmp_path / "code-list_csv.csv").write_text(code_list)
+    (tmp_path / "aliases.json").write_text(json.dumps({"UN-LOCODE-gb:bsi": ["Bognor"]}))
+    (tmp_path / "transliterations.json").write_text(json.dumps({"Аберкарн": "Abercarn"}))
+    (tmp_path / "tags.json").write_text(json.dumps({"UN-LOCODE-gb:bsi": ["coastal"]}))
+    (tmp_path / "rewrites.json").write_text(json.dumps({"states": {"wales": "bg"}}))
+    db = load(str(tmp_path))
+    assert db.retrieve("ISO-3166-1-bg").get_alt_names()["fr"] == ["Bulgarie"]
+    assert db.retrieve("UN-LOCODE-gb:bsi").tags == ["coastal"]
+    assert db.query("Abercarn Wales", 1, 0) == []
+    assert [loc.key for loc in db.query("Holidays in Bognor", 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    assert db.query("Аберкарн", 1, 0)[0].matched_language == "ru"
+
+    reports = []
+    load(str(tmp_path), on_progress=lambda *report: reports.append(report))
+    assert reports == [("files", n, 6) for n in range(1, 7)] + [("build", 0, 1), ("build", 1, 1)]
+
+    (tmp_path / "aliases.json").write_text(json.dumps({"UN-LOCODE-gb:xxx": ["Nowhere"]}))
+    with pytest.raises(KeyError):
+        load(str(tmp_path))
+
+def test_load_data_files(test_codes, tmp_path):
+    (tmp_path / "state.json").write_text(json.dumps(test_codes))
+    (tmp_path / "locode.json").write_text("{}")
+    code_list = (Path(__file__).parent / "data" / "test-code-list.csv").read_text()
+    (tmp_path / "code-list_csv.csv").write_text(code_list)
+    with pytest.raises(FileNotFoundError) as excinfo:
+        load(str(tmp_path))
+    assert "subdivision.json" in str(excinfo.value) and "iata.json" in str(excinfo.value)
+
+    # regional files are optional
+    for file in ("subdivision.json", "iata.json"):
+        (tmp_path / file).write_text("{}")
+    assert load(str(tmp_path)).retrieve("UN-LOCODE-gb:bsi").get_names() == ["bognor regis"]
+
+    db = load(str(tmp_path), files=["state.json", ("json", "locode.json"), ("code-list", "code-list_csv.csv")])
+    assert db.retrieve("UN-LOCODE-gb:bsi").get_names() == ["bognor regis"]
+    with pytest.raises(ValueError):
+        load(str(tmp_path), files=[("parquet", "state.parquet")])
+
+    (tmp_path / "data-files.json").write_text(json.dumps(["state.json", "locode.json", "code-list_csv.csv"]))
+    assert load(str(tmp_path)).retrieve("UN-LOCODE-gb:bsi").get_names() == ["bognor regis"]
+    (tmp_path / "data-files.json").write_text(json.dumps(["state.json", "ISO-3166-2:FR.json"]))
+    with pytest.raises(FileNotFoundError):
+        load(str(tmp_path))
+
+    (tmp_path / "data-files.json").unlink()
+    (tmp_path / "iata.json").write_text("{")
+    (tmp_path / "subdivision.json").write_text("[")
+    with pytest.raises(TypeError) as excinfo:
+        load(str(tmp_path))
+    assert "subdivision.json" in str(excinfo.value) and "iata.json" in str(excinfo.value)
+
+def test_dataset_version(test_codes, tmp_path, caplog):
+    (tmp_path / "state.json").write_text(json.dumps(test_codes))
+    code_list = (Path(__file__).parent / "data" / "test-code-list.csv").read_text()
+    (tmp_path / "code-list_csv.csv").write_text(code_list)
+    files = ["state.json", "code-list_csv.csv"]
+    assert load(str(tmp_path), files=files).version is None
+
+    (tmp_path / "version.json").write_text(json.dumps({"version": "2024-2", "date": "2024-12-18"}))
+    with caplog.at_level(logging.WARNING, logger="berlin"):
+        db = load(str(tmp_path), files=files)
+    assert db.version == {"version": "2024-2", "date": "2024-12-18"}
+    assert not caplog.records
+
+    other = tmp_path / "other"
+    other.mkdir()
+    (other / "locode.json").write_text("{}")
+    (other / "version.json").write_text(json.dumps({"version": "2023-1"}))
+    with caplog.at_level(logging.WARNING, logger="berlin"):
+        db = load(str(tmp_path), files=files + [str(other / "locode.json")])
+    assert db.version["version"] == "2024-2"
+    assert "2023-1" in caplog.records[0].getMessage()
+
+    (other / "version.json").write_text(json.dumps({"date": "2023-06-01"}))
+    with pytest.raises(TypeError):
+        load(str(tmp_path), files=files + [str(other / "locode.json")])
+
+def test_index_on_disk(db, tmp_path):
+    queries = ["Abercarn", "Hotels in Bognor Regis", "Bogner", "gb", "Sofia, BG"]
+    expected = [[(loc.key, loc.get_score()) for loc in db.query(query, 5, 2)] for query in queries]
+    completions = db.autocomplete("ab")
+    stats = db.index_stats()
+    assert db.capabilities()["fst"]["on_disk"] is False
+
+    db.move_index_to_disk(str(tmp_path / "index"))
+    assert db.capabilities()["fst"]["on_disk"] is True
+    assert len(list((tmp_path / "index").iterdir())) == 2
+    assert [[(loc.key, loc.get_score()) for loc in db.query(query, 5, 2)] for query in queries] == expected
+    assert db.autocomplete("ab") == completions
+    assert db.index_stats() == stats
+
+    # changes rewrite the files
+    db.add_location("MY-STANDARD", "GB:WLV", {
+        "name": "Wolverton Works",
+        "supercode": "GB",
+        "subcode": "WLV",
+        "subdivision_code": "ABD",
+    })
+    assert len(list((tmp_path / "index").iterdir())) == 2
+    result = db.query("Deliveries to Wolverton Works", 1, 2, state="GB")
+    assert [loc.key for loc in result] == ["MY-STANDARD-gb:wlv"]
+
+def test_load_low_memory(test_codes, tmp_path):
+    (tmp_path / "data").mkdir()
+    (tmp_path / "data" / "state.json").write_text(json.dumps(test_codes))
+    for file in ("subdivision.json", "locode.json", "iata.json"):
+        (tmp_path / "data" / file).write_text("{}")
+    code_list = (Path(__file__).parent / "data" / "test-code-list.csv").read_text()
+    (tmp_path / "data" / "code-list_csv.csv").write_text(code_list)
+    db = load(str(tmp_path / "data"), low_memory=True, index_dir=str(tmp_path / "index"))
+    assert db.capabilities()["fst"]["on_disk"] is True
+    assert [loc.key for loc in db.query("Holidays in Bognor Regis", 1, 2)] == ["UN-LOCODE-gb:bsi"]
+    del db
+    assert list((tmp_path / "index").iterdir()) == []
+
+def test_load_progress(test_codes, test_code_list):
+    reports = []
+    blocks = [{key: entry} for key, entry in test_codes.items()]
+    load_from_json(blocks, test_code_list, on_progress=lambda *report: reports.append(report))
+    assert reports[:2] == [("blocks", 1, len(blocks)), ("blocks", 2, len(blocks))]
+    assert reports[-2:] == [("build", 0, 1), ("build", 1, 1)]
+
+    reports = []
+    load_from_json(iter(blocks), test_code_list, on_progress=lambda *report: reports.append(report))
+    assert reports[0] == ("blocks", 1, None)
+
+    def cancel(stage, done, total):
+        if done == 2:
+            raise KeyboardInterrupt
+    with pytest.raises(KeyboardInterrupt):
+        load_from_json(blocks, test_code_list, on_progress=cancel)
+
+def test_queued_updates(db):
+    assert db.update_stats() == {"queue_depth": 0, "applied": 0, "failed": 0, "last_applied": None}
+
+    db.queue_location("MY-STANDARD", "BG:SOF", {"name": "Sofia", "supercode": "BG", "subcode": "SOF"})
+    db.queue_alias("MY-STANDARD-bg:sof", "Serdica")
+    db.queue_removal("ISO-3166-2-gb:abd")
+    db.flush_updates()
+
+    stats = db.update_stats()
+    assert stats["queue_depth"] == 0
+    assert stats["applied"] == 3
+    assert stats["last_applied"] is not None
+    assert [loc.key for loc in db.query("Hotels in Serdica", 1, 2)] == ["MY-STANDARD-bg:sof"]
+    with pytest.raises(KeyError):
+        db.retrieve("ISO-3166-2-gb:abd")
+    with pytest.raises(KeyError):
+        db.retrieve("UN-LOCODE-gb:svn")
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+    with pytest.raises(TypeError):
+        db.queue_location("MY-STANDARD", "BG:XXX", {"supercode": "BG"})
+
+def test_removed_location_results(db):
+    loc = db.retrieve("UN-LOCODE-gb:svn")
+    db.queue_removal("UN-LOCODE-gb:svn")
+    db.flush_updates()
+
+    assert loc.key == "UN-LOCODE-gb:svn"
+    with pytest.raises(KeyError):
+        loc.get_names()
+    with pytest.raises(KeyError):
+        loc.to_dict()
+
+def test_removal_clears_aliases(db):
+    db.add_alias("UN-LOCODE-gb:bsi", "Big Smoke Town")
+    db.queue_removal("UN-LOCODE-gb:bsi")
+    db.flush_updates()
+
+    assert db.query("big smoke town", 3, 0, exact=True) == []
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+def test_autocomplete(db):
+    assert db.autocomplete("abe") == [
+        ("ISO-3166-2-gb:abd", "Aberdeenshire"),
+        ("UN-LOCODE-gb:abc", "Abercarn"),
+    ]
+    assert db.autocomplete("abe", limit=1) == [("ISO-3166-2-gb:abd", "Aberdeenshire")]
+    assert db.autocomplete("abe", state="bg") == []
+    assert db.autocomplete("Stoneh") == [("UN-LOCODE-gb:svn", "Stonehaven")]
+    assert db.autocomplete(" ") == []
+
+def test_to_text(db):
+    loc = db.retrieve("UN-LOCODE-gb:svn")
+    state = loc.state.to_text("{name}")
+    assert loc.to_text() == f"Stonehaven, Aberdeenshire, {state} — UN-LOCODE GB SVN"
+    assert loc.to_text("{name}[ ({subdiv_code})]") == "Stonehaven (ABD)"
+    assert db.retrieve("ISO-3166-1-gb").to_text("{name}[, {state}]") == state
+    assert db.to_texts(["UN-LOCODE-gb:svn", "ISO-3166-2-gb:abd"], "{key}: {name}") == [
+        "UN-LOCODE-gb:svn: Stonehaven",
+        "ISO-3166-2-gb:abd: Aberdeenshire",
+    ]
+
+    for template in ("{nome}", "{name", "[{name}", "{name}]"):
+        with pytest.raises(ValueError):
+            loc.to_text(template)
+    with pytest.raises(KeyError):
+        db.to_texts(["UN-LOCODE-gb:xxx"])
+
+def test_index_stats(db):
+    stats = db.index_stats(top=3)
+    assert stats["words"] > 0
+    assert stats["average_postings"] >= 1
+    counts = [count for _, count in stats["most_ambiguous"]]
+    assert len(counts) == 3
+    assert counts == sorted(counts, reverse=True)
+    assert counts[0] >= stats["average_postings"]
+
+def test_typed_fields(db):
+    state = db.retrieve("ISO-3166-1-gb")
+    assert state.loc_type == "state"
+    assert state.alpha3 == "gbr"
+    assert state.continent is not None
+    assert state.level is None
+
+    subdiv = db.retrieve("ISO-3166-2-gb:abd")
+    assert subdiv.loc_type == "subdivision"
+    assert subdiv.level is not None
+    assert subdiv.continent is None
+
+    loc = db.retrieve("UN-LOCODE-gb:svn")
+    assert loc.loc_type == "locode"
+    assert loc.function_code == "123-----"
+    assert loc.status == "AA"
+    assert loc.date == "0701"
+    assert loc.city is None
+    assert loc.elevation is None
+    assert state.status is None
+
+def test_slow_query_warning(db, caplog):
+    assert db.slow_query_ms is None
+    with caplog.at_level(logging.WARNING, logger="berlin"):
+        db.query("Dentists in Abercarn", 1, 2)
+    assert not caplog.records
+
+    db.slow_query_ms = 0
+    with caplog.at_level(logging.WARNING, logger="berlin"):
+        db.query("Dentists in Abercarn", 1, 2)
+    [record] = caplog.records
+    assert "Dentists in Abercarn" in record.getMessage()
+    assert record.query == "Dentists in Abercarn"
+    assert set(record.timings) == {"lock", "search", "grouping", "results"}
+
+def test_location_identity(db):
+    loc = db.retrieve("UN-LOCODE-gb:svn")
+    [found] = db.query("Dentists in Stonehaven", 1, 2)
+    assert found == loc
+    assert found != db.retrieve("UN-LOCODE-gb:abc")
+    assert loc != "UN-LOCODE-gb:svn"
+    assert len({found, loc, db.retrieve("UN-LOCODE-gb:svn")}) == 1
+    assert {loc: 1}[found] == 1
+
+    assert repr(loc) == '<Location UN-LOCODE-gb:svn "Stonehaven">'
+    assert repr(found).startswith('<Location UN-LOCODE-gb:svn "Stonehaven" score=')
+    assert str(loc) == loc.to_text()
+
+def test_query_group_by(db):
+    query = "Abercarn, Stonehaven or Lozarevo"
+    by_state = db.query(query, 5, 2, group_by="state")
+    assert list(by_state) == ["ISO-3166-1-gb", "ISO-3166-1-bg"]
+    assert [loc.key for loc in by_state["ISO-3166-1-gb"]] == [
+        "UN-LOCODE-gb:svn",
+        "UN-LOCODE-gb:abc",
+    ]
+    by_subdiv = db.query("Aberdeenshire and Stonehaven", 5, 2, group_by="subdiv")
+    assert [loc.key for loc in by_subdiv["ISO-3166-2-gb:abd"]] == [
+        "UN-LOCODE-gb:svn",
+        "ISO-3166-2-gb:abd",
+    ]
+
+    with pytest.raises(ValueError):
+        db.query(query, 5, 2, group_by="continent")
+
+def test_stubs_up_to_date(db):
+    root = Path(__file__).parent.parent
+    spec = importlib.util.spec_from_file_location("generate_stubs", root / "scripts" / "generate_stubs.py")
+    generate_stubs = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(generate_stubs)
+    stubs = (root / "python" / "berlin" / "_berlin.pyi").read_text()
+    assert stubs == generate_stubs.render_stubs((root / "src" / "lib.rs").read_text())
+
+    tree = ast.parse(stubs)
+    classes = {node.name: node for node in tree.body if isinstance(node, ast.ClassDef)}
+    for cls, obj in (("LocationsDbProxy", db), ("Location", db.retrieve("UN-LOCODE-gb:svn"))):
+        stubbed = {
+            getattr(node, "name", None) or getattr(getattr(node, "target", None), "id", None)
+            for node in classes[cls].body
+        }
+        public = {name for name in dir(type(obj)) if not name.startswith("_")}
+        assert public <= stubbed
+
+def test_capabilities(db):
+    capabilities = db.capabilities()
+    assert set(capabilities) == {"fst", "exact", "phonetic", "infix"}
+    for name in ("fst", "exact", "phonetic"):
+        assert capabilities[name]["present"]
+        assert capabilities[name]["entries"] > 0
+        assert capabilities[name]["bytes"] > 0
+        assert capabilities[name]["build_ms"] >= 0
+    assert capabilities["infix"] == {"present": False, "entries": 0, "bytes": 0, "build_ms": None, "on_disk": False}
+
+    db.query("Dentists in Abercarn", 1, 2, infix=True)
+    infix = db.capabilities()["infix"]
+    assert infix["present"] and infix["entries"] > 0 and infix["build_ms"] >= 0
+
+def test_load_from_csv(tmp_path):
+    (tmp_path / "states.csv").write_text(
+        "alpha2,alpha3,name,continent,name:fr\n"
+        "BG,BGR,Bulgaria,EU,Bulgarie\n"
+        "GB,GBR,United Kingdom,EU,Royaume-Uni\n"
+    )
+    (tmp_path / "subdivisions.csv").write_text(
+        "supercode,subcode,name,level\n"
+        "BG,01,Blagoevgrad,region\n"
+        "BG,02,Burgas,region\n"
+        "GB,ABD,Aberdeenshire,council area\n"
+        "GB,CAY,Caerphilly,unitary authority\n"
+        "GB,WSX,West Sussex,two-tier county\n"
+    )
+    (tmp_path / "airports.csv").write_text(
+        "iata,name,type,city,country,region,x,y,elevation\n"
+        "ABZ,Aberdeen Dyce Airport,large_airport,Aberdeen,GB,GB-SCT,-2.19,57.2,215\n"
+    )
+    code_list = Path(__file__).parent / "data" / "test-code-list.csv"
+    db = load_from_csv({
+        "state": str(tmp_path / "states.csv"),
+        "subdivision": str(tmp_path / "subdivisions.csv"),
+        "locode": str(code_list),
+        "iata": str(tmp_path / "airports.csv"),
+    })
+    assert db.retrieve("ISO-3166-1-bg").get_alt_names()["fr"] == ["Bulgarie"]
+    loc = db.retrieve("UN-LOCODE-gb:svn")
+    assert loc.subdiv.key == "ISO-3166-2-gb:abd"
+    assert loc.status == "AA"
+    assert loc.coordinates is not None
+    assert db.retrieve("IATA-abz").elevation == 215
+    assert [loc.key for loc in db.query("Dentists in Abercarn", 1, 2)] == ["UN-LOCODE-gb:abc"]
+
+    with pytest.raises(ValueError):
+        load_from_csv({"country": str(tmp_path / "states.csv")})
+    (tmp_path / "airports.csv").write_text("iata,name,x,y\nABZ,Aberdeen,west,57.2\n")
+    with pytest.raises(TypeError):
+        load_from_csv({"iata": str(tmp_path / "airports.csv")})
+
+def test_load_nuts(region_files, tmp_path):
+    (tmp_path / "nuts.csv").write_text(
+        "code,name,subdivision\n"
+        "UK,United Kingdom,\n"
+        "UKM,Scotland,\n"
+        "UKM5,North Eastern Scotland,\n"
+        "UKM50,Aberdeen and North East Moray,GB-ABD\n"
+    )
+    db = load_from_csv({**region_files, "nuts": str(tmp_path / "nuts.csv")})
+    loc = db.retrieve("NUTS-ukm50")
+    assert loc.loc_type == "nuts" and loc.nuts_level == 3
+    assert loc.state.key == "ISO-3166-1-gb"
+    assert loc.subdiv.key == "ISO-3166-2-gb:abd"
+    assert db.retrieve("NUTS-ukm").nuts_level == 1
+    assert db.retrieve("ISO-3166-1-gb").nuts_level is None
+    with pytest.raises(KeyError):
+        db.retrieve("NUTS-uk")
+    assert [loc.key for loc in db.query("North Eastern Scotland", 1, 2)] == ["NUTS-ukm5"]
+
+    (tmp_path / "nuts.csv").write_text("code,name\nUKM5000,Somewhere\n")
+    with pytest.raises(LoadError) as excinfo:
+        load_from_csv({"nuts": str(tmp_path / "nuts.csv")})
+    assert excinfo.value.errors[0]["field"] == "code"
+
+def test_load_fips(region_files, tmp_path):
+    (tmp_path / "fips.csv").write_text(
+        "code,name,subdivision\n"
+        "06,California,US-CA\n"
+        "6037,Los Angeles County,US-CA\n"
+    )
+    db = load_from_csv({**region_files, "fips": str(tmp_path / "fips.csv")})
+    loc = db.retrieve("FIPS-06037")
+    assert loc.loc_type == "fips" and loc.get_codes() == ["06037"]
+    assert loc.subdiv.key == "ISO-3166-2-us:ca"
+    assert [loc.key for loc in db.query("county 06037", 1, 2)] == ["FIPS-06037"]
+
+    (tmp_path / "fips.csv").write_text("code,name\nCA,California\n")
+    with pytest.raises(LoadError):
+        load_from_csv({"fips": str(tmp_path / "fips.csv")})
+
+def test_load_postal(region_files, tmp_path):
+    (tmp_path / "postal.csv").write_text(
+        "country,code,name,subdivision,lat,lon\n"
+        "GB,EC1A,,GB-LND,51.518,-0.099\n"
+        "US,90210,Beverly Hills,US-CA,34.09,-118.41\n"
+    )
+    db = load_from_csv({**region_files, "postal": str(tmp_path / "postal.csv")})
+    loc = db.retrieve("POSTAL-gb:ec1a")
+    assert loc.loc_type == "postal" and loc.get_codes() == ["ec1a"]
+    assert loc.subdiv.key == "ISO-3166-2-gb:lnd"
+    lat, lon = loc.coordinates
+    assert abs(lat - 51.518) < 0.01 and abs(lon + 0.099) < 0.01
+    assert [loc.key for loc in db.query("offices in EC1A 1BB", 1, 2)] == ["POSTAL-gb:ec1a"]
+    assert [loc.key for loc in db.query("90210", 1, 2)] == ["POSTAL-us:90210"]
+
+    (tmp_path / "postal.csv").write_text("country,code,lat\nGB,EC1A,51.5\n")
+    with pytest.raises(LoadError) as excinfo:
+        load_from_csv({"postal": str(tmp_path / "postal.csv")})
+    assert excinfo.value.errors[0]["field"] == "lon"
+
+def test_fetch_data(test_codes, tmp_path, monkeypatch):
+    source = tmp_path / "source"
+    for version in ("2024-1", "latest"):
+        files = {"state.json": json.dumps(test_codes), "code-list_csv.csv": "Country,Location\n"}
+        (source / version).mkdir(parents=True)
+        for name, contents in files.items():
+            (source / version / name).write_text(contents)
+        checksums = {name: hashlib.sha256(contents.encode()).hexdigest() for name, contents in files.items()}
+        manifest = {"version": "2024-1", "files": checksums}
+        (source / version / "manifest.json").write_text(json.dumps(manifest))
+    monkeypatch.setenv("BERLIN_CACHE_DIR", str(tmp_path / "cache"))
+
+    dest = fetch_data(tmp_path / "data", url=source.as_uri())
+    assert json.loads((dest / "state.json").read_text()) == test_codes
+    assert (tmp_path / "cache" / "2024-1" / "state.json").exists()
+
+    # cached files are not downloaded again
+    (source / "2024-1" / "state.json").write_text("changed")
+    fetch_data(tmp_path / "other", version="2024-1", url=source.as_uri())
+    assert json.loads((tmp_path / "other" / "state.json").read_text()) == test_codes
+
+    (tmp_path / "cache" / "2024-1" / "state.json").unlink()
+    with pytest.raises(ValueError):
+        fetch_data(tmp_path / "other", version="2024-1", url=source.as_uri())
+    assert not (tmp_path / "cache" / "2024-1" / "state.json").exists()
+    # nor is the partial download left behind, nor one that fails
+    assert sorted(path.name for path in (tmp_path / "cache" / "2024-1").iterdir()) == ["code-list_csv.csv"]
+    def interrupted(response, f, length):
+        f.write(b"{")
+        raise OSError("connection reset")
+    with monkeypatch.context() as patch:
+        patch.setattr("berlin.data.shutil.copyfileobj", interrupted)
+        with pytest.raises(OSError):
+            fetch_data(tmp_path / "other", version="2024-1", url=source.as_uri())
+    assert sorted(path.name for path in (tmp_path / "cache" / "2024-1").iterdir()) == ["code-list_csv.csv"]
+
+    for version, name in (("../outside", "state.json"), ("2024-1", "../state.json"), ("2024-1", "/tmp/state.json")):
+        manifest = {"version": version, "files": {name: "0" * 64}}
+        (source / "latest" / "manifest.json").write_text(json.dumps(manifest))
+        with pytest.raises(ValueError):
+            fetch_data(tmp_path / "unsafe", url=source.as_uri())
+    assert not (tmp_path / "outside").exists()
+    assert not (tmp_path / "unsafe").exists()
+
+    monkeypatch.delenv("BERLIN_DATA_URL", raising=False)
+    with pytest.raises(ValueError):
+        fetch_data(tmp_path / "data")
+
+def test_detect_language(db):
+    for query, language in (
+        ("Dentists in Abercarn", "en"),
+        ("Zahnärzte in der Nähe von Stonehaven", "de"),
+        ("Hôtels près de Stonehaven", "fr"),
+        ("Hoteles cerca de Stonehaven", "es"),
+        ("東京の地図", "ja"),
+        ("北京", "zh"),
+        ("서울", "ko"),
+        ("القاهرة", "ar"),
+        ("Отели в Ловеч", "ru"),
+        ("Готелі біля Києва", "uk"),
+        ("Stonehaven", None),
+    ):
+        assert db.detect_language(query) == language
+
+    # "dans" is a French stop word, rather than a misspelling of "da3"
+    query = "Hotels dans le Aberdeenshire"
+    assert "UN-LOCODE-bg:da3" in [loc.key for loc in db.query(query, 5, 2)]
+    results = db.query(query, 5, 2, detect_language=True)
+    assert [loc.key for loc in results] == ["ISO-3166-2-gb:abd"]
+    assert results[0].query_language == "fr"
+    assert db.query(query, 5, 2)[0].query_language is None